@@ -1,4 +1,6 @@
-use miette::{IntoDiagnostic, Result, Context};
+use std::path::PathBuf;
+
+use miette::{Context, IntoDiagnostic, Result};
 use once_cell::sync::Lazy;
 use rust_i18n::t;
 use serde::{Deserialize, Serialize};
@@ -8,15 +10,102 @@ use crate::env::get_app_config_file;
 
 #[derive(Serialize, Deserialize)]
 pub struct DMConfiguration {
-    pub using_profile: String,
+    /// Profile chosen by `dm profile use`, selected by rules of profiles if not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub using_profile: Option<String>,
     pub locale: String,
+    #[serde(default)]
+    pub encrypt: DMEncryptConfiguration,
+    #[serde(default)]
+    pub backup: DMBackupConfiguration,
+    #[serde(default)]
+    pub history: DMHistoryConfiguration,
+    #[serde(default)]
+    pub merge: DMMergeConfiguration,
+    /// WebDAV collection which the depository is mirrored to by `dm mirror`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub webdav: Option<DMWebDavConfiguration>,
+}
+
+/// Keys used by encrypted entries
+#[derive(Serialize, Deserialize, Default)]
+pub struct DMEncryptConfiguration {
+    /// Passphrase to encrypt entries
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub passphrase: Option<String>,
+    /// Path to an age identity file, used instead of passphrase to encrypt entries
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub identity: Option<PathBuf>,
+}
+
+/// Retention policy of the backup store, applied whenever a backup is created
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct DMBackupConfiguration {
+    /// Count of backups kept for each path, 0 to keep all of them
+    pub keep: usize,
+    /// Backups older than this many days are pruned
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_age_days: Option<u64>,
+}
+
+impl Default for DMBackupConfiguration {
+    fn default() -> Self {
+        Self {
+            keep: 5,
+            max_age_days: None,
+        }
+    }
+}
+
+/// Retention policy of transaction history, applied whenever a transaction is recorded
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct DMHistoryConfiguration {
+    /// Count of the latest transactions kept, 0 to keep all of them
+    pub keep: usize,
+}
+
+impl Default for DMHistoryConfiguration {
+    fn default() -> Self {
+        Self { keep: 50 }
+    }
+}
+
+/// How to merge a file modified both locally and in depository
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct DMMergeConfiguration {
+    /// Command line of external merge tool, run by the default shell
+    ///
+    /// Paths of the versions are given in environment variables `DM_BASE`,
+    /// `DM_LOCAL`, `DM_DEPOSITORY`, and the result is read from `DM_MERGED`,
+    /// e.g. `vimdiff "$DM_LOCAL" "$DM_MERGED" "$DM_DEPOSITORY"`.
+    /// Built-in line-based merge is used if it is not set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool: Option<String>,
+}
+
+/// Location and credentials of a WebDAV collection
+#[derive(Serialize, Deserialize, Clone)]
+pub struct DMWebDavConfiguration {
+    /// URL of the collection, e.g. `https://dav.example.com/dm/`
+    pub url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
 }
 
 impl Default for DMConfiguration {
     fn default() -> Self {
         Self {
-            using_profile: String::from("default"),
+            using_profile: None,
             locale: String::from("en"),
+            encrypt: DMEncryptConfiguration::default(),
+            backup: DMBackupConfiguration::default(),
+            history: DMHistoryConfiguration::default(),
+            merge: DMMergeConfiguration::default(),
+            webdav: None,
         }
     }
 }
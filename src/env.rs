@@ -17,6 +17,7 @@ fn env_option_to_result<T>(result: Option<T>) -> Result<T> {
         .into_diagnostic()
 }
 
+#[allow(clippy::redundant_closure)]
 pub fn get_app_data_dir() -> Result<PathBuf> {
     let path = env::var("DM_DATA")
         .ok()
@@ -30,6 +31,7 @@ pub fn get_app_data_dir() -> Result<PathBuf> {
     env_option_to_result(path)
 }
 
+#[allow(clippy::redundant_closure)]
 pub fn get_app_config_file() -> Result<PathBuf> {
     let path = env::var("DM_CONFIG_FILE")
         .ok()
@@ -38,8 +40,8 @@ pub fn get_app_config_file() -> Result<PathBuf> {
     env_option_to_result(path)
 }
 
-pub fn get_group_dir(group_name: &str) -> Result<PathBuf> {
-    Ok(get_app_data_dir()?.join(format!("{}/{}", "depository", group_name)))
+pub fn get_hostname() -> String {
+    gethostname::gethostname().to_string_lossy().to_string()
 }
 
 pub struct SpecDir {
@@ -73,6 +75,12 @@ impl SpecDir {
             }
         }
     }
+    /// Platform-specific standard locations, keyed by name without `#`
+    pub fn platform_dirs(&self) -> impl Iterator<Item = (&'static str, &Path)> {
+        self.platform
+            .iter()
+            .map(|(name, path)| (*name, path.as_path()))
+    }
     pub fn match_path<P: AsRef<Path>>(&self, path: P) -> Result<Vec<(String, &PathBuf)>> {
         let path = dunce::canonicalize(path).into_diagnostic()?;
         let matches = self
@@ -191,12 +199,13 @@ fn get_platform_spec_dir() -> Result<HashMap<&'static str, PathBuf>> {
     Ok(data.into_iter().collect())
 }
 
+#[allow(clippy::manual_strip)]
 pub fn to_depositiory_path<P: AsRef<Path>>(path: P) -> PathBuf {
     let path = std::fs::canonicalize(path).unwrap();
     let path = path.to_str().unwrap();
     if path.starts_with("/") {
-        // Unix path
-        PathBuf::from("ROOT/").join(path.split_at(0).1)
+        // Unix path, entries recorded as absolute paths are migrated on loading
+        PathBuf::from("ROOT/").join(path.split_at(1).1)
     } else if path.starts_with("\\\\?\\") {
         // MSDOS path
         let filepath = &path[4..];
@@ -1,6 +1,7 @@
 use miette::Diagnostic;
 use thiserror::Error;
 
+#[allow(clippy::enum_variant_names)]
 #[derive(Error, Diagnostic, Debug)]
 pub enum DMError {
     #[error(transparent)]
@@ -31,6 +32,69 @@ pub enum DMError {
         #[help]
         advice: Option<String>,
     },
+    #[error("ScriptError: {msg}")]
+    #[diagnostic()]
+    ScriptError {
+        msg: String,
+        #[help]
+        advice: Option<String>,
+    },
+    #[error("EncryptError: {msg}")]
+    #[diagnostic()]
+    EncryptError {
+        msg: String,
+        #[help]
+        advice: Option<String>,
+    },
+    #[error("TemplateError: {msg}")]
+    #[diagnostic()]
+    TemplateError {
+        msg: String,
+        #[help]
+        advice: Option<String>,
+    },
+    #[error("HistoryError: {msg}")]
+    #[diagnostic()]
+    HistoryError {
+        msg: String,
+        #[help]
+        advice: Option<String>,
+    },
+    #[error("BackupError: {msg}")]
+    #[diagnostic()]
+    BackupError {
+        msg: String,
+        #[help]
+        advice: Option<String>,
+    },
+    #[error("ConflictError: {msg}")]
+    #[diagnostic()]
+    ConflictError {
+        msg: String,
+        #[help]
+        advice: Option<String>,
+    },
+    #[error("GitError: {msg}")]
+    #[diagnostic()]
+    GitError {
+        msg: String,
+        #[help]
+        advice: Option<String>,
+    },
+    #[error("SyncError: {msg}")]
+    #[diagnostic()]
+    SyncError {
+        msg: String,
+        #[help]
+        advice: Option<String>,
+    },
+    #[error("StorageError: {msg}")]
+    #[diagnostic()]
+    StorageError {
+        msg: String,
+        #[help]
+        advice: Option<String>,
+    },
     #[error("EnvError: {msg}")]
     #[diagnostic()]
     EnvError {
@@ -51,10 +115,15 @@ pub enum ProfileErrorKind {
     DuplicateCreate,
     NotExists,
     IlleagalOperation,
+    CyclicInherit,
 }
 
 #[derive(Debug)]
 pub enum GroupErrorKind {
     DuplicateCreate,
     NotExists,
+    InstallFailed,
+    FileNotExists,
+    NoInstallPath,
+    OldLayout,
 }
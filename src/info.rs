@@ -5,12 +5,25 @@ use rust_i18n::t;
 use crate::{
     available_locales,
     env::{self, get_app_config_file, get_app_data_dir},
+    local::profile::{current_profile, ProfileSource},
 };
 
+async fn profile_info() -> Result<String> {
+    let profile = current_profile().await?;
+    Ok(match profile.source {
+        ProfileSource::Config => t!("app.info.profile.config", name = &profile.name),
+        ProfileSource::Rule(rule) => t!(
+            "app.info.profile.rule",
+            name = &profile.name,
+            rule = &rule.to_string()
+        ),
+        ProfileSource::Fallback => t!("app.info.profile.fallback", name = &profile.name),
+    })
+}
 
-pub fn all_info() -> Result<String> {
+pub async fn all_info() -> Result<String> {
     Ok(format!(
-        "{locales_tip}\n{depository_path}\n{config_path}\n{pssl}",
+        "{locales_tip}\n{depository_path}\n{config_path}\n{profile}\n{pssl}",
         locales_tip = t!(
             "app.avaliable_locales",
             locales = &format!("{:?}", available_locales())
@@ -23,6 +36,7 @@ pub fn all_info() -> Result<String> {
             "app.configuration_path",
             path = get_app_config_file().unwrap().to_str().unwrap()
         ),
+        profile = profile_info().await?,
         pssl = t!(
             "app.info.pssl",
             loc = &format!("\n{}", env::SpecDir::new()?.display_tree())
@@ -0,0 +1,252 @@
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use miette::{Context, IntoDiagnostic, Result};
+use rust_i18n::t;
+
+use crate::{
+    config::{self, DMBackupConfiguration},
+    env::get_app_data_dir,
+    error::DMError,
+    ui::{MsgLevel, Ui},
+};
+
+use super::{
+    db::{self, BackupRow},
+    journal::{copy_path, remove_path, Journal},
+    lock::DepositoryLock,
+};
+
+fn get_backup_dir() -> Result<PathBuf> {
+    Ok(get_app_data_dir()?.join("backup"))
+}
+
+fn now() -> Duration {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap()
+}
+
+fn path_size(path: &Path) -> Result<u64> {
+    let metadata = path.symlink_metadata().into_diagnostic()?;
+    if !metadata.is_dir() {
+        return Ok(metadata.len());
+    }
+    let mut size = 0;
+    for item in std::fs::read_dir(path).into_diagnostic()? {
+        size += path_size(&item.into_diagnostic()?.path())?;
+    }
+    Ok(size)
+}
+
+/// Move a file or directory, copy it if they are in different file systems
+fn move_path(src: &Path, dst: &Path) -> Result<()> {
+    if std::fs::rename(src, dst).is_err() {
+        copy_path(src, dst)?;
+        remove_path(src)?;
+    }
+    Ok(())
+}
+
+/// A file or directory in backup store
+pub struct BackupEntry {
+    pub id: i64,
+    /// Where the backup comes from
+    pub origin: PathBuf,
+    /// Location in backup store
+    pub path: PathBuf,
+    pub time: SystemTime,
+    /// Size in bytes, including all files if it is a directory
+    pub size: u64,
+}
+
+impl BackupEntry {
+    fn from_row(row: BackupRow, backup_dir: &Path) -> Self {
+        Self {
+            id: row.id,
+            origin: PathBuf::from(row.origin),
+            path: backup_dir.join(row.stored),
+            time: UNIX_EPOCH + Duration::from_secs(row.time as u64),
+            size: row.size as u64,
+        }
+    }
+}
+
+fn query_entries() -> Result<Vec<BackupEntry>> {
+    let backup_dir = get_backup_dir()?;
+    Ok(db::query_backups()?
+        .into_iter()
+        .map(|row| BackupEntry::from_row(row, &backup_dir))
+        .collect())
+}
+
+/// Absolute path of `path` as origin of backups
+///
+/// Only the parent is resolved, `path` itself may be a link, even a broken one.
+fn resolve_origin(path: &Path) -> Result<PathBuf> {
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    Ok(dunce::canonicalize(parent)
+        .into_diagnostic()?
+        .join(path.file_name().unwrap()))
+}
+
+/// Move `path` into backup store, and prune old backups of it
+///
+/// Backups are saved in `<app data>/backup/<timestamp>/<file name>`.
+pub(super) async fn backup_file(path: &Path) -> Result<()> {
+    let origin = resolve_origin(path)?;
+    let time = now();
+    let backup_dir = get_backup_dir()?;
+    let mut dir_name = time.as_millis().to_string();
+    let mut index = 0;
+    while backup_dir.join(&dir_name).exists() {
+        index += 1;
+        dir_name = format!("{}-{}", time.as_millis(), index);
+    }
+    let stored = Path::new(&dir_name).join(origin.file_name().unwrap());
+    std::fs::create_dir_all(backup_dir.join(&dir_name)).into_diagnostic()?;
+
+    let size = path_size(path)?;
+    move_path(path, &backup_dir.join(&stored))?;
+    db::insert_backup(
+        &origin.to_string_lossy(),
+        &stored.to_string_lossy(),
+        time.as_secs() as i64,
+        size as i64,
+    )?;
+
+    let policy = config::CONFIG.lock().await.backup.clone();
+    prune(&policy, Some(&origin))?;
+    Ok(())
+}
+
+/// Store paths of backups created after backup `id`
+pub(super) fn backups_after(id: i64) -> Result<Vec<PathBuf>> {
+    Ok(query_entries()?
+        .into_iter()
+        .take_while(|entry| entry.id > id)
+        .map(|entry| entry.path)
+        .collect())
+}
+
+fn remove_entry(entry: &BackupEntry) -> Result<()> {
+    // Forgotten before removed, so that a backup in index always exists
+    db::delete_backup(entry.id)?;
+    remove_path(&entry.path)?;
+    if let Some(dir) = entry.path.parent() {
+        // The timestamp directory holds only one backup
+        let _ = std::fs::remove_dir(dir);
+    }
+    Ok(())
+}
+
+/// Remove backups out of `policy`, only backups of `origin` are checked if it is given
+fn prune(policy: &DMBackupConfiguration, origin: Option<&Path>) -> Result<usize> {
+    let max_age = policy
+        .max_age_days
+        .map(|days| Duration::from_secs(days * 24 * 60 * 60));
+    let now = SystemTime::now();
+    let mut kept: Vec<(PathBuf, usize)> = vec![];
+    let mut count = 0;
+    for entry in query_entries()? {
+        if origin.is_some_and(|origin| origin != entry.origin) {
+            continue;
+        }
+        let index = match kept.iter_mut().find(|(origin, _)| *origin == entry.origin) {
+            Some((_, index)) => {
+                *index += 1;
+                *index
+            }
+            None => {
+                kept.push((entry.origin.clone(), 0));
+                0
+            }
+        };
+        let too_many = policy.keep != 0 && index >= policy.keep;
+        let too_old = max_age.is_some_and(|max_age| {
+            now.duration_since(entry.time)
+                .is_ok_and(|age| age > max_age)
+        });
+        if too_many || too_old {
+            remove_entry(&entry)?;
+            count += 1;
+        }
+    }
+    Ok(count)
+}
+
+/// All backups, the latest first, only backups of `origin` are listed if it is given
+pub async fn list_backup(origin: Option<PathBuf>) -> Result<Vec<BackupEntry>> {
+    let origin = origin.map(|origin| resolve_origin(&origin).unwrap_or(origin));
+    Ok(query_entries()?
+        .into_iter()
+        .filter(|entry| origin.as_ref().is_none_or(|origin| *origin == entry.origin))
+        .collect())
+}
+
+/// Stage backup `entry` to be copied to `target`, and move the file at target
+/// into backup store
+async fn stage_restore(journal: &mut Journal, entry: &BackupEntry, target: &Path) -> Result<()> {
+    // Copied before backing up target, which may prune the backup being restored
+    journal.stage_copy(&entry.path, target)?;
+    journal.protect(target)?;
+    if target.symlink_metadata().is_ok() {
+        backup_file(target).await?;
+    }
+    Ok(())
+}
+
+/// Copy backup `id` back to where it comes from, or to `target` if it is given
+///
+/// The file at target is moved into backup store before overwritten.
+pub async fn restore_backup(ui_handle: &dyn Ui, id: i64, target: Option<PathBuf>) -> Result<()> {
+    let _lock = DepositoryLock::acquire()?;
+    Journal::recover().wrap_err(t!("error.ctx.transcation.recover"))?;
+    let entry = match db::query_backup(id)? {
+        Some(row) => BackupEntry::from_row(row, &get_backup_dir()?),
+        None => Err(DMError::BackupError {
+            msg: t!("error.backup.not_exists.msg", id = &id.to_string()),
+            advice: Some(t!("error.backup.not_exists.advice")),
+        })
+        .into_diagnostic()?,
+    };
+    let target = target.unwrap_or(entry.origin.clone());
+    if target.symlink_metadata().is_ok() {
+        let prompt = t!("backup.restore.overwrite", path = &target.to_string_lossy());
+        if !ui_handle.input_yes_or_no(Some(&prompt), true)? {
+            return Ok(());
+        }
+    }
+    let mut journal = Journal::new()?;
+    if let Err(err) = stage_restore(&mut journal, &entry, &target).await {
+        journal.abort()?;
+        return Err(err);
+    }
+    journal.commit()?;
+    ui_handle.msg(
+        MsgLevel::Info,
+        t!(
+            "backup.restore.restored",
+            id = &id.to_string(),
+            path = &target.to_string_lossy()
+        ),
+    );
+    Ok(())
+}
+
+/// Remove backups out of retention policy
+///
+/// `keep` and `max_age_days` override the policy in configuration.
+/// Returns the count of removed backups.
+pub async fn prune_backup(keep: Option<usize>, max_age_days: Option<u64>) -> Result<usize> {
+    let _lock = DepositoryLock::acquire()?;
+    let mut policy = config::CONFIG.lock().await.backup.clone();
+    if let Some(keep) = keep {
+        policy.keep = keep;
+    }
+    if max_age_days.is_some() {
+        policy.max_age_days = max_age_days;
+    }
+    prune(&policy, None)
+}
@@ -0,0 +1,94 @@
+use std::io::{Read, Write};
+use std::path::PathBuf;
+
+use age::{
+    secrecy::SecretString,
+    stream::{StreamReader, StreamWriter},
+    Decryptor, Encryptor, Identity, IdentityFile, Recipient,
+};
+use miette::{Context, IntoDiagnostic, Result};
+use rust_i18n::t;
+
+use crate::{config, env::get_app_config_file, error::DMError};
+
+/// Keys to encrypt and decrypt entries, loaded from configuration
+///
+/// If an age identity file is given, entries are encrypted to its recipients,
+/// otherwise the passphrase is used.
+pub(super) struct Keys {
+    identity: Option<PathBuf>,
+    passphrase: Option<String>,
+}
+
+impl Keys {
+    pub async fn load() -> Result<Self> {
+        let guard = config::CONFIG.lock().await;
+        let config = &guard.encrypt;
+        if config.identity.is_none() && config.passphrase.is_none() {
+            Err(DMError::EncryptError {
+                msg: t!("error.encrypt.no_key.msg"),
+                advice: Some(t!(
+                    "error.encrypt.no_key.advice",
+                    config = &get_app_config_file()?.to_string_lossy()
+                )),
+            })
+            .into_diagnostic()?;
+        }
+        Ok(Self {
+            identity: config.identity.clone(),
+            passphrase: config.passphrase.clone(),
+        })
+    }
+
+    fn identity_file(&self) -> Result<Option<IdentityFile<age::NoCallbacks>>> {
+        match &self.identity {
+            Some(path) => IdentityFile::from_file(path.to_string_lossy().to_string())
+                .map(Some)
+                .into_diagnostic()
+                .wrap_err(t!(
+                    "error.ctx.encrypt.identity",
+                    path = &path.to_string_lossy()
+                )),
+            None => Ok(None),
+        }
+    }
+
+    /// Wrap `output` so that data written into it is encrypted
+    ///
+    /// [`StreamWriter::finish`] must be called after writing
+    pub fn encrypt<W: Write>(&self, output: W) -> Result<StreamWriter<W>> {
+        let encryptor = match self.identity_file()? {
+            Some(identity_file) => {
+                let recipients = identity_file.to_recipients().into_diagnostic()?;
+                Encryptor::with_recipients(
+                    recipients
+                        .iter()
+                        .map(|recipient| recipient.as_ref() as &dyn Recipient),
+                )
+                .into_diagnostic()?
+            }
+            None => Encryptor::with_user_passphrase(SecretString::from(
+                self.passphrase.clone().unwrap(),
+            )),
+        };
+        encryptor.wrap_output(output).into_diagnostic()
+    }
+
+    /// Wrap `input` so that data read from it is decrypted
+    pub fn decrypt<R: Read>(&self, input: R) -> Result<StreamReader<R>> {
+        let decryptor = Decryptor::new(input).into_diagnostic()?;
+        let mut identities: Vec<Box<dyn Identity>> = vec![];
+        if let Some(identity_file) = self.identity_file()? {
+            identities.extend(identity_file.into_identities().into_diagnostic()?);
+        }
+        if let Some(passphrase) = &self.passphrase {
+            identities.push(Box::new(age::scrypt::Identity::new(SecretString::from(
+                passphrase.clone(),
+            ))));
+        }
+        decryptor
+            .decrypt(identities.iter().map(|identity| identity.as_ref()))
+            .into_diagnostic()
+            .wrap_err(t!("error.ctx.encrypt.decrypt"))
+    }
+}
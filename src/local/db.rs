@@ -1,55 +1,293 @@
-use std::{path::Path, sync::Mutex};
+use std::sync::Mutex;
 
 use miette::{IntoDiagnostic, Result};
 use once_cell::sync::Lazy;
-use rusqlite::Connection;
+use rusqlite::{Connection, OptionalExtension, Row};
 
 use crate::env::get_app_data_dir;
 
+/// Statements to upgrade schema, the n-th of them upgrades schema from version n to n + 1.
+/// Schema version is saved in `user_version` of database.
+const MIGRATIONS: &[&str] = &[
+    // Backups may be indexed before schema is versioned
+    "CREATE TABLE IF NOT EXISTS backup (
+        id     INTEGER PRIMARY KEY AUTOINCREMENT,
+        origin TEXT NOT NULL,
+        stored TEXT NOT NULL,
+        time   INTEGER NOT NULL,
+        size   INTEGER NOT NULL
+    );
+    DROP TABLE IF EXISTS sha256;",
+    "CREATE TABLE entry_state (
+        group_name   TEXT NOT NULL,
+        path         TEXT NOT NULL,
+        live_hash    TEXT NOT NULL,
+        live_size    INTEGER NOT NULL,
+        live_mtime   INTEGER NOT NULL,
+        stored_hash  TEXT NOT NULL,
+        stored_size  INTEGER NOT NULL,
+        stored_mtime INTEGER NOT NULL,
+        PRIMARY KEY (group_name, path)
+    );",
+    "CREATE TABLE mirror_state (
+        remote TEXT NOT NULL,
+        path   TEXT NOT NULL,
+        hash   TEXT NOT NULL,
+        etag   TEXT,
+        PRIMARY KEY (remote, path)
+    );",
+];
+
+fn migrate(connect: &mut Connection) -> rusqlite::Result<()> {
+    let version: usize = connect.query_row("PRAGMA user_version", (), |row| row.get(0))?;
+    for (idx, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        let transaction = connect.transaction()?;
+        transaction.execute_batch(migration)?;
+        transaction.pragma_update(None, "user_version", idx + 1)?;
+        transaction.commit()?;
+    }
+    Ok(())
+}
+
 static CACHE_DB_CONNECT: Lazy<Mutex<Connection>> = Lazy::new(|| {
-    let connect = Connection::open(get_app_data_dir().unwrap().join("cache.db")).unwrap();
+    let mut connect = Connection::open(get_app_data_dir().unwrap().join("cache.db")).unwrap();
+    migrate(&mut connect).unwrap();
+    Mutex::new(connect)
+});
+
+/// Content of a file or directory when it is synchronized last time
+pub struct FileState {
+    /// SHA-256 of content in hex
+    pub hash: String,
+    pub size: i64,
+    /// Modified time in nanoseconds since unix epoch
+    pub mtime: i64,
+}
+
+/// State of the live file and the stored file of entry `path` in group,
+/// as `(live, stored)`, recorded when they are synchronized last time
+pub fn query_entry_state(group_name: &str, path: &str) -> Result<Option<(FileState, FileState)>> {
+    CACHE_DB_CONNECT
+        .lock()
+        .unwrap()
+        .query_row(
+            "SELECT live_hash, live_size, live_mtime, stored_hash, stored_size, stored_mtime
+                FROM entry_state WHERE group_name = ?1 AND path = ?2",
+            [group_name, path],
+            |row| {
+                Ok((
+                    FileState {
+                        hash: row.get(0)?,
+                        size: row.get(1)?,
+                        mtime: row.get(2)?,
+                    },
+                    FileState {
+                        hash: row.get(3)?,
+                        size: row.get(4)?,
+                        mtime: row.get(5)?,
+                    },
+                ))
+            },
+        )
+        .optional()
+        .into_diagnostic()
+}
+
+pub fn update_entry_state(
+    group_name: &str,
+    path: &str,
+    live: &FileState,
+    stored: &FileState,
+) -> Result<()> {
+    CACHE_DB_CONNECT
+        .lock()
+        .unwrap()
+        .execute(
+            "INSERT OR REPLACE INTO entry_state VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            (
+                group_name,
+                path,
+                &live.hash,
+                live.size,
+                live.mtime,
+                &stored.hash,
+                stored.size,
+                stored.mtime,
+            ),
+        )
+        .into_diagnostic()?;
+    Ok(())
+}
+
+pub fn delete_entry_state(group_name: &str, path: &str) -> Result<()> {
+    CACHE_DB_CONNECT
+        .lock()
+        .unwrap()
+        .execute(
+            "DELETE FROM entry_state WHERE group_name = ?1 AND path = ?2",
+            [group_name, path],
+        )
+        .into_diagnostic()?;
+    Ok(())
+}
+
+/// Move states of entries in group `name` to group `new_name`, replacing
+/// states left by an earlier group of that name
+pub fn rename_group_state(name: &str, new_name: &str) -> Result<()> {
+    let mut connect = CACHE_DB_CONNECT.lock().unwrap();
+    let transaction = connect.transaction().into_diagnostic()?;
+    transaction
+        .execute("DELETE FROM entry_state WHERE group_name = ?1", [new_name])
+        .into_diagnostic()?;
+    transaction
+        .execute(
+            "UPDATE entry_state SET group_name = ?2 WHERE group_name = ?1",
+            [name, new_name],
+        )
+        .into_diagnostic()?;
+    transaction.commit().into_diagnostic()
+}
+
+pub fn delete_group_state(name: &str) -> Result<()> {
+    CACHE_DB_CONNECT
+        .lock()
+        .unwrap()
+        .execute("DELETE FROM entry_state WHERE group_name = ?1", [name])
+        .into_diagnostic()?;
+    Ok(())
+}
+
+/// A file or directory moved into backup store
+pub struct BackupRow {
+    pub id: i64,
+    /// Where the backup comes from
+    pub origin: String,
+    /// Path relative to backup store
+    pub stored: String,
+    /// Unix timestamp in seconds
+    pub time: i64,
+    pub size: i64,
+}
+
+impl BackupRow {
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Self {
+            id: row.get(0)?,
+            origin: row.get(1)?,
+            stored: row.get(2)?,
+            time: row.get(3)?,
+            size: row.get(4)?,
+        })
+    }
+}
+
+pub fn insert_backup(origin: &str, stored: &str, time: i64, size: i64) -> Result<i64> {
+    let connect = CACHE_DB_CONNECT.lock().unwrap();
     connect
         .execute(
-            "CREATE TABLE IF NOT EXISTS sha256 (
-                    id    INTEGER PRIMARY KEY AUTOINCREMENT,
-                    path  TEXT NOT NULL,
-                    value TEXT NOT NULL,
-                )",
-            (),
+            "INSERT INTO backup (origin, stored, time, size) VALUES (?1, ?2, ?3, ?4)",
+            (origin, stored, time, size),
         )
-        .unwrap();
+        .into_diagnostic()?;
+    Ok(connect.last_insert_rowid())
+}
 
-    Mutex::new(connect)
-});
+/// All backups, the latest first
+pub fn query_backups() -> Result<Vec<BackupRow>> {
+    let connect = CACHE_DB_CONNECT.lock().unwrap();
+    let mut stmt = connect
+        .prepare("SELECT id, origin, stored, time, size FROM backup ORDER BY id DESC")
+        .into_diagnostic()?;
+    let rows = stmt
+        .query_map((), BackupRow::from_row)
+        .into_diagnostic()?
+        .collect::<rusqlite::Result<Vec<_>>>()
+        .into_diagnostic()?;
+    Ok(rows)
+}
 
-pub fn query_file_sha256<P: AsRef<Path>>(path: P) -> Result<String> {
-    let path_buf = dunce::canonicalize(path).into_diagnostic()?;
-    let path = path_buf.to_string_lossy();
+pub fn query_backup(id: i64) -> Result<Option<BackupRow>> {
     CACHE_DB_CONNECT
         .lock()
         .unwrap()
         .query_row(
-            "SELECT value FROM sha256 WHERE path = ?1",
-            [path.as_ref()],
-            |row| row.get(0),
+            "SELECT id, origin, stored, time, size FROM backup WHERE id = ?1",
+            [id],
+            BackupRow::from_row,
         )
+        .optional()
+        .into_diagnostic()
+}
+
+/// ID of the latest backup, 0 if there is no backup
+pub fn query_last_backup_id() -> Result<i64> {
+    CACHE_DB_CONNECT
+        .lock()
+        .unwrap()
+        .query_row("SELECT IFNULL(MAX(id), 0) FROM backup", (), |row| {
+            row.get(0)
+        })
         .into_diagnostic()
 }
 
-pub fn update_file_sha256<P: AsRef<Path>>(path: P, value: &str) -> Result<()> {
-    let path_buf = dunce::canonicalize(path).into_diagnostic()?;
-    let path = path_buf.to_string_lossy();
+pub fn delete_backup(id: i64) -> Result<()> {
+    CACHE_DB_CONNECT
+        .lock()
+        .unwrap()
+        .execute("DELETE FROM backup WHERE id = ?1", [id])
+        .into_diagnostic()?;
+    Ok(())
+}
+
+/// A file in depository when it is mirrored to remote storage last time
+pub struct MirrorRow {
+    /// Path relative to app data directory, separated by `/`
+    pub path: String,
+    /// SHA-256 of local content in hex
+    pub hash: String,
+    /// Version of remote file, such as ETag of WebDAV
+    pub etag: Option<String>,
+}
+
+/// All mirrored files of `remote`
+pub fn query_mirror_states(remote: &str) -> Result<Vec<MirrorRow>> {
+    let connect = CACHE_DB_CONNECT.lock().unwrap();
+    let mut stmt = connect
+        .prepare("SELECT path, hash, etag FROM mirror_state WHERE remote = ?1")
+        .into_diagnostic()?;
+    let rows = stmt
+        .query_map([remote], |row| {
+            Ok(MirrorRow {
+                path: row.get(0)?,
+                hash: row.get(1)?,
+                etag: row.get(2)?,
+            })
+        })
+        .into_diagnostic()?
+        .collect::<rusqlite::Result<Vec<_>>>()
+        .into_diagnostic()?;
+    Ok(rows)
+}
+
+pub fn update_mirror_state(remote: &str, row: &MirrorRow) -> Result<()> {
+    CACHE_DB_CONNECT
+        .lock()
+        .unwrap()
+        .execute(
+            "INSERT OR REPLACE INTO mirror_state VALUES (?1, ?2, ?3, ?4)",
+            (remote, &row.path, &row.hash, &row.etag),
+        )
+        .into_diagnostic()?;
+    Ok(())
+}
 
+pub fn delete_mirror_state(remote: &str, path: &str) -> Result<()> {
     CACHE_DB_CONNECT
         .lock()
         .unwrap()
         .execute(
-            "INSERT OR REPLACE INTO sha256 (id, path, value) VALUES(
-                    (SELECT id FROM sha256 WHERE path = ?1),
-                    ?1,
-                    ?2
-                )",
-            (path, value),
+            "DELETE FROM mirror_state WHERE remote = ?1 AND path = ?2",
+            [remote, path],
         )
         .into_diagnostic()?;
     Ok(())
@@ -0,0 +1,124 @@
+use std::path::{Path, PathBuf};
+
+use miette::{Context, IntoDiagnostic, Result};
+use rust_i18n::t;
+use similar::TextDiff;
+
+use super::{
+    file::{match_entry, resolve_entry_path},
+    updater::{self, Stored},
+    ItemEntryKind, TomlGroup, TomlItemEntry, Transaction,
+};
+
+pub use super::updater::DirDiff;
+
+#[derive(Debug)]
+pub enum DiffContent {
+    /// Unified diff of text file, from the stored copy to the live one
+    Text(String),
+    /// Content differs but at least one side is not a text file
+    Binary,
+    /// Summary of files changed inside a directory
+    Dir(DirDiff),
+}
+
+#[derive(Debug)]
+pub struct EntryDiff {
+    pub group: String,
+    /// Path in depository
+    pub path: String,
+    pub live: PathBuf,
+    pub content: DiffContent,
+}
+
+/// Take data as binary if it contains NUL or is not valid UTF-8
+fn as_text(data: &[u8]) -> Option<&str> {
+    if data.iter().take(8000).any(|byte| *byte == 0) {
+        None
+    } else {
+        std::str::from_utf8(data).ok()
+    }
+}
+
+/// Template entries are compared with the rendered result
+async fn diff_file(
+    entry: &TomlItemEntry,
+    group: &TomlGroup,
+    stored: &Stored,
+    live: &Path,
+) -> Result<Option<DiffContent>> {
+    let old = if entry.template {
+        updater::render_stored_template(entry, &group.name, &group.variables, stored)
+            .await?
+            .into_bytes()
+    } else {
+        updater::read_stored_file(entry, stored).await?
+    };
+    let new = if live.exists() {
+        tokio::fs::read(live).await.into_diagnostic()?
+    } else {
+        vec![]
+    };
+    if old == new {
+        return Ok(None);
+    }
+    let content = match (as_text(&old), as_text(&new)) {
+        (Some(old), Some(new)) => DiffContent::Text(
+            TextDiff::from_lines(old, new)
+                .unified_diff()
+                .context_radius(3)
+                .header(&stored.to_string(), &live.to_string_lossy())
+                .to_string(),
+        ),
+        _ => DiffContent::Binary,
+    };
+    Ok(Some(content))
+}
+
+/// Collect differences between live files and depository
+///
+/// If `group` is `None`, all groups in current profile are checked.
+/// If `path` is given, only the entry matching it is checked.
+pub async fn diff(group: Option<String>, path: Option<PathBuf>) -> Result<Vec<EntryDiff>> {
+    let transaction = Transaction::start_shared()
+        .await
+        .wrap_err(t!("error.ctx.transcation.init"))?;
+    let groups = super::group::select_groups(&transaction, group).await?;
+
+    let mut diffs = vec![];
+    for group_name in groups {
+        let group = transaction.group(&group_name).await?.clone();
+        for entry in &group.files {
+            if let Some(path) = &path {
+                if !match_entry(&**transaction.storage(), entry, &group_name, path)? {
+                    continue;
+                }
+            }
+            let live = match resolve_entry_path(&**transaction.storage(), entry, &group_name)? {
+                Some((live, _)) => live,
+                None => continue,
+            };
+            let stored = Stored::of(transaction.storage(), &group_name, entry);
+            let content = match entry.kind {
+                ItemEntryKind::File => diff_file(entry, &group, &stored, &live).await?,
+                ItemEntryKind::Dir => {
+                    let dir_diff = updater::diff_stored_dir(entry, &live, &stored).await?;
+                    if dir_diff.is_empty() {
+                        None
+                    } else {
+                        Some(DiffContent::Dir(dir_diff))
+                    }
+                }
+            };
+            if let Some(content) = content {
+                diffs.push(EntryDiff {
+                    group: group_name.clone(),
+                    path: entry.path.clone(),
+                    live,
+                    content,
+                });
+            }
+        }
+    }
+    Ok(diffs)
+}
@@ -4,11 +4,21 @@ use miette::{Context, IntoDiagnostic, Result};
 use rust_i18n::t;
 
 use crate::{
-    env::{self, get_group_dir, to_depositiory_path, SpecDir},
-    ui::Ui,
+    config,
+    env::{self, to_depositiory_path, SpecDir},
+    error::{DMError, GroupErrorKind},
+    ui::{MsgLevel, Ui},
 };
 
-use super::{DMPath, ItemEntryKind, TomlItemEntry, Transaction, updater};
+use super::{
+    backup,
+    journal::write_atomic,
+    merge, state,
+    status::EntryStatus,
+    storage::{self, Storage},
+    updater::{self, Stored},
+    DMPath, ItemEntryKind, LinkMode, TomlGroup, TomlItemEntry, TomlScriptEntry, Transaction,
+};
 
 fn recongize_spec_path(path: PathBuf, try_recongized: bool, ui_handle: &dyn Ui) -> Result<DMPath> {
     let value = if try_recongized {
@@ -55,29 +65,386 @@ fn recongize_spec_path(path: PathBuf, try_recongized: bool, ui_handle: &dyn Ui)
     Ok(value)
 }
 
-pub(super) async fn check_update(entry: &TomlItemEntry, group_name:&str) -> Result<bool>{
-    let src = entry
-        .get_platform_install_path()
-        .unwrap()
-        .parse(&SpecDir::new()?)?;
-    let dst = get_group_dir(group_name).unwrap().join(&entry.path);
-    let mut updater = updater::construct_updater(entry)?;
-    updater.is_diff(entry, &src, &dst).await
+/// Resolve the live path and the depository path of an entry in current platform
+///
+/// Returns `None` if the entry has no install path for current platform
+///
+/// The state of entries is recorded by their files on local file system, so
+/// `storage` of depository must be local.
+pub(super) fn resolve_entry_path(
+    storage: &dyn Storage,
+    entry: &TomlItemEntry,
+    group_name: &str,
+) -> Result<Option<(PathBuf, PathBuf)>> {
+    let live = match entry.get_platform_install_path() {
+        Some(path) => path.parse(&SpecDir::new()?)?,
+        None => return Ok(None),
+    };
+    let path = storage::group_path(group_name, &entry.path);
+    let stored = storage
+        .local_path(&path)
+        .ok_or_else(|| storage::not_local(&path))
+        .into_diagnostic()?;
+    Ok(Some((live, stored)))
+}
+
+/// Check whether `path` refers to the entry, either by its install path or by its depository path
+pub(super) fn match_entry(
+    storage: &dyn Storage,
+    entry: &TomlItemEntry,
+    group_name: &str,
+    path: &Path,
+) -> Result<bool> {
+    if Path::new(&entry.path) == path {
+        return Ok(true);
+    }
+    let path = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        std::env::current_dir().into_diagnostic()?.join(path)
+    };
+    // Links in any of them are resolved, paths not existing are compared as they are
+    let canonical = |path: PathBuf| dunce::canonicalize(&path).unwrap_or(path);
+    let path = canonical(path);
+    Ok(match resolve_entry_path(storage, entry, group_name)? {
+        Some((live, stored)) => canonical(live) == path || canonical(stored) == path,
+        None => false,
+    })
+}
+
+/// Install path and stored path of an entry, fails if it is not available in current platform
+fn entry_paths(
+    storage: &dyn Storage,
+    entry: &TomlItemEntry,
+    group_name: &str,
+) -> Result<(PathBuf, PathBuf)> {
+    resolve_entry_path(storage, entry, group_name)?
+        .ok_or_else(|| DMError::GroupError {
+            kind: GroupErrorKind::NoInstallPath,
+            msg: t!(
+                "error.group.no_install_path.msg",
+                path = &entry.path,
+                os = std::env::consts::OS
+            ),
+            advice: None,
+        })
+        .into_diagnostic()
+}
+
+/// Whether the live file of entry differs from the stored one, entries not
+/// available in current platform have nothing to update
+pub(super) async fn check_update(
+    ui_handle: &dyn Ui,
+    transaction: &Transaction,
+    entry: &TomlItemEntry,
+    group: &TomlGroup,
+) -> Result<bool> {
+    let storage = transaction.storage();
+    let (src, dst) = match resolve_entry_path(&**storage, entry, &group.name)? {
+        Some(paths) => paths,
+        None => return Ok(false),
+    };
+    // Rendered template and scripts may change even if files are untouched,
+    // and a link replaced by a copy of the same content is not told by cache
+    let cacheable = !entry.template && !entry.manaul && matches!(entry.link, LinkMode::Copy);
+    if cacheable && state::is_synced(&group.name, &entry.path, &src, &dst)? {
+        return Ok(false);
+    }
+    let mut updater = updater::construct_updater(entry, group, ui_handle, storage)?;
+    let diff = updater
+        .is_diff(entry, &src, &Stored::of(storage, &group.name, entry))
+        .await?;
+    if !diff {
+        state::record_sync(&group.name, &entry.path, &src, &dst, !entry.encrypt)?;
+    }
+    Ok(diff)
 }
 
+/// Copy the live file of an entry into depository
+///
+/// The stored file is protected by transaction, so it is restored if the
+/// transaction is not committed. Its backup is kept in backup store anyway.
 pub(super) async fn update_file_from_entry(
     ui_handle: &dyn Ui,
-    group_name: &str,
+    transaction: &Transaction,
+    group: &TomlGroup,
     entry: &TomlItemEntry,
 ) -> Result<()> {
-    let src = entry
-        .get_platform_install_path()
-        .unwrap()
-        .parse(&SpecDir::new()?)?;
-    let dst = get_group_dir(group_name).unwrap().join(&entry.path);
+    let storage = transaction.storage();
+    let (src, dst) = entry_paths(&**storage, entry, &group.name)?;
+    transaction.protect(&dst)?;
+
+    let mut updater = updater::construct_updater(entry, group, ui_handle, storage)?;
+    updater
+        .update(entry, &src, &Stored::of(storage, &group.name, entry))
+        .await
+        .wrap_err(t!("error.ctx.io.copy2depository"))?;
+    state::record_sync(&group.name, &entry.path, &src, &dst, !entry.encrypt)
+}
+
+/// Copy the stored file of an entry back to its install location
+///
+/// Returns the install location, or `None` if the entry is not available in current platform
+pub(super) async fn install_file_from_entry(
+    ui_handle: &dyn Ui,
+    transaction: &Transaction,
+    group: &TomlGroup,
+    entry: &TomlItemEntry,
+) -> Result<Option<PathBuf>> {
+    let storage = transaction.storage();
+    let (dst, src) = match resolve_entry_path(&**storage, entry, &group.name)? {
+        Some(paths) => paths,
+        None => return Ok(None),
+    };
+
+    let mut updater = updater::construct_updater(entry, group, ui_handle, storage)?;
+    updater
+        .install(entry, &Stored::of(storage, &group.name, entry), &dst)
+        .await
+        .wrap_err(t!("error.ctx.io.copy2install"))?;
+    state::record_sync(&group.name, &entry.path, &dst, &src, !entry.encrypt)?;
+    Ok(Some(dst))
+}
+
+/// Classify an entry by comparing it with its state at last synchronization
+///
+/// Both the live file and the stored file must exist, entries not available in
+/// current platform are `NoInstallPath`.
+pub(super) async fn sync_status(
+    ui_handle: &dyn Ui,
+    transaction: &Transaction,
+    entry: &TomlItemEntry,
+    group: &TomlGroup,
+) -> Result<EntryStatus> {
+    let (live, stored) = match resolve_entry_path(&**transaction.storage(), entry, &group.name)? {
+        Some(paths) => paths,
+        None => return Ok(EntryStatus::NoInstallPath),
+    };
+    if !check_update(ui_handle, transaction, entry, group).await? {
+        return Ok(EntryStatus::Clean);
+    }
+    Ok(
+        match state::changed_sides(&group.name, &entry.path, &live, &stored)? {
+            Some((true, false)) => EntryStatus::LocalModified,
+            // Rendered template may change while neither file is modified
+            Some((false, _)) => EntryStatus::DepositoryModified,
+            _ => EntryStatus::Conflicted,
+        },
+    )
+}
+
+/// Whether both sides of entry could be merged as plain text
+fn is_mergeable(entry: &TomlItemEntry) -> bool {
+    matches!(entry.kind, ItemEntryKind::File)
+        && matches!(entry.link, LinkMode::Copy)
+        && !entry.manaul
+        && !entry.template
+}
+
+/// Merge changes of the live file and the stored file since last
+/// synchronization, and write the result to both of them
+///
+/// Built-in merge is used first, the external merge tool in configuration
+/// is only run if it leaves conflicts. Encrypted entries are never passed to
+/// the tool, so that they are not written to temporary files in plain.
+async fn merge_entry(
+    transaction: &Transaction,
+    group: &TomlGroup,
+    entry: &TomlItemEntry,
+) -> Result<()> {
+    let storage = transaction.storage();
+    let (live, stored) = entry_paths(&**storage, entry, &group.name)?;
+    let base = state::read_base(&group.name, &entry.path)?.unwrap_or_default();
+    let local = std::fs::read(&live).into_diagnostic()?;
+    let stored_file = Stored::of(storage, &group.name, entry);
+    let depository = updater::read_stored_file(entry, &stored_file).await?;
+    let merged = match (
+        std::str::from_utf8(&base),
+        std::str::from_utf8(&local),
+        std::str::from_utf8(&depository),
+    ) {
+        (Ok(base), Ok(local), Ok(depository)) => Some(merge::merge(base, local, depository)),
+        _ => None,
+    };
+
+    let tool = config::CONFIG
+        .lock()
+        .await
+        .merge
+        .tool
+        .clone()
+        .filter(|_| !entry.encrypt);
+    // Only advise the merge tool if it would be used
+    let advice = if entry.encrypt {
+        t!("error.conflict.advice")
+    } else {
+        t!("error.conflict.advice_tool")
+    };
+    let result = match (tool, merged) {
+        (_, Some(merged)) if merged.is_clean() => merged.text.into_bytes(),
+        (Some(tool), merged) => {
+            let initial = merged.map_or_else(|| local.clone(), |merged| merged.text.into_bytes());
+            let extension = live
+                .extension()
+                .map(|extension| format!(".{}", extension.to_string_lossy()))
+                .unwrap_or_default();
+            let result =
+                merge::merge_with_tool(&tool, &base, &local, &depository, &initial, &extension)
+                    .await?;
+            match result {
+                Some(result) if !merge::has_conflict_markers(&result) => result,
+                _ => Err(DMError::ConflictError {
+                    msg: t!("error.conflict.tool.msg", path = &entry.path, tool = &tool),
+                    advice: Some(t!("error.conflict.advice")),
+                })
+                .into_diagnostic()?,
+            }
+        }
+        (None, Some(merged)) => Err(DMError::ConflictError {
+            msg: t!(
+                "error.conflict.merge.msg",
+                path = &entry.path,
+                count = &merged.conflicts.to_string()
+            ),
+            advice: Some(advice),
+        })
+        .into_diagnostic()?,
+        (None, None) => Err(DMError::ConflictError {
+            msg: t!("error.conflict.not_text.msg", path = &entry.path),
+            advice: Some(advice),
+        })
+        .into_diagnostic()?,
+    };
+    transaction.protect(&stored)?;
+    transaction.protect(&live)?;
+    updater::write_stored_file(entry, &stored_file, &result).await?;
+    backup::backup_file(&live).await?;
+    write_atomic(&live, &result)?;
+    state::record_sync(&group.name, &entry.path, &live, &stored, !entry.encrypt)
+}
+
+/// Ask user how to resolve a conflicted entry
+///
+/// Returns `false` if user skips it, so that nothing is overwritten.
+pub(super) async fn resolve_conflict(
+    ui_handle: &dyn Ui,
+    transaction: &Transaction,
+    group: &TomlGroup,
+    entry: &TomlItemEntry,
+) -> Result<bool> {
+    let mut options = vec![
+        t!("conflict.skip"),
+        t!("conflict.keep_local"),
+        t!("conflict.keep_stored"),
+    ];
+    if is_mergeable(entry) {
+        options.push(t!("conflict.merge"));
+    }
+    let choice = ui_handle.choose(
+        Some(&t!("conflict.prompt", path = &entry.path)),
+        options.iter().map(String::as_str).collect(),
+    )?;
+    match choice {
+        1 => update_file_from_entry(ui_handle, transaction, group, entry).await?,
+        2 => {
+            install_file_from_entry(ui_handle, transaction, group, entry).await?;
+        }
+        3 => merge_entry(transaction, group, entry).await?,
+        _ => return Ok(false),
+    }
+    Ok(true)
+}
+
+/// Scripts used to manage a manual entry in a platform
+#[derive(Default)]
+pub struct ManualScripts {
+    /// Platform running the scripts, current one if not set
+    pub os: Option<String>,
+    pub install: Option<PathBuf>,
+    pub update: Option<PathBuf>,
+    pub diff: Option<PathBuf>,
+}
+
+impl ManualScripts {
+    /// Platform the scripts are recorded for
+    fn os(&self) -> &str {
+        self.os.as_deref().unwrap_or(std::env::consts::OS)
+    }
+
+    /// Copy scripts of `entry_path` into the group directory, return their paths relative to it
+    async fn store(
+        &self,
+        transaction: &Transaction,
+        group_name: &str,
+        entry_path: &str,
+    ) -> Result<TomlScriptEntry> {
+        let dir = format!("{}/{}", script_dir(entry_path), self.os());
+        let store = |kind: &'static str, script: &Option<PathBuf>| {
+            store_script(
+                transaction,
+                group_name,
+                format!("{}/{}", dir, kind),
+                script.clone(),
+            )
+        };
+        Ok(TomlScriptEntry::new(
+            store("install", &self.install).await?,
+            store("update", &self.update).await?,
+            store("diff", &self.diff).await?,
+        ))
+    }
+}
+
+/// Directory of scripts used by entry at `entry_path`, relative to the group directory
+fn script_dir(entry_path: &str) -> String {
+    format!("script/{}", entry_path)
+}
+
+/// Copy script into the group directory as `name`, return its path relative to it
+///
+/// The extension of script is kept, which decides how it runs on some platforms.
+async fn store_script(
+    transaction: &Transaction,
+    group_name: &str,
+    mut name: String,
+    script: Option<PathBuf>,
+) -> Result<Option<String>> {
+    let script = match script {
+        Some(script) => script,
+        None => return Ok(None),
+    };
+    if let Some(extension) = script.extension() {
+        name = format!("{}.{}", name, extension.to_string_lossy());
+    }
+    let path = storage::group_path(group_name, &name);
+    let storage = transaction.storage();
+    let local = storage
+        .local_path(&path)
+        .ok_or_else(|| storage::not_local(&path))
+        .into_diagnostic()?;
+    transaction.protect(&local)?;
+    let data = std::fs::read(script).into_diagnostic()?;
+    storage
+        .write(&path, &data)
+        .await
+        .wrap_err(t!("error.ctx.io.copy_script"))?;
+    Ok(Some(name))
+}
 
-    let mut updater = updater::construct_updater(entry)?;
-    updater.update(entry, &src, &dst).await.wrap_err(t!("error.ctx.io.copy2depository"))
+/// Options to add a file
+#[derive(Default)]
+pub struct AddOptions {
+    /// Recongize special path according to platform and environment variable
+    pub try_recongize: bool,
+    /// Use scripts to install manually
+    pub manaul_install: bool,
+    /// Only used when `manaul_install` is set
+    pub scripts: ManualScripts,
+    pub link: LinkMode,
+    pub compress: bool,
+    pub encrypt: bool,
+    /// Store the file as a template rendered at install time
+    pub template: bool,
 }
 
 /// Add a file or directory to repository
@@ -86,12 +453,13 @@ pub async fn add_file<P: AsRef<Path>>(
     ui_handle: &dyn Ui,
     path: P,
     group_name: &str,
-    try_recongize: bool,
-    manaul_install: bool,
+    options: AddOptions,
 ) -> Result<()> {
     let path = path.as_ref().to_path_buf();
-    let mut transaction = Transaction::start().wrap_err(t!("error.ctx.transcation.init"))?;
-    let mut group = transaction.group_mut(group_name)?;
+    let mut transaction = Transaction::start()
+        .await
+        .wrap_err(t!("error.ctx.transcation.init"))?;
+    let group = transaction.group_mut(group_name).await?.clone();
     if path.is_symlink() {
         todo!("throw an error")
     }
@@ -101,23 +469,164 @@ pub async fn add_file<P: AsRef<Path>>(
     } else {
         ItemEntryKind::Dir
     };
+    if options.template && matches!(kind, ItemEntryKind::Dir) {
+        Err(DMError::TemplateError {
+            msg: t!("error.template.dir.msg", path = &path.to_string_lossy()),
+            advice: None,
+        })
+        .into_diagnostic()?;
+    }
 
-    let dm_path = recongize_spec_path(path.clone(), try_recongize, ui_handle)?;
+    let dm_path = recongize_spec_path(path.clone(), options.try_recongize, ui_handle)?;
+
+    let mut depository_path = to_depositiory_path(path).to_str().unwrap().to_string();
+    if matches!(kind, ItemEntryKind::Dir) && (options.compress || options.encrypt) {
+        depository_path.push_str(".tar");
+    }
+    if options.compress {
+        depository_path.push_str(".zst");
+    }
+    if options.encrypt {
+        depository_path.push_str(".age");
+    }
 
     let mut file_entry = TomlItemEntry::new(
         kind,
-        to_depositiory_path(path).to_str().unwrap().to_string(),
-        manaul_install,
+        depository_path,
+        options.manaul_install,
+        options.link,
+        options.compress,
+        options.encrypt,
+        options.template,
     );
     file_entry.insert_platform_install_path(dm_path);
-    update_file_from_entry(ui_handle, group_name, &file_entry)
+    if options.manaul_install {
+        let scripts = options
+            .scripts
+            .store(&transaction, group_name, &file_entry.path)
+            .await?;
+        file_entry.insert_script(options.scripts.os(), scripts);
+    }
+    update_file_from_entry(ui_handle, &transaction, &group, &file_entry)
         .await
         .wrap_err(t!("error.ctx.io.update_file"))?;
 
-    group.files.push(file_entry);
+    transaction
+        .group_mut(group_name)
+        .await?
+        .files
+        .push(file_entry);
 
-    std::mem::drop(group);
     transaction
         .commit()
         .wrap_err(t!("error.ctx.transcation.commit"))
 }
+
+/// Remove a file or directory at `path`, symbolic link is removed without following it
+async fn remove_path(path: &Path) -> Result<()> {
+    let metadata = tokio::fs::symlink_metadata(path).await.into_diagnostic()?;
+    if metadata.is_dir() {
+        tokio::fs::remove_dir_all(path).await.into_diagnostic()
+    } else {
+        tokio::fs::remove_file(path).await.into_diagnostic()
+    }
+}
+
+/// Options to remove a file
+#[derive(Default)]
+pub struct RemoveOptions {
+    /// Delete the stored file in depository
+    pub delete_stored: bool,
+    /// Delete the installed file on this machine
+    pub delete_installed: bool,
+}
+
+/// Stop tracking the entry matching `path` in group
+///
+/// `path` could be either the install path or the depository path of entry.
+/// Files are kept by default, otherwise they are deleted along with the manifest
+/// entry, except the installed file which is deleted after commit.
+pub async fn remove_file<P: AsRef<Path>>(
+    ui_handle: &dyn Ui,
+    path: P,
+    group_name: &str,
+    options: RemoveOptions,
+) -> Result<()> {
+    let path = path.as_ref();
+    let mut transaction = Transaction::start()
+        .await
+        .wrap_err(t!("error.ctx.transcation.init"))?;
+    super::group::select_groups(&transaction, Some(group_name.to_string())).await?;
+
+    let storage = transaction.storage().clone();
+    let entry = {
+        let mut group = transaction.group_mut(group_name).await?;
+        let mut position = None;
+        for (idx, entry) in group.files.iter().enumerate() {
+            if match_entry(&*storage, entry, group_name, path)? {
+                position = Some(idx);
+                break;
+            }
+        }
+        match position {
+            Some(idx) => group.files.remove(idx),
+            None => Err(DMError::GroupError {
+                kind: GroupErrorKind::FileNotExists,
+                msg: t!(
+                    "error.group.file_not_exists.msg",
+                    path = &path.to_string_lossy(),
+                    name = group_name
+                ),
+                advice: None,
+            })
+            .into_diagnostic()?,
+        }
+    };
+    let stored = Stored::of(&storage, group_name, &entry);
+    let live = resolve_entry_path(&*storage, &entry, group_name)?.map(|(live, _)| live);
+
+    // Stored files are deleted along with the manifest, so that they are
+    // recorded in history and restored by undo
+    let mut deleted = None;
+    if options.delete_stored {
+        let scripts =
+            transaction.local_path(&storage::group_path(group_name, &script_dir(&entry.path)))?;
+        let stored_path = stored.local_path()?;
+        let existed = stored_path.symlink_metadata().is_ok();
+        for path in [&scripts, &stored_path] {
+            if path.symlink_metadata().is_ok() {
+                transaction.protect(path)?;
+                super::journal::remove_path(path).wrap_err(t!("error.ctx.io.delete_stored"))?;
+            }
+        }
+        deleted = existed.then_some(stored_path);
+    }
+    state::forget(group_name, &entry.path)?;
+    transaction
+        .commit()
+        .wrap_err(t!("error.ctx.transcation.commit"))?;
+    ui_handle.msg(
+        MsgLevel::Info,
+        t!("file.remove.removed", path = &entry.path),
+    );
+
+    if let Some(stored) = deleted {
+        ui_handle.msg(
+            MsgLevel::Info,
+            t!("file.remove.deleted", path = &stored.to_string_lossy()),
+        );
+    }
+
+    if options.delete_installed {
+        if let Some(live) = live.filter(|live| live.symlink_metadata().is_ok()) {
+            remove_path(&live)
+                .await
+                .wrap_err(t!("error.ctx.io.delete_installed"))?;
+            ui_handle.msg(
+                MsgLevel::Info,
+                t!("file.remove.deleted", path = &live.to_string_lossy()),
+            );
+        }
+    }
+    Ok(())
+}
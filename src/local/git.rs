@@ -0,0 +1,147 @@
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+use miette::{Context, IntoDiagnostic, Result};
+use once_cell::sync::OnceCell;
+use rust_i18n::t;
+
+use crate::{
+    env::{get_app_data_dir, get_hostname},
+    error::DMError,
+};
+
+use super::{history, lock::DepositoryLock};
+
+/// Only the global configuration and the depository are tracked, caches,
+/// history and backups are local to this machine
+const GITIGNORE: &str = "/*\n!/.gitignore\n!/dm.toml\n!/depository/\n";
+
+/// Root of the repository, which is the app data directory
+fn get_repository_dir() -> Result<PathBuf> {
+    get_app_data_dir()
+}
+
+/// Whether user has configured an identity for git
+static HAS_IDENTITY: OnceCell<bool> = OnceCell::new();
+
+fn git_command() -> Result<Command> {
+    let dir = get_repository_dir()?;
+    let has_identity = *HAS_IDENTITY.get_or_try_init(|| {
+        Command::new("git")
+            .args(["config", "user.email"])
+            .current_dir(&dir)
+            .output()
+            .map(|output| output.status.success())
+            .into_diagnostic()
+            .wrap_err(t!("error.ctx.git.run"))
+    })?;
+    let mut command = Command::new("git");
+    command.current_dir(dir);
+    // Identity of user is preferred, dm commits on behalf of this host if it is not set
+    if !has_identity {
+        command
+            .args(["-c", "user.name=dm", "-c"])
+            .arg(format!("user.email=dm@{}", get_hostname()));
+    }
+    Ok(command)
+}
+
+/// Run git with `args` and capture its output
+pub(super) fn git_output(args: &[&str]) -> Result<Output> {
+    git_command()?
+        .args(args)
+        .output()
+        .into_diagnostic()
+        .wrap_err(t!("error.ctx.git.run"))
+}
+
+/// Whether git exits with success when running with `args`
+pub(super) fn succeeds(args: &[&str]) -> Result<bool> {
+    Ok(git_output(args)?.status.success())
+}
+
+/// Run git with `args` and capture its output, fails if git exits with failure
+pub(super) fn run_git(args: &[&str]) -> Result<Output> {
+    check(args, git_output(args)?)
+}
+
+/// Fail with the error message of git if `output` of running `args` is a failure
+pub(super) fn check(args: &[&str], output: Output) -> Result<Output> {
+    if !output.status.success() {
+        Err(DMError::GitError {
+            msg: t!(
+                "error.git.failed.msg",
+                command = &args.join(" "),
+                err = String::from_utf8_lossy(&output.stderr).trim()
+            ),
+            advice: None,
+        })
+        .into_diagnostic()?;
+    }
+    Ok(output)
+}
+
+/// Whether the depository is version-controlled by git
+///
+/// It is opt-in by running `dm git init`.
+pub fn is_enabled() -> Result<bool> {
+    Ok(get_repository_dir()?.join(".git").exists())
+}
+
+fn has_commit() -> Result<bool> {
+    succeeds(&["rev-parse", "--verify", "--quiet", "HEAD"])
+}
+
+/// Commit staged changes with `message`
+pub(super) fn commit(message: &str) -> Result<()> {
+    run_git(&["commit", "--quiet", "--allow-empty", "--message", message])?;
+    Ok(())
+}
+
+/// Commit all changes of the tracked files with `message`, nothing is
+/// committed if nothing changes
+pub(super) fn commit_all(message: &str) -> Result<()> {
+    let gitignore = get_repository_dir()?.join(".gitignore");
+    if !gitignore.exists() {
+        std::fs::write(gitignore, GITIGNORE).into_diagnostic()?;
+    }
+    run_git(&["add", "--all"])?;
+    if succeeds(&["diff", "--cached", "--quiet"])? && has_commit()? {
+        return Ok(());
+    }
+    commit(message)
+}
+
+/// Commit changes made by a committed transaction, if git is enabled
+///
+/// The message is the command line which started the transaction.
+pub(super) fn commit_transaction() -> Result<()> {
+    if !is_enabled()? {
+        return Ok(());
+    }
+    let message = format!("{}\n\nHost: {}", history::command_line(), get_hostname());
+    commit_all(&message).map_err(|err| {
+        err.wrap_err(DMError::GitError {
+            msg: t!("error.git.commit.msg"),
+            advice: Some(t!("error.git.commit.advice")),
+        })
+    })
+}
+
+/// Run git with `args` in the repository, returns the exit code of git
+///
+/// The depository is locked while git is running. If it is the first time
+/// git is enabled, the current depository is committed at once.
+pub async fn git(args: Vec<String>) -> Result<i32> {
+    let _lock = DepositoryLock::acquire()?;
+    let status = git_command()?
+        .args(&args)
+        .status()
+        .into_diagnostic()
+        .wrap_err(t!("error.ctx.git.run"))?;
+    if is_enabled()? && !has_commit()? {
+        commit_all(&history::command_line())?;
+    }
+    // Killed by signal is taken as failure
+    Ok(status.code().unwrap_or(-1))
+}
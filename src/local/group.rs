@@ -1,46 +1,306 @@
-use miette::{Context, Result};
+use miette::{Context, IntoDiagnostic, Result};
 use rust_i18n::t;
 
-use crate::{config, ui::Ui};
+use crate::{
+    error::{DMError, GroupErrorKind, ProfileErrorKind},
+    ui::{MsgLevel, Ui},
+};
 
-use super::Transaction;
+use super::{file, status::EntryStatus, Transaction};
 
-pub async fn create_group(name: String, nouse:bool) -> Result<()>{
-    let mut transaction = Transaction::start().wrap_err(t!("error.ctx.transcation.init"))?;
+pub async fn create_group(name: String, nouse: bool) -> Result<()> {
+    let use_profile = super::profile::current_profile().await?.name;
+    let mut transaction = Transaction::start()
+        .await
+        .wrap_err(t!("error.ctx.transcation.init"))?;
     transaction.create_group(&name)?;
 
     if !nouse {
-        let use_profile = &config::CONFIG.lock().await.using_profile;
-        let pos = transaction
-            .global
-            .registery
-            .profile
-            .iter()
-            .position(|entry| &entry.name == use_profile)
-            .unwrap();
         transaction
-            .global
+            .global_mut()
             .registery
             .profile
-            .get_mut(pos)
-            .unwrap()
+            .iter_mut()
+            .find(|entry| entry.name == use_profile)
+            .ok_or_else(|| DMError::ProfileError {
+                kind: ProfileErrorKind::NotExists,
+                msg: t!("error.profile.not_exists.msg", name = &use_profile),
+                advice: None,
+            })
+            .into_diagnostic()?
             .group
             .push(name);
     }
-    transaction.commit().wrap_err(t!("error.ctx.transcation.commit"))
+    transaction
+        .commit()
+        .wrap_err(t!("error.ctx.transcation.commit"))
+}
+
+/// Delete group and all files stored in it, after confirmed by user
+pub async fn delete_group(ui_handle: &dyn Ui, name: String, confirm_all: bool) -> Result<()> {
+    let mut transaction = Transaction::start()
+        .await
+        .wrap_err(t!("error.ctx.transcation.init"))?;
+    transaction.check_group_exists(&name)?;
+    if !confirm_all
+        && !ui_handle.input_yes_or_no(Some(&t!("group.delete.confirm", name = &name)), false)?
+    {
+        return Ok(());
+    }
+    transaction.delete_group(&name)?;
+    transaction
+        .commit()
+        .wrap_err(t!("error.ctx.transcation.commit"))
+}
+
+pub async fn rename_group(name: String, new_name: String) -> Result<()> {
+    let mut transaction = Transaction::start()
+        .await
+        .wrap_err(t!("error.ctx.transcation.init"))?;
+    transaction.rename_group(&name, &new_name).await?;
+    transaction
+        .commit()
+        .wrap_err(t!("error.ctx.transcation.commit"))
+}
+
+/// Set description of group, an empty description clears it
+pub async fn describe_group(name: String, description: String) -> Result<()> {
+    let mut transaction = Transaction::start()
+        .await
+        .wrap_err(t!("error.ctx.transcation.init"))?;
+    transaction.check_group_exists(&name)?;
+    transaction.group_mut(&name).await?.description = if description.is_empty() {
+        None
+    } else {
+        Some(description)
+    };
+    transaction
+        .commit()
+        .wrap_err(t!("error.ctx.transcation.commit"))
+}
+
+#[derive(Debug)]
+pub struct GroupSummary {
+    pub name: String,
+    pub description: Option<String>,
+    /// Count of files in group
+    pub files: usize,
+    /// Profiles which the group is attached to directly
+    pub profiles: Vec<String>,
+}
+
+/// List all groups in depository
+pub async fn list_group() -> Result<Vec<GroupSummary>> {
+    let transaction = Transaction::start_shared()
+        .await
+        .wrap_err(t!("error.ctx.transcation.init"))?;
+    let mut summaries = vec![];
+    for name in &transaction.global().registery.group {
+        let group = transaction.group(name).await?;
+        summaries.push(GroupSummary {
+            name: name.clone(),
+            description: group.description.clone(),
+            files: group.files.len(),
+            profiles: transaction
+                .global()
+                .registery
+                .profile
+                .iter()
+                .filter(|profile| profile.group.contains(name))
+                .map(|profile| profile.name.clone())
+                .collect(),
+        });
+    }
+    Ok(summaries)
 }
 
+/// Error for conflicted entries skipped by user
+fn conflicts_skipped(count: usize) -> Result<()> {
+    if count == 0 {
+        return Ok(());
+    }
+    Err(DMError::ConflictError {
+        msg: t!("error.conflict.skipped.msg", count = &count.to_string()),
+        advice: Some(t!("error.conflict.skipped.advice")),
+    })
+    .into_diagnostic()
+}
 
-pub async fn update_group(ui_handle: &dyn Ui,name: String) -> Result<()> {
-    let transaction = Transaction::start().wrap_err(t!("error.ctx.transcation.init"))?;
-    let group = transaction.group(&name)?;
+/// Copy modified live files of a group into depository
+///
+/// Entries modified in depository are skipped, and conflicted entries are
+/// resolved by user. If `force` is set, live files always win.
+pub async fn update_group(ui_handle: &dyn Ui, name: String, force: bool) -> Result<()> {
+    let transaction = Transaction::start()
+        .await
+        .wrap_err(t!("error.ctx.transcation.init"))?;
+    let group = transaction.group(&name).await?.clone();
+    let mut conflicts = 0;
     for entry in &group.files {
-        if crate::local::file::check_update(entry, &group.name).await? {
-            let prompt = t!("group.prompt.update_file_or_not", path = &entry.path);
-            if ui_handle.input_yes_or_no(Some(&prompt), false)? {
-                crate::local::file::update_file_from_entry(ui_handle, &group.name, entry).await?;
+        if file::resolve_entry_path(&**transaction.storage(), entry, &name)?.is_none() {
+            ui_handle.msg(
+                MsgLevel::Warn,
+                t!(
+                    "group.update.skipped",
+                    path = &entry.path,
+                    os = std::env::consts::OS
+                ),
+            );
+            continue;
+        }
+        let status = if force {
+            if file::check_update(ui_handle, &transaction, entry, &group).await? {
+                EntryStatus::LocalModified
+            } else {
+                EntryStatus::Clean
+            }
+        } else {
+            file::sync_status(ui_handle, &transaction, entry, &group).await?
+        };
+        match status {
+            EntryStatus::LocalModified => {
+                let prompt = t!("group.prompt.update_file_or_not", path = &entry.path);
+                if ui_handle.input_yes_or_no(Some(&prompt), false)? {
+                    file::update_file_from_entry(ui_handle, &transaction, &group, entry).await?;
+                }
+            }
+            EntryStatus::DepositoryModified => ui_handle.msg(
+                MsgLevel::Warn,
+                t!("group.update.depository_modified", path = &entry.path),
+            ),
+            EntryStatus::Conflicted
+                if !file::resolve_conflict(ui_handle, &transaction, &group, entry).await? =>
+            {
+                conflicts += 1;
+            }
+            _ => {}
+        }
+    }
+    transaction
+        .commit()
+        .wrap_err(t!("error.ctx.transcation.commit"))?;
+    conflicts_skipped(conflicts)
+}
+
+/// Get the group `name` after checking it exists, or all groups in current profile if `None`
+pub(super) async fn select_groups(
+    transaction: &Transaction,
+    name: Option<String>,
+) -> Result<Vec<String>> {
+    match name {
+        Some(name) => {
+            transaction.check_group_exists(&name)?;
+            Ok(vec![name])
+        }
+        None => {
+            let use_profile = super::profile::current_profile().await?.name;
+            crate::local::profile::profile_groups(transaction, &use_profile)
+        }
+    }
+}
+
+/// Install files of a group to current machine
+///
+/// If `name` is `None`, all groups in current profile will be installed.
+/// Entries modified locally are skipped, and conflicted entries are resolved
+/// by user. If `force` is set, stored files always win.
+pub async fn install_group(ui_handle: &dyn Ui, name: Option<String>, force: bool) -> Result<()> {
+    let transaction = Transaction::start()
+        .await
+        .wrap_err(t!("error.ctx.transcation.init"))?;
+    let groups = select_groups(&transaction, name).await?;
+
+    let (mut installed, mut skipped, mut failed, mut conflicts) = (0, 0, 0, 0);
+    for group_name in &groups {
+        let group = transaction.group(group_name).await?.clone();
+        for entry in &group.files {
+            let both_exist = file::resolve_entry_path(&**transaction.storage(), entry, group_name)?
+                .is_some_and(|(live, stored)| live.exists() && stored.exists());
+            if !force && both_exist {
+                match file::sync_status(ui_handle, &transaction, entry, &group).await? {
+                    // Already installed
+                    EntryStatus::Clean => continue,
+                    EntryStatus::LocalModified => {
+                        skipped += 1;
+                        ui_handle.msg(
+                            MsgLevel::Warn,
+                            t!("group.install.local_modified", path = &entry.path),
+                        );
+                        continue;
+                    }
+                    EntryStatus::Conflicted => {
+                        if file::resolve_conflict(ui_handle, &transaction, &group, entry).await? {
+                            installed += 1;
+                        } else {
+                            conflicts += 1;
+                        }
+                        continue;
+                    }
+                    _ => {}
+                }
+            }
+            match file::install_file_from_entry(ui_handle, &transaction, &group, entry).await {
+                Ok(Some(dst)) => {
+                    installed += 1;
+                    ui_handle.msg(
+                        MsgLevel::Info,
+                        t!(
+                            "group.install.installed",
+                            path = &entry.path,
+                            dst = &dst.to_string_lossy()
+                        ),
+                    );
+                }
+                Ok(None) => {
+                    skipped += 1;
+                    ui_handle.msg(
+                        MsgLevel::Warn,
+                        t!(
+                            "group.install.skipped",
+                            path = &entry.path,
+                            os = std::env::consts::OS
+                        ),
+                    );
+                }
+                Err(err) => {
+                    failed += 1;
+                    ui_handle.msg(
+                        MsgLevel::Error,
+                        t!(
+                            "group.install.failed",
+                            path = &entry.path,
+                            err = &format!("{:?}", err)
+                        ),
+                    );
+                }
             }
         }
     }
-    Ok(())
+    ui_handle.msg(
+        MsgLevel::Info,
+        t!(
+            "group.install.summary",
+            installed = &installed.to_string(),
+            skipped = &skipped.to_string(),
+            failed = &failed.to_string()
+        ),
+    );
+    // Depository is changed if a conflict is resolved by keeping local file
+    transaction
+        .commit()
+        .wrap_err(t!("error.ctx.transcation.commit"))?;
+    conflicts_skipped(conflicts)?;
+    if failed != 0 {
+        Err(DMError::GroupError {
+            kind: GroupErrorKind::InstallFailed,
+            msg: t!(
+                "error.group.install_failed.msg",
+                count = &failed.to_string()
+            ),
+            advice: None,
+        })
+        .into_diagnostic()
+    } else {
+        Ok(())
+    }
 }
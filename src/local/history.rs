@@ -0,0 +1,313 @@
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use miette::{Context, IntoDiagnostic, Result};
+use rust_i18n::t;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::DMHistoryConfiguration,
+    env::get_app_data_dir,
+    error::DMError,
+    ui::{MsgLevel, Ui},
+};
+
+use super::{
+    journal::{copy_path, remove_path, Journal},
+    Transaction,
+};
+
+fn get_history_dir() -> Result<PathBuf> {
+    Ok(get_app_data_dir()?.join("history"))
+}
+
+/// Original state of a path changed by transaction
+#[derive(Serialize, Deserialize)]
+struct Snapshot {
+    path: PathBuf,
+    /// Name of the copy in `objects` directory, `None` if the path did not exist
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    object: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct RenameRecord {
+    from: PathBuf,
+    to: PathBuf,
+}
+
+/// A committed transaction, saved in `<app data>/history/<id>/record.toml`
+/// with copies of the original content in `objects`
+#[derive(Serialize, Deserialize)]
+pub struct HistoryRecord {
+    pub id: u64,
+    /// Command line which started the transaction
+    pub command: String,
+    /// Unix timestamp in seconds
+    time: u64,
+    /// Global configuration and group manifests changed
+    #[serde(default)]
+    pub manifests: Vec<PathBuf>,
+    /// Files and directories changed in depository
+    #[serde(default)]
+    pub files: Vec<PathBuf>,
+    /// Backups of stored files created by the transaction
+    #[serde(default)]
+    pub backups: Vec<PathBuf>,
+    #[serde(default)]
+    renamed: Vec<RenameRecord>,
+    #[serde(default)]
+    snapshot: Vec<Snapshot>,
+}
+
+impl HistoryRecord {
+    pub fn time(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(self.time)
+    }
+
+    /// Restore depository to the state before this transaction, all changed
+    /// paths are protected by `transaction`
+    fn restore(&self, transaction: &Transaction) -> Result<()> {
+        let objects = get_history_dir()?.join(self.id.to_string()).join("objects");
+        for snapshot in self.snapshot.iter().rev() {
+            transaction.protect(&snapshot.path)?;
+            remove_path(&snapshot.path)?;
+            if let Some(object) = &snapshot.object {
+                std::fs::create_dir_all(snapshot.path.parent().unwrap()).into_diagnostic()?;
+                copy_path(&objects.join(object), &snapshot.path)?;
+            }
+        }
+        for rename in self.renamed.iter().rev() {
+            transaction.protect(&rename.from)?;
+            transaction.protect(&rename.to)?;
+            if rename.to.exists() && !rename.from.exists() {
+                std::fs::rename(&rename.to, &rename.from).into_diagnostic()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A manifest written when commit
+pub(super) struct ManifestChange {
+    pub path: PathBuf,
+    /// Where the manifest is before commit, differs from `path` if its group is renamed
+    pub original: PathBuf,
+    pub content: String,
+}
+
+/// Everything a transaction changes besides protected paths
+#[derive(Default)]
+pub(super) struct Changes {
+    pub manifests: Vec<ManifestChange>,
+    pub renamed: Vec<(PathBuf, PathBuf)>,
+    pub deleted: Vec<PathBuf>,
+    pub backups: Vec<PathBuf>,
+}
+
+fn is_same_file(a: &Path, b: &Path) -> bool {
+    match (std::fs::read(a), std::fs::read(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+fn load_record(dir: &Path) -> Result<HistoryRecord> {
+    let content = std::fs::read_to_string(dir.join("record.toml")).into_diagnostic()?;
+    toml_edit::de::from_str(&content)
+        .into_diagnostic()
+        .wrap_err(t!("error.ctx.serde.deserializing"))
+}
+
+/// Ids of recorded transactions, in no particular order
+fn history_ids() -> Result<Vec<u64>> {
+    let dir = get_history_dir()?;
+    let mut ids = vec![];
+    if !dir.exists() {
+        return Ok(ids);
+    }
+    for item in std::fs::read_dir(dir).into_diagnostic()? {
+        if let Some(id) = item
+            .into_diagnostic()?
+            .file_name()
+            .to_str()
+            .and_then(|name| name.parse::<u64>().ok())
+        {
+            ids.push(id);
+        }
+    }
+    Ok(ids)
+}
+
+fn load_history() -> Result<Vec<HistoryRecord>> {
+    let dir = get_history_dir()?;
+    let mut records = history_ids()?
+        .into_iter()
+        .map(|id| load_record(&dir.join(id.to_string())))
+        .collect::<Result<Vec<_>>>()?;
+    records.sort_by_key(|record| std::cmp::Reverse(record.id));
+    Ok(records)
+}
+
+/// All recorded transactions, the latest first
+pub async fn list_history() -> Result<Vec<HistoryRecord>> {
+    load_history()
+}
+
+/// Command line which started current process
+pub(super) fn command_line() -> String {
+    std::iter::once(String::from("dm"))
+        .chain(std::env::args().skip(1))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Save the history of transaction into journal, it is moved into place when
+/// the journal commits. Nothing is recorded if the transaction changes nothing.
+///
+/// Transactions out of `policy` are removed along with their objects when the
+/// journal commits, so they can't be undone any more.
+pub(super) fn record(
+    journal: &mut Journal,
+    changes: Changes,
+    policy: &DMHistoryConfiguration,
+) -> Result<()> {
+    let history_dir = get_history_dir()?;
+    let ids = history_ids()?;
+    let id = ids.iter().max().map_or(1, |id| id + 1);
+
+    // All protected paths are kept, a path protected after its parent
+    // directory is restored before the directory
+    let mut snapshot: Vec<(PathBuf, Option<PathBuf>)> = journal
+        .originals()
+        .map(|(path, original)| (path.to_path_buf(), original.map(Path::to_path_buf)))
+        .collect();
+    let changed: Vec<PathBuf> = snapshot
+        .iter()
+        .filter(|(path, original)| match original {
+            Some(original) => original.is_dir() || !is_same_file(path, original),
+            None => path.symlink_metadata().is_ok(),
+        })
+        .map(|(path, _)| path.clone())
+        .collect();
+    let mut files = changed.clone();
+    let mut manifests = vec![];
+    for manifest in changes.manifests {
+        if snapshot.iter().any(|(path, _)| *path == manifest.path) {
+            if changed.contains(&manifest.path) {
+                manifests.push(manifest.path);
+            }
+            continue;
+        }
+        let unchanged = std::fs::read(&manifest.original)
+            .is_ok_and(|original| original == manifest.content.as_bytes());
+        if unchanged {
+            continue;
+        }
+        let group_dir = manifest.original.parent().unwrap();
+        if manifest.path != manifest.original || group_dir.exists() {
+            let original = manifest.original.exists().then_some(manifest.original);
+            snapshot.push((manifest.path.clone(), original));
+        } else {
+            // Directory of a new group is removed as a whole
+            snapshot.insert(0, (group_dir.to_path_buf(), None));
+        }
+        manifests.push(manifest.path);
+    }
+    files.retain(|path| !manifests.contains(path));
+    for path in changes.deleted {
+        if !snapshot.iter().any(|(protected, _)| *protected == path) {
+            snapshot.push((path.clone(), Some(path.clone())));
+            files.push(path);
+        }
+    }
+    if files.is_empty() && manifests.is_empty() && changes.renamed.is_empty() {
+        return Ok(());
+    }
+
+    let dir = journal.stage_dir(&history_dir.join(id.to_string()))?;
+    let objects = dir.join("objects");
+    std::fs::create_dir_all(&objects).into_diagnostic()?;
+    let snapshot = snapshot
+        .into_iter()
+        .enumerate()
+        .map(|(idx, (path, original))| {
+            let object = match original {
+                Some(original) => {
+                    copy_path(&original, &objects.join(idx.to_string()))?;
+                    Some(idx.to_string())
+                }
+                None => None,
+            };
+            Ok(Snapshot { path, object })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let record = HistoryRecord {
+        id,
+        command: command_line(),
+        time: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs(),
+        manifests,
+        files,
+        backups: changes.backups,
+        renamed: changes
+            .renamed
+            .into_iter()
+            .map(|(from, to)| RenameRecord { from, to })
+            .collect(),
+        snapshot,
+    };
+    let value = toml_edit::ser::to_string_pretty(&record)
+        .into_diagnostic()
+        .wrap_err(t!("error.ctx.serde.serializing"))?;
+    std::fs::write(dir.join("record.toml"), value).into_diagnostic()?;
+
+    if policy.keep != 0 {
+        for old in ids.into_iter().filter(|old| old + policy.keep as u64 <= id) {
+            journal.stage_remove(&history_dir.join(old.to_string()));
+        }
+    }
+    Ok(())
+}
+
+/// Revert transaction `id` and all transactions after it, the latest one if `id` is `None`
+///
+/// Undo is a transaction itself, so it is recorded and can be undone as well.
+pub async fn undo(ui_handle: &dyn Ui, id: Option<u64>) -> Result<()> {
+    let mut transaction = Transaction::start()
+        .await
+        .wrap_err(t!("error.ctx.transcation.init"))?;
+    let records = list_history().await?;
+    let id = match id.or(records.first().map(|record| record.id)) {
+        Some(id) => id,
+        None => Err(DMError::HistoryError {
+            msg: t!("error.history.empty.msg"),
+            advice: None,
+        })
+        .into_diagnostic()?,
+    };
+    if !records.iter().any(|record| record.id == id) {
+        Err(DMError::HistoryError {
+            msg: t!("error.history.not_exists.msg", id = &id.to_string()),
+            advice: Some(t!("error.history.not_exists.advice")),
+        })
+        .into_diagnostic()?;
+    }
+    for record in records.iter().take_while(|record| record.id >= id) {
+        record.restore(&transaction)?;
+        ui_handle.msg(
+            MsgLevel::Info,
+            t!(
+                "history.undo.undone",
+                id = &record.id.to_string(),
+                command = &record.command
+            ),
+        );
+    }
+    transaction.reload().await?;
+    transaction
+        .commit()
+        .wrap_err(t!("error.ctx.transcation.commit"))
+}
@@ -0,0 +1,358 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use miette::{Context, IntoDiagnostic, Result};
+use rust_i18n::t;
+use serde::{Deserialize, Serialize};
+
+use crate::{env::get_app_data_dir, platform};
+
+/// Write `data` to a temporary file beside `path` and rename it to `path`,
+/// so that `path` holds either the old content or the new one
+pub(super) fn write_atomic(path: &Path, data: &[u8]) -> Result<()> {
+    let mut filename = path.file_name().unwrap().to_os_string();
+    filename.push(".dm-tmp");
+    let temp = path.with_file_name(filename);
+    let mut file = std::fs::File::create(&temp).into_diagnostic()?;
+    file.write_all(data).into_diagnostic()?;
+    file.sync_all().into_diagnostic()?;
+    std::fs::rename(&temp, path).into_diagnostic()
+}
+
+fn get_journal_dir() -> Result<PathBuf> {
+    Ok(get_app_data_dir()?.join("journal"))
+}
+
+/// Remove a file or directory, do nothing if it does not exist
+pub(super) fn remove_path(path: &Path) -> Result<()> {
+    match path.symlink_metadata() {
+        Ok(metadata) if metadata.is_dir() => std::fs::remove_dir_all(path).into_diagnostic(),
+        Ok(_) => std::fs::remove_file(path).into_diagnostic(),
+        Err(_) => Ok(()),
+    }
+}
+
+/// Copy a file or directory, symbolic links are copied as links rather than
+/// the files they point to
+pub(super) fn copy_path(src: &Path, dst: &Path) -> Result<()> {
+    let metadata = src.symlink_metadata().into_diagnostic()?;
+    if metadata.is_symlink() {
+        let target = std::fs::read_link(src).into_diagnostic()?;
+        if src.is_dir() {
+            platform::symlink_dir_specify(target, dst).into_diagnostic()?;
+        } else {
+            platform::symlink_file_specify(target, dst).into_diagnostic()?;
+        }
+    } else if metadata.is_dir() {
+        std::fs::create_dir_all(dst).into_diagnostic()?;
+        for item in std::fs::read_dir(src).into_diagnostic()? {
+            let item = item.into_diagnostic()?;
+            copy_path(&item.path(), &dst.join(item.file_name()))?;
+        }
+    } else {
+        std::fs::copy(src, dst).into_diagnostic()?;
+    }
+    Ok(())
+}
+
+/// Original state of a path modified in transaction
+#[derive(Serialize, Deserialize)]
+struct UndoRecord {
+    path: PathBuf,
+    /// Copy of the original content, `None` if the path did not exist
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    backup: Option<PathBuf>,
+}
+
+/// Operation applied when transaction commits, each of them could be
+/// applied again safely
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "op")]
+enum RedoRecord {
+    /// Move a staged file to its target
+    Write {
+        staged: PathBuf,
+        target: PathBuf,
+    },
+    Rename {
+        from: PathBuf,
+        to: PathBuf,
+    },
+    Remove {
+        path: PathBuf,
+    },
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct JournalFile {
+    /// Whether all operations are staged, journal is rolled forward since then
+    committing: bool,
+    #[serde(default)]
+    undo: Vec<UndoRecord>,
+    #[serde(default)]
+    redo: Vec<RedoRecord>,
+}
+
+/// Write-ahead journal of a transaction, saved in `<app data>/journal`
+///
+/// Before commit, files modified in depository are protected by keeping
+/// a copy, and restored if the transaction is aborted. When commit, new
+/// manifests are staged in journal and moved into place after the journal
+/// is marked as committing. A journal left by a crashed process is finished
+/// by the next transaction.
+pub(super) struct Journal {
+    dir: PathBuf,
+    file: JournalFile,
+}
+
+impl Journal {
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            dir: get_journal_dir()?,
+            file: JournalFile::default(),
+        })
+    }
+
+    fn is_empty(&self) -> bool {
+        self.file.undo.is_empty() && self.file.redo.is_empty()
+    }
+
+    fn save(&self) -> Result<()> {
+        std::fs::create_dir_all(&self.dir).into_diagnostic()?;
+        let value = toml_edit::ser::to_string_pretty(&self.file)
+            .into_diagnostic()
+            .wrap_err(t!("error.ctx.serde.serializing"))?;
+        write_atomic(&self.dir.join("journal.toml"), value.as_bytes())
+    }
+
+    /// Keep the original content of `path` before it is modified
+    pub fn protect(&mut self, path: &Path) -> Result<()> {
+        if self.file.undo.iter().any(|record| record.path == path) {
+            return Ok(());
+        }
+        let backup = if path.symlink_metadata().is_ok() {
+            let backup = self
+                .dir
+                .join("backup")
+                .join(self.file.undo.len().to_string());
+            std::fs::create_dir_all(backup.parent().unwrap()).into_diagnostic()?;
+            copy_path(path, &backup)?;
+            Some(backup)
+        } else {
+            None
+        };
+        self.file.undo.push(UndoRecord {
+            path: path.to_path_buf(),
+            backup,
+        });
+        self.save()
+    }
+
+    /// Stage content to be written to `target` when commit
+    pub fn stage_write(&mut self, target: &Path, data: &[u8]) -> Result<()> {
+        let staged = self
+            .dir
+            .join("stage")
+            .join(self.file.redo.len().to_string());
+        std::fs::create_dir_all(staged.parent().unwrap()).into_diagnostic()?;
+        write_atomic(&staged, data)?;
+        self.file.redo.push(RedoRecord::Write {
+            staged,
+            target: target.to_path_buf(),
+        });
+        Ok(())
+    }
+
+    /// Stage a copy of `src` to be moved to `target` when commit
+    pub fn stage_copy(&mut self, src: &Path, target: &Path) -> Result<()> {
+        let staged = self
+            .dir
+            .join("stage")
+            .join(self.file.redo.len().to_string());
+        std::fs::create_dir_all(staged.parent().unwrap()).into_diagnostic()?;
+        copy_path(src, &staged)?;
+        self.file.redo.push(RedoRecord::Write {
+            staged,
+            target: target.to_path_buf(),
+        });
+        Ok(())
+    }
+
+    /// Create an empty directory to be moved to `target` when commit
+    pub fn stage_dir(&mut self, target: &Path) -> Result<PathBuf> {
+        let staged = self
+            .dir
+            .join("stage")
+            .join(self.file.redo.len().to_string());
+        std::fs::create_dir_all(&staged).into_diagnostic()?;
+        self.file.redo.push(RedoRecord::Write {
+            staged: staged.clone(),
+            target: target.to_path_buf(),
+        });
+        Ok(staged)
+    }
+
+    /// Protected paths and copies of their original content
+    pub fn originals(&self) -> impl Iterator<Item = (&Path, Option<&Path>)> {
+        self.file
+            .undo
+            .iter()
+            .map(|record| (record.path.as_path(), record.backup.as_deref()))
+    }
+
+    pub fn stage_rename(&mut self, from: &Path, to: &Path) {
+        self.file.redo.push(RedoRecord::Rename {
+            from: from.to_path_buf(),
+            to: to.to_path_buf(),
+        });
+    }
+
+    pub fn stage_remove(&mut self, path: &Path) {
+        self.file.redo.push(RedoRecord::Remove {
+            path: path.to_path_buf(),
+        });
+    }
+
+    fn roll_forward(&self) -> Result<()> {
+        for record in &self.file.redo {
+            match record {
+                RedoRecord::Write { staged, target } => {
+                    if staged.symlink_metadata().is_ok() {
+                        std::fs::create_dir_all(target.parent().unwrap()).into_diagnostic()?;
+                        std::fs::rename(staged, target).into_diagnostic()?;
+                    }
+                }
+                RedoRecord::Rename { from, to } => {
+                    if from.symlink_metadata().is_ok() && to.symlink_metadata().is_err() {
+                        std::fs::rename(from, to).into_diagnostic()?;
+                    }
+                }
+                RedoRecord::Remove { path } => remove_path(path)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Restore protected paths, which could be run again if it is interrupted
+    ///
+    /// Copies of the original content are kept until the journal is cleared.
+    fn roll_back(&self) -> Result<()> {
+        for record in self.file.undo.iter().rev() {
+            match &record.backup {
+                Some(backup) if backup.symlink_metadata().is_err() => continue,
+                Some(backup) => {
+                    remove_path(&record.path)?;
+                    std::fs::create_dir_all(record.path.parent().unwrap()).into_diagnostic()?;
+                    copy_path(backup, &record.path)?;
+                }
+                None => remove_path(&record.path)?,
+            }
+        }
+        Ok(())
+    }
+
+    fn clear(&mut self) -> Result<()> {
+        self.file = JournalFile::default();
+        remove_path(&self.dir)
+    }
+
+    /// Apply staged operations, the journal is empty afterwards
+    pub fn commit(&mut self) -> Result<()> {
+        if self.is_empty() {
+            return Ok(());
+        }
+        self.file.committing = true;
+        self.save()?;
+        self.roll_forward()?;
+        self.clear()
+    }
+
+    /// Restore protected files, or finish staged operations if commit is
+    /// interrupted, the journal is empty afterwards
+    pub fn abort(&mut self) -> Result<()> {
+        if self.is_empty() {
+            return Ok(());
+        }
+        if self.file.committing {
+            self.roll_forward()?;
+        } else {
+            self.roll_back()?;
+        }
+        self.clear()
+    }
+
+    /// Whether an interrupted transaction left its journal
+    pub fn is_pending() -> Result<bool> {
+        Ok(get_journal_dir()?.join("journal.toml").exists())
+    }
+
+    /// Finish the journal left by an interrupted transaction
+    pub fn recover() -> Result<()> {
+        let dir = get_journal_dir()?;
+        let path = dir.join("journal.toml");
+        if !path.exists() {
+            // Staged files without journal are never referenced
+            return remove_path(&dir);
+        }
+        let file: JournalFile =
+            toml_edit::de::from_str(&std::fs::read_to_string(path).into_diagnostic()?)
+                .into_diagnostic()
+                .wrap_err(t!("error.ctx.serde.deserializing"))?;
+        Self { dir, file }.abort()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("dm-journal-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn roll_back_twice() {
+        let dir = temp_dir("roll-back");
+        let modified = dir.join("modified");
+        let created = dir.join("created");
+        std::fs::write(&modified, "original").unwrap();
+
+        let mut journal = Journal {
+            dir: dir.join("journal"),
+            file: JournalFile::default(),
+        };
+        journal.protect(&modified).unwrap();
+        journal.protect(&created).unwrap();
+        std::fs::write(&modified, "changed").unwrap();
+        std::fs::write(&created, "created").unwrap();
+
+        journal.roll_back().unwrap();
+        // A process crashed before clearing the journal rolls back again
+        journal.roll_back().unwrap();
+        assert_eq!(std::fs::read_to_string(&modified).unwrap(), "original");
+        assert!(!created.exists());
+
+        journal.clear().unwrap();
+        assert!(!dir.join("journal").exists());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn roll_forward_twice() {
+        let dir = temp_dir("roll-forward");
+        let target = dir.join("target");
+        let mut journal = Journal {
+            dir: dir.join("journal"),
+            file: JournalFile::default(),
+        };
+        journal.protect(&target).unwrap();
+        journal.stage_write(&target, b"staged").unwrap();
+        journal.roll_forward().unwrap();
+        journal.roll_forward().unwrap();
+        assert_eq!(std::fs::read_to_string(&target).unwrap(), "staged");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
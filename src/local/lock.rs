@@ -0,0 +1,254 @@
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io::{Read, Seek, Write};
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use miette::{IntoDiagnostic, Result};
+use rust_i18n::t;
+use same_file::Handle;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    env::{get_app_data_dir, get_hostname},
+    error::DMError,
+    platform::process_exists,
+    ui::{MsgLevel, Ui},
+};
+
+fn get_lock_path() -> Result<PathBuf> {
+    Ok(get_app_data_dir()?.join(".lock"))
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+/// Owner of the lock, written into lock file
+#[derive(Serialize, Deserialize, Debug)]
+struct LockInfo {
+    pid: u32,
+    hostname: String,
+    /// Unix timestamp in seconds
+    started: u64,
+}
+
+impl LockInfo {
+    fn current() -> Self {
+        Self {
+            pid: std::process::id(),
+            hostname: get_hostname(),
+            started: now(),
+        }
+    }
+
+    fn read(file: &mut File) -> Option<Self> {
+        let mut content = String::new();
+        file.rewind().ok()?;
+        file.read_to_string(&mut content).ok()?;
+        toml_edit::de::from_str(&content).ok()
+    }
+
+    /// Whether the owner could be checked on this host, unknown owner is
+    /// assumed to be local
+    fn is_local(info: &Option<Self>) -> bool {
+        info.as_ref()
+            .is_none_or(|info| info.hostname == get_hostname())
+    }
+
+    /// Whether the owner is still running on this host
+    ///
+    /// The lock file alone can be taken over by mistake where advisory lock
+    /// doesn't work, e.g. on network file systems, so the process is checked
+    /// as well. Current process can't be the owner, the file must be left by
+    /// an earlier process with the same pid.
+    fn is_alive(info: &Option<Self>) -> bool {
+        info.as_ref().is_some_and(|info| {
+            info.hostname == get_hostname()
+                && info.pid != std::process::id()
+                && process_exists(info.pid)
+        })
+    }
+
+    fn describe(info: &Option<Self>) -> String {
+        match info {
+            Some(info) => t!(
+                "lock.owner",
+                pid = &info.pid.to_string(),
+                hostname = &info.hostname,
+                elapsed = &now().saturating_sub(info.started).to_string()
+            ),
+            None => t!("lock.unknown_owner"),
+        }
+    }
+}
+
+/// Result of trying to lock the lock file
+enum LockState {
+    /// Lock is acquired, returns the previous owner recorded in lock file
+    Acquired(Option<LockInfo>),
+    /// Lock is held by another process
+    Held(Option<LockInfo>),
+}
+
+/// Lock of depository, released when dropped
+///
+/// The lock file records the owner and is locked by OS advisory lock while
+/// the owner is alive. If the lock file exists but is neither locked nor its
+/// owner process running, the owner must have exited without removing it, so
+/// the lock is taken over. Owner on another host can't be detected in this
+/// way, so the lock is left to user.
+///
+/// Readers share the lock with each other, but not with the owner. They are
+/// not recorded in the lock file, and leave it in place when released.
+pub(super) struct DepositoryLock {
+    file: File,
+    path: PathBuf,
+    shared: bool,
+}
+
+impl DepositoryLock {
+    fn open(path: &PathBuf, shared: bool) -> Result<(File, LockState)> {
+        loop {
+            let mut file = OpenOptions::new()
+                .create(true)
+                .truncate(false)
+                .read(true)
+                .write(true)
+                .open(path)
+                .into_diagnostic()?;
+            let locked = if shared {
+                file.try_lock_shared()
+            } else {
+                file.try_lock()
+            };
+            match locked {
+                Ok(()) => {}
+                Err(TryLockError::WouldBlock) => {
+                    let info = LockInfo::read(&mut file);
+                    return Ok((file, LockState::Held(info)));
+                }
+                Err(TryLockError::Error(err)) => return Err(err).into_diagnostic(),
+            }
+            // The lock file may be removed by its owner before we lock it
+            if !path.exists()
+                || Handle::from_file(file.try_clone().into_diagnostic()?).into_diagnostic()?
+                    != Handle::from_path(path).into_diagnostic()?
+            {
+                continue;
+            }
+            let info = LockInfo::read(&mut file);
+            return Ok((file, LockState::Acquired(info)));
+        }
+    }
+
+    /// Fail if the lock is held by others, or left by another host
+    fn check(state: LockState) -> Result<()> {
+        match state {
+            LockState::Acquired(info) if !LockInfo::is_local(&info) => {
+                Err(DMError::LockError {
+                    msg: t!(
+                        "error.transcation.lock.msg",
+                        owner = &LockInfo::describe(&info)
+                    ),
+                    advice: t!("error.transcation.lock.advice_remote"),
+                })
+                .into_diagnostic()?;
+            }
+            LockState::Acquired(info) if !LockInfo::is_alive(&info) => {}
+            LockState::Acquired(info) | LockState::Held(info) => {
+                Err(DMError::LockError {
+                    msg: t!(
+                        "error.transcation.lock.msg",
+                        owner = &LockInfo::describe(&info)
+                    ),
+                    advice: t!("error.transcation.lock.advice"),
+                })
+                .into_diagnostic()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Lock depository exclusively and record current process as owner
+    pub fn acquire() -> Result<Self> {
+        let path = get_lock_path()?;
+        let (mut file, state) = Self::open(&path, false)?;
+        Self::check(state)?;
+        let content = toml_edit::ser::to_string_pretty(&LockInfo::current()).into_diagnostic()?;
+        file.set_len(0).into_diagnostic()?;
+        file.rewind().into_diagnostic()?;
+        file.write_all(content.as_bytes()).into_diagnostic()?;
+        file.sync_all().into_diagnostic()?;
+        Ok(Self {
+            file,
+            path,
+            shared: false,
+        })
+    }
+
+    /// Lock depository for reading, along with other readers
+    pub fn acquire_shared() -> Result<Self> {
+        let path = get_lock_path()?;
+        let (file, state) = Self::open(&path, true)?;
+        Self::check(state)?;
+        Ok(Self {
+            file,
+            path,
+            shared: true,
+        })
+    }
+}
+
+impl Drop for DepositoryLock {
+    fn drop(&mut self) {
+        // Remove the file before unlocking, so that nobody else could lock it
+        if !self.shared {
+            let _ = std::fs::remove_file(&self.path);
+        }
+        let _ = self.file.unlock();
+    }
+}
+
+/// Remove the lock of depository
+///
+/// Stale lock is removed directly. Lock held by a running process or by
+/// another host is removed only if `force` is set and user confirms it.
+pub async fn unlock(ui_handle: &dyn Ui, force: bool) -> Result<()> {
+    let path = get_lock_path()?;
+    if !path.exists() {
+        ui_handle.msg(MsgLevel::Info, t!("lock.unlock.not_locked"));
+        return Ok(());
+    }
+    let (file, state) = DepositoryLock::open(&path, false)?;
+    let owner = match state {
+        LockState::Acquired(info) if LockInfo::is_local(&info) && !LockInfo::is_alive(&info) => {
+            drop(DepositoryLock {
+                file,
+                path,
+                shared: false,
+            });
+            ui_handle.msg(
+                MsgLevel::Info,
+                t!("lock.unlock.stale", owner = &LockInfo::describe(&info)),
+            );
+            return Ok(());
+        }
+        LockState::Acquired(info) | LockState::Held(info) => LockInfo::describe(&info),
+    };
+    drop(file);
+    if !force {
+        Err(DMError::LockError {
+            msg: t!("error.transcation.lock.msg", owner = &owner),
+            advice: t!("error.transcation.lock.advice"),
+        })
+        .into_diagnostic()?;
+    }
+    if ui_handle.input_yes_or_no(Some(&t!("lock.unlock.confirm", owner = &owner)), false)? {
+        std::fs::remove_file(&path).into_diagnostic()?;
+        ui_handle.msg(MsgLevel::Info, t!("lock.unlock.removed"));
+    }
+    Ok(())
+}
@@ -0,0 +1,576 @@
+use miette::{Context, IntoDiagnostic, Result};
+use rust_i18n::t;
+use similar::{capture_diff_slices, Algorithm, DiffOp};
+use toml_edit::{Array, ArrayOfTables, Document, Item, Table, Value};
+
+use crate::{platform, tempfile::Tempfile};
+
+const LOCAL_MARKER: &str = "<<<<<<< local";
+const DEPOSITORY_MARKER: &str = ">>>>>>> depository";
+
+/// Result of a line-based three-way merge
+pub(super) struct Merged {
+    /// Merged text, conflicted regions are surrounded by conflict markers
+    pub text: String,
+    /// Count of conflicted regions
+    pub conflicts: usize,
+}
+
+impl Merged {
+    pub fn is_clean(&self) -> bool {
+        self.conflicts == 0
+    }
+}
+
+fn split_lines(text: &str) -> Vec<&str> {
+    text.split_inclusive('\n').collect()
+}
+
+/// A region `base[base_start..base_end]` replaced by `other[other_start..other_end]`
+#[derive(Clone, Copy)]
+struct Hunk {
+    base_start: usize,
+    base_end: usize,
+    other_start: usize,
+    other_end: usize,
+}
+
+/// Regions of `base` changed in `other`
+fn hunks(base: &[&str], other: &[&str]) -> Vec<Hunk> {
+    let mut hunks: Vec<Hunk> = vec![];
+    for op in capture_diff_slices(Algorithm::Myers, base, other) {
+        if let DiffOp::Equal { .. } = op {
+            continue;
+        }
+        let (base_range, other_range) = (op.old_range(), op.new_range());
+        match hunks.last_mut() {
+            Some(last) if last.base_end == base_range.start => {
+                last.base_end = base_range.end;
+                last.other_end = other_range.end;
+            }
+            _ => hunks.push(Hunk {
+                base_start: base_range.start,
+                base_end: base_range.end,
+                other_start: other_range.start,
+                other_end: other_range.end,
+            }),
+        }
+    }
+    hunks
+}
+
+/// Content of `base[start..end]` after applying `hunks` inside it
+fn apply<'a>(
+    base: &[&'a str],
+    other: &[&'a str],
+    hunks: &[Hunk],
+    start: usize,
+    end: usize,
+) -> Vec<&'a str> {
+    let mut lines = vec![];
+    let mut pos = start;
+    for hunk in hunks {
+        lines.extend_from_slice(&base[pos..hunk.base_start]);
+        lines.extend_from_slice(&other[hunk.other_start..hunk.other_end]);
+        pos = hunk.base_end;
+    }
+    lines.extend_from_slice(&base[pos..end]);
+    lines
+}
+
+fn push_lines(text: &mut String, lines: &[&str]) {
+    for line in lines {
+        text.push_str(line);
+    }
+}
+
+fn push_marker(text: &mut String, marker: &str) {
+    if !text.is_empty() && !text.ends_with('\n') {
+        text.push('\n');
+    }
+    text.push_str(marker);
+    text.push('\n');
+}
+
+/// Merge changes from `base` to `local` and from `base` to `depository`
+///
+/// Regions changed by only one side take that side, regions changed by both
+/// sides in different ways are conflicts.
+pub(super) fn merge(base: &str, local: &str, depository: &str) -> Merged {
+    let (base, local, depository) = (
+        split_lines(base),
+        split_lines(local),
+        split_lines(depository),
+    );
+    let local_hunks = hunks(&base, &local);
+    let depository_hunks = hunks(&base, &depository);
+
+    let mut text = String::new();
+    let mut conflicts = 0;
+    let (mut pos, mut i, mut j) = (0, 0, 0);
+    while i < local_hunks.len() || j < depository_hunks.len() {
+        // Collect overlapping hunks of both sides into one region
+        let start = match (local_hunks.get(i), depository_hunks.get(j)) {
+            (Some(a), Some(b)) => a.base_start.min(b.base_start),
+            (Some(a), None) => a.base_start,
+            (None, Some(b)) => b.base_start,
+            (None, None) => unreachable!(),
+        };
+        let mut end = start;
+        let (first_i, first_j) = (i, j);
+        loop {
+            let overlaps = |hunk: &Hunk| hunk.base_start < end || hunk.base_start == start;
+            if let Some(hunk) = local_hunks.get(i).filter(|hunk| overlaps(hunk)) {
+                end = end.max(hunk.base_end);
+                i += 1;
+            } else if let Some(hunk) = depository_hunks.get(j).filter(|hunk| overlaps(hunk)) {
+                end = end.max(hunk.base_end);
+                j += 1;
+            } else {
+                break;
+            }
+        }
+
+        push_lines(&mut text, &base[pos..start]);
+        let local_part = apply(&base, &local, &local_hunks[first_i..i], start, end);
+        let depository_part = apply(
+            &base,
+            &depository,
+            &depository_hunks[first_j..j],
+            start,
+            end,
+        );
+        if first_j == j || local_part == depository_part {
+            push_lines(&mut text, &local_part);
+        } else if first_i == i {
+            push_lines(&mut text, &depository_part);
+        } else {
+            conflicts += 1;
+            push_marker(&mut text, LOCAL_MARKER);
+            push_lines(&mut text, &local_part);
+            push_marker(&mut text, "=======");
+            push_lines(&mut text, &depository_part);
+            push_marker(&mut text, DEPOSITORY_MARKER);
+        }
+        pos = end;
+    }
+    push_lines(&mut text, &base[pos..]);
+    Merged { text, conflicts }
+}
+
+/// Whether `data` still contains conflict markers written by [`merge`]
+pub(super) fn has_conflict_markers(data: &[u8]) -> bool {
+    String::from_utf8_lossy(data)
+        .lines()
+        .any(|line| line == LOCAL_MARKER || line == DEPOSITORY_MARKER)
+}
+
+fn write_temp(suffix: &str, data: &[u8]) -> Result<Tempfile> {
+    let file = Tempfile::new(suffix)?;
+    std::fs::write(file.get_path_buf(), data)
+        .into_diagnostic()
+        .wrap_err(t!("error.ctx.io.temp"))?;
+    Ok(file)
+}
+
+/// Merge by external merge `tool`, returns the merged content or `None` if the tool fails
+///
+/// Each version is written to a temporary file ending with `extension`, and
+/// the merged file starts with `initial`.
+pub(super) async fn merge_with_tool(
+    tool: &str,
+    base: &[u8],
+    local: &[u8],
+    depository: &[u8],
+    initial: &[u8],
+    extension: &str,
+) -> Result<Option<Vec<u8>>> {
+    let base = write_temp(&format!(".base{}", extension), base)?;
+    let local = write_temp(&format!(".local{}", extension), local)?;
+    let depository = write_temp(&format!(".depository{}", extension), depository)?;
+    let merged = write_temp(&format!(".merged{}", extension), initial)?;
+    let status = platform::shell_command(tool)
+        .env("DM_BASE", base.get_path_buf())
+        .env("DM_LOCAL", local.get_path_buf())
+        .env("DM_DEPOSITORY", depository.get_path_buf())
+        .env("DM_MERGED", merged.get_path_buf())
+        .status()
+        .await
+        .into_diagnostic()
+        .wrap_err(t!("error.ctx.merge.tool", tool = tool))?;
+    if !status.success() {
+        return Ok(None);
+    }
+    Ok(Some(
+        std::fs::read(merged.get_path_buf()).into_diagnostic()?,
+    ))
+}
+
+/// Result of a key-wise three-way merge of TOML documents
+pub(super) struct MergedDocument {
+    /// Merged document, `None` if it is deleted
+    pub text: Option<String>,
+    /// Keys changed by both sides in different ways, e.g. `files[<path>].template`
+    pub conflicts: Vec<String>,
+}
+
+/// Keys identifying a table in an array of tables, such as profiles and entries
+const IDENTITY_KEYS: [&str; 2] = ["name", "path"];
+
+/// Arrays whose order matters, such as inherited profiles which are resolved in order
+const ORDERED_KEYS: [&str; 1] = ["parent"];
+
+/// Content of a value regardless of formatting
+fn canonical_value(value: &Value) -> String {
+    match value {
+        Value::String(string) => format!("{:?}", string.value()),
+        Value::Array(array) => format!(
+            "[{}]",
+            array
+                .iter()
+                .map(canonical_value)
+                .collect::<Vec<_>>()
+                .join(",")
+        ),
+        Value::InlineTable(table) => {
+            let mut pairs: Vec<_> = table
+                .iter()
+                .map(|(key, value)| format!("{:?}={}", key, canonical_value(value)))
+                .collect();
+            pairs.sort();
+            format!("{{{}}}", pairs.join(","))
+        }
+        _ => value.to_string().trim().to_string(),
+    }
+}
+
+/// Content of an item regardless of formatting
+fn canonical(item: &Item) -> String {
+    match item {
+        Item::None => String::new(),
+        Item::Value(value) => canonical_value(value),
+        Item::Table(table) => {
+            let mut pairs: Vec<_> = table
+                .iter()
+                .map(|(key, item)| format!("{:?}={}", key, canonical(item)))
+                .collect();
+            pairs.sort();
+            format!("{{{}}}", pairs.join(","))
+        }
+        Item::ArrayOfTables(array) => format!(
+            "[{}]",
+            array
+                .iter()
+                .map(|table| canonical(&Item::Table(table.clone())))
+                .collect::<Vec<_>>()
+                .join(",")
+        ),
+    }
+}
+
+fn same(a: Option<&Item>, b: Option<&Item>) -> bool {
+    a.map(canonical) == b.map(canonical)
+}
+
+fn child_key(key: &str, child: &str) -> String {
+    if key.is_empty() {
+        child.to_string()
+    } else {
+        format!("{}.{}", key, child)
+    }
+}
+
+fn merge_table(
+    key: &str,
+    base: Option<&Table>,
+    ours: &Table,
+    theirs: &Table,
+    conflicts: &mut Vec<String>,
+) -> Table {
+    let mut merged = ours.clone();
+    let keys: Vec<String> = ours
+        .iter()
+        .map(|(key, _)| key)
+        .chain(
+            theirs
+                .iter()
+                .map(|(key, _)| key)
+                .filter(|key| !ours.contains_key(key)),
+        )
+        .map(String::from)
+        .collect();
+    for child in keys {
+        let item = merge_item(
+            &child_key(key, &child),
+            base.and_then(|base| base.get(&child)),
+            ours.get(&child),
+            theirs.get(&child),
+            conflicts,
+        );
+        match item {
+            Some(item) => merged.insert(&child, item),
+            None => merged.remove(&child),
+        };
+    }
+    merged
+}
+
+/// The key identifying every table in arrays
+fn identity_key(arrays: &[Option<&ArrayOfTables>]) -> Option<&'static str> {
+    IDENTITY_KEYS.into_iter().find(|id| {
+        arrays
+            .iter()
+            .flatten()
+            .flat_map(|array| array.iter())
+            .all(|table| table.get(id).and_then(Item::as_str).is_some())
+    })
+}
+
+/// Merge arrays of tables as maps from the identity key to table
+fn merge_keyed_tables(
+    key: &str,
+    id: &str,
+    base: Option<&ArrayOfTables>,
+    ours: &ArrayOfTables,
+    theirs: &ArrayOfTables,
+    conflicts: &mut Vec<String>,
+) -> ArrayOfTables {
+    let id_of = |table: &Table| table.get(id).and_then(Item::as_str).unwrap().to_string();
+    let find = |array: Option<&ArrayOfTables>, value: &str| {
+        array
+            .and_then(|array| array.iter().find(|table| id_of(table) == value))
+            .map(|table| Item::Table(table.clone()))
+    };
+    let mut ids: Vec<String> = ours.iter().map(id_of).collect();
+    for value in theirs.iter().map(id_of) {
+        if !ids.contains(&value) {
+            ids.push(value);
+        }
+    }
+    let mut merged = ArrayOfTables::new();
+    for value in ids {
+        let item = merge_item(
+            &format!("{}[{}]", key, value),
+            find(base, &value).as_ref(),
+            find(Some(ours), &value).as_ref(),
+            find(Some(theirs), &value).as_ref(),
+            conflicts,
+        );
+        if let Some(Item::Table(table)) = item {
+            merged.push(table);
+        }
+    }
+    merged
+}
+
+/// Merge arrays as sets, values added or removed by either side are kept
+fn merge_set(base: Option<&Array>, ours: &Array, theirs: &Array) -> Array {
+    let contains = |array: Option<&Array>, value: &Value| {
+        array.is_some_and(|array| {
+            array
+                .iter()
+                .any(|item| canonical_value(item) == canonical_value(value))
+        })
+    };
+    let mut merged = Array::new();
+    for value in ours.iter() {
+        // Skip values removed by theirs
+        if !contains(base, value) || contains(Some(theirs), value) {
+            merged.push_formatted(value.clone());
+        }
+    }
+    for value in theirs.iter() {
+        if !contains(base, value) && !contains(Some(ours), value) {
+            merged.push_formatted(value.clone());
+        }
+    }
+    merged.fmt();
+    merged
+}
+
+/// Merge arrays as sequences line by line, so that the order of both sides is kept
+fn merge_sequence(
+    key: &str,
+    base: Option<&Array>,
+    ours: &Array,
+    theirs: &Array,
+    conflicts: &mut Vec<String>,
+) -> Array {
+    let lines = |array: Option<&Array>| -> String {
+        array
+            .into_iter()
+            .flat_map(|array| array.iter())
+            .map(|value| format!("{}\n", canonical_value(value)))
+            .collect()
+    };
+    let merged = merge(&lines(base), &lines(Some(ours)), &lines(Some(theirs)));
+    if !merged.is_clean() {
+        conflicts.push(key.to_string());
+        return ours.clone();
+    }
+    let mut array = Array::new();
+    for line in merged.text.lines() {
+        // Every line comes from either side
+        let value = ours
+            .iter()
+            .chain(theirs.iter())
+            .find(|value| canonical_value(value) == line)
+            .unwrap();
+        array.push_formatted(value.clone());
+    }
+    array.fmt();
+    array
+}
+
+fn merge_item(
+    key: &str,
+    base: Option<&Item>,
+    ours: Option<&Item>,
+    theirs: Option<&Item>,
+    conflicts: &mut Vec<String>,
+) -> Option<Item> {
+    if same(ours, theirs) || same(base, theirs) {
+        return ours.cloned();
+    }
+    if same(base, ours) {
+        return theirs.cloned();
+    }
+    match (ours, theirs) {
+        (Some(Item::Table(ours)), Some(Item::Table(theirs))) => Some(Item::Table(merge_table(
+            key,
+            base.and_then(Item::as_table),
+            ours,
+            theirs,
+            conflicts,
+        ))),
+        (Some(Item::ArrayOfTables(ours)), Some(Item::ArrayOfTables(theirs))) => {
+            let base = base.and_then(Item::as_array_of_tables);
+            match identity_key(&[base, Some(ours), Some(theirs)]) {
+                Some(id) => Some(Item::ArrayOfTables(merge_keyed_tables(
+                    key, id, base, ours, theirs, conflicts,
+                ))),
+                None => {
+                    conflicts.push(key.to_string());
+                    Some(Item::ArrayOfTables(ours.clone()))
+                }
+            }
+        }
+        (Some(Item::Value(Value::Array(ours))), Some(Item::Value(Value::Array(theirs)))) => {
+            let base = base.and_then(Item::as_array);
+            let name = key.rsplit('.').next().unwrap();
+            Some(Item::Value(Value::Array(if ORDERED_KEYS.contains(&name) {
+                merge_sequence(key, base, ours, theirs, conflicts)
+            } else {
+                merge_set(base, ours, theirs)
+            })))
+        }
+        _ => {
+            conflicts.push(key.to_string());
+            ours.cloned()
+        }
+    }
+}
+
+/// Merge changes from `base` to `ours` and from `base` to `theirs` key by key,
+/// `None` means the document does not exist
+///
+/// Tables in an array of tables are matched by their `name` or `path`, and
+/// arrays of values are merged as sets, so that adding different entries
+/// or groups on each side is not a conflict. Arrays in [`ORDERED_KEYS`] are
+/// merged as sequences instead.
+pub(super) fn merge_document(
+    base: Option<&str>,
+    ours: Option<&str>,
+    theirs: Option<&str>,
+) -> Result<MergedDocument> {
+    let parse = |text: Option<&str>| -> Result<Option<Item>> {
+        match text {
+            Some(text) => {
+                let document = text.parse::<Document>().into_diagnostic()?;
+                Ok(Some(Item::Table(document.as_table().clone())))
+            }
+            None => Ok(None),
+        }
+    };
+    let (base, ours, theirs) = (parse(base)?, parse(ours)?, parse(theirs)?);
+    let mut conflicts = vec![];
+    let merged = merge_item(
+        "",
+        base.as_ref(),
+        ours.as_ref(),
+        theirs.as_ref(),
+        &mut conflicts,
+    );
+    let text = merged
+        .and_then(|item| item.into_table().ok())
+        .map(|table| Document::from(table).to_string());
+    Ok(MergedDocument { text, conflicts })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn merge_toml(base: &str, ours: &str, theirs: &str) -> MergedDocument {
+        merge_document(Some(base), Some(ours), Some(theirs)).unwrap()
+    }
+
+    #[test]
+    fn merge_text() {
+        let merged = merge("a\nb\nc\n", "A\nb\nc\n", "a\nb\nC\n");
+        assert!(merged.is_clean());
+        assert_eq!(merged.text, "A\nb\nC\n");
+
+        let merged = merge("a\n", "b\n", "c\n");
+        assert_eq!(merged.conflicts, 1);
+        assert!(has_conflict_markers(merged.text.as_bytes()));
+    }
+
+    #[test]
+    fn merge_groups_as_set() {
+        let merged = merge_toml(
+            "group = [\"a\", \"b\"]\n",
+            "group = [\"a\", \"b\", \"c\"]\n",
+            "group = [\"b\", \"d\"]\n",
+        );
+        assert!(merged.conflicts.is_empty());
+        assert_eq!(merged.text.unwrap(), "group = [\"b\", \"c\", \"d\"]\n");
+    }
+
+    #[test]
+    fn merge_tables_by_identity() {
+        let base = "[[files]]\npath = \"a\"\ncompress = false\n";
+        let ours = "[[files]]\npath = \"a\"\ncompress = true\n";
+        let theirs = "[[files]]\npath = \"a\"\ncompress = false\n\n[[files]]\npath = \"b\"\n";
+        let merged = merge_toml(base, ours, theirs);
+        assert!(merged.conflicts.is_empty());
+        let text = merged.text.unwrap();
+        assert!(text.contains("compress = true"));
+        assert!(text.contains("path = \"b\""));
+
+        let theirs = "[[files]]\npath = \"a\"\ncompress = \"zstd\"\n";
+        let merged = merge_toml(base, ours, theirs);
+        assert_eq!(merged.conflicts, vec!["files[a].compress"]);
+    }
+
+    #[test]
+    fn merge_parent_in_order() {
+        let merged = merge_toml(
+            "[p]\nparent = [\"a\", \"b\"]\n",
+            "[p]\nparent = [\"b\", \"a\"]\n",
+            "[p]\nparent = [\"a\", \"b\", \"c\"]\n",
+        );
+        assert!(merged.conflicts.is_empty());
+        assert_eq!(
+            merged.text.unwrap(),
+            "[p]\nparent = [\"b\", \"a\", \"c\"]\n"
+        );
+
+        let merged = merge_toml(
+            "[p]\nparent = [\"a\"]\n",
+            "[p]\nparent = [\"b\", \"a\"]\n",
+            "[p]\nparent = [\"c\", \"a\"]\n",
+        );
+        assert_eq!(merged.conflicts, vec!["p.parent"]);
+    }
+}
@@ -0,0 +1,311 @@
+use std::collections::HashMap;
+
+use miette::{Context, IntoDiagnostic, Result};
+use rust_i18n::t;
+
+use crate::{
+    config,
+    error::DMError,
+    ui::{MsgLevel, Ui},
+};
+
+use super::{
+    db::{self, MirrorRow},
+    journal::Journal,
+    lock::DepositoryLock,
+    state,
+    storage::{
+        self, local::LocalStorage, webdav::WebDavStorage, Storage, StorageStat, WriteCondition,
+    },
+    Transaction,
+};
+
+/// Whether `path` relative to app data directory is mirrored, which are the
+/// global configuration and the depository
+fn is_mirrored(path: &str) -> bool {
+    path == "dm.toml" || path.starts_with("depository/")
+}
+
+/// Mirrored files on this machine, as paths relative to app data directory
+async fn local_files(local: &LocalStorage) -> Result<Vec<String>> {
+    let mut files = vec![];
+    if local.stat("dm.toml").await?.is_some() {
+        files.push("dm.toml".to_string());
+    }
+    files.extend(
+        local
+            .list("depository")
+            .await?
+            .into_iter()
+            .map(|(path, _)| path),
+    );
+    Ok(files)
+}
+
+/// Directories containing `path`, the deepest first
+fn parent_dirs(path: &str) -> impl Iterator<Item = &str> {
+    path.rmatch_indices('/')
+        .map(move |(index, _)| &path[..index])
+}
+
+/// Hash of local file `path`, `None` if it does not exist
+async fn local_hash(local: &LocalStorage, path: &str) -> Result<Option<String>> {
+    if local.stat(path).await?.is_none() {
+        return Ok(None);
+    }
+    Ok(Some(state::hash_bytes(&local.read(path).await?)))
+}
+
+/// Whether remote file is not modified since it is mirrored last time
+///
+/// A file without version is always taken as modified.
+fn is_remote_untouched(record: Option<&MirrorRow>, stat: Option<&StorageStat>) -> bool {
+    match (record, stat) {
+        (Some(record), Some(stat)) => record.etag.is_some() && record.etag == stat.version,
+        (None, None) => true,
+        _ => false,
+    }
+}
+
+/// Storage configured in `webdav` of configuration, and its key in mirror state
+async fn open_storage() -> Result<(String, WebDavStorage)> {
+    match config::CONFIG.lock().await.webdav.clone() {
+        Some(webdav) => Ok((webdav.url.clone(), WebDavStorage::new(&webdav)?)),
+        None => Err(DMError::StorageError {
+            msg: t!("error.storage.no_config.msg"),
+            advice: Some(t!("error.storage.no_config.advice")),
+        })
+        .into_diagnostic(),
+    }
+}
+
+/// Remote files in mirrored paths and the recorded state of them
+async fn remote_state(
+    remote: &str,
+    storage: &dyn Storage,
+) -> Result<(HashMap<String, MirrorRow>, HashMap<String, StorageStat>)> {
+    let records = db::query_mirror_states(remote)?
+        .into_iter()
+        .map(|row| (row.path.clone(), row))
+        .collect();
+    let stats = storage
+        .list("")
+        .await?
+        .into_iter()
+        .filter(|(path, _)| is_mirrored(path))
+        .collect();
+    Ok((records, stats))
+}
+
+fn check_conflicts(conflicts: Vec<String>, advice: String) -> Result<()> {
+    if !conflicts.is_empty() {
+        Err(DMError::StorageError {
+            msg: t!(
+                "error.storage.conflict.msg",
+                count = &conflicts.len().to_string(),
+                conflicts = &conflicts.join("\n")
+            ),
+            advice: Some(advice),
+        })
+        .into_diagnostic()?;
+    }
+    Ok(())
+}
+
+/// Upload files changed since last mirroring to the WebDAV collection in
+/// configuration, and delete files removed locally
+///
+/// Remote files changed by others are conflicts, nothing is uploaded if any
+/// of them is found unless `force` is set. Files only exist in remote are
+/// kept, run `dm mirror pull` to download them.
+pub async fn push(ui_handle: &dyn Ui, force: bool) -> Result<()> {
+    let (remote, storage) = open_storage().await?;
+    let _lock = DepositoryLock::acquire()?;
+    Journal::recover().wrap_err(t!("error.ctx.transcation.recover"))?;
+    let (records, stats) = remote_state(&remote, &storage).await?;
+    let local = storage::depository()?;
+
+    let mut conflicts = vec![];
+    let mut uploads = vec![];
+    let mut deletes = vec![];
+    let mut unchanged = vec![];
+    let files = local_files(&local).await?;
+    for path in &files {
+        let data = local.read(path).await?;
+        let hash = state::hash_bytes(&data);
+        let (record, stat) = (records.get(path), stats.get(path));
+        if is_remote_untouched(record, stat) {
+            if record.is_none_or(|record| record.hash != hash) {
+                uploads.push((path, data, hash));
+            }
+            continue;
+        }
+        match stat {
+            Some(stat) if state::hash_bytes(&storage.read(path).await?) == hash => {
+                unchanged.push(MirrorRow {
+                    path: path.clone(),
+                    hash,
+                    etag: stat.version.clone(),
+                });
+            }
+            _ if force => uploads.push((path, data, hash)),
+            _ => conflicts.push(path.clone()),
+        }
+    }
+    for (path, record) in &records {
+        if files.contains(path) {
+            continue;
+        }
+        let stat = stats.get(path);
+        if stat.is_none() || is_remote_untouched(Some(record), stat) || force {
+            deletes.push(path);
+        } else {
+            conflicts.push(path.clone());
+        }
+    }
+    conflicts.sort();
+    check_conflicts(conflicts, t!("error.storage.conflict.advice_push"))?;
+
+    for row in unchanged {
+        db::update_mirror_state(&remote, &row)?;
+    }
+    for (path, data, hash) in &uploads {
+        // Remote file must be the one checked above, unless it is overwritten by force
+        let condition = match stats.get(*path) {
+            _ if force => WriteCondition::Always,
+            Some(stat) => stat
+                .version
+                .as_deref()
+                .map_or(WriteCondition::Always, WriteCondition::Version),
+            None => WriteCondition::Missing,
+        };
+        let etag = storage.write_if(path, data, condition).await?;
+        let row = MirrorRow {
+            path: path.to_string(),
+            hash: hash.clone(),
+            etag,
+        };
+        db::update_mirror_state(&remote, &row)?;
+    }
+    for path in &deletes {
+        storage.delete(path).await?;
+        db::delete_mirror_state(&remote, path)?;
+    }
+    // Directories left empty are deleted as well, such as the directory of a deleted group
+    let remaining: Vec<&String> = files.iter().chain(stats.keys()).collect();
+    let mut empty_dirs: Vec<&str> = deletes
+        .iter()
+        .flat_map(|path| parent_dirs(path))
+        .filter(|dir| {
+            let prefix = format!("{}/", dir);
+            *dir != "depository"
+                && !remaining
+                    .iter()
+                    .any(|path| path.starts_with(&prefix) && !deletes.contains(path))
+        })
+        .collect();
+    empty_dirs.sort();
+    empty_dirs.dedup();
+    empty_dirs.sort_by_key(|dir| std::cmp::Reverse(dir.len()));
+    for dir in empty_dirs {
+        storage.delete(dir).await?;
+    }
+    ui_handle.msg(
+        MsgLevel::Info,
+        t!(
+            "mirror.push.pushed",
+            uploaded = &uploads.len().to_string(),
+            deleted = &deletes.len().to_string(),
+            url = &remote
+        ),
+    );
+    Ok(())
+}
+
+/// Download files changed since last mirroring from the WebDAV collection in
+/// configuration, and delete files removed remotely
+///
+/// Local files changed since last mirroring are conflicts, nothing is
+/// downloaded if any of them is found unless `force` is set. Pulling is a
+/// transaction, so it could be undone by `dm undo`.
+pub async fn pull(ui_handle: &dyn Ui, force: bool) -> Result<()> {
+    let (remote, storage) = open_storage().await?;
+    let mut transaction = Transaction::start()
+        .await
+        .wrap_err(t!("error.ctx.transcation.init"))?;
+    let (records, stats) = remote_state(&remote, &storage).await?;
+    let local = storage::depository()?;
+
+    let mut conflicts = vec![];
+    let mut downloads = vec![];
+    let mut deletes = vec![];
+    let mut unchanged = vec![];
+    for (path, stat) in &stats {
+        let record = records.get(path);
+        if is_remote_untouched(record, Some(stat)) {
+            continue;
+        }
+        let data = storage.read(path).await?;
+        let row = MirrorRow {
+            path: path.clone(),
+            hash: state::hash_bytes(&data),
+            etag: stat.version.clone(),
+        };
+        let hash = local_hash(&local, path).await?;
+        if hash.as_ref() == Some(&row.hash) {
+            unchanged.push(row);
+        } else if force || record.map(|record| &record.hash) == hash.as_ref() {
+            downloads.push((row, data));
+        } else {
+            conflicts.push(path.clone());
+        }
+    }
+    for (path, record) in &records {
+        if stats.contains_key(path) {
+            continue;
+        }
+        match local_hash(&local, path).await? {
+            Some(hash) if hash != record.hash && !force => conflicts.push(path.clone()),
+            _ => deletes.push(path),
+        }
+    }
+    conflicts.sort();
+    check_conflicts(conflicts, t!("error.storage.conflict.advice_pull"))?;
+
+    for (row, data) in &downloads {
+        transaction.protect(&local.path(&row.path))?;
+        local.write(&row.path, data).await?;
+    }
+    for path in &deletes {
+        transaction.protect(&local.path(path))?;
+        local.delete(path).await?;
+        for dir in parent_dirs(path).take_while(|dir| *dir != "depository") {
+            // Fails if the directory is not empty
+            if std::fs::remove_dir(local.path(dir)).is_err() {
+                break;
+            }
+        }
+    }
+    transaction.reload().await?;
+    transaction
+        .commit()
+        .wrap_err(t!("error.ctx.transcation.commit"))?;
+
+    // Recorded after commit, so that rolled back files are never taken as mirrored
+    for row in unchanged.iter().chain(downloads.iter().map(|(row, _)| row)) {
+        db::update_mirror_state(&remote, row)?;
+    }
+    for path in &deletes {
+        db::delete_mirror_state(&remote, path)?;
+    }
+    ui_handle.msg(
+        MsgLevel::Info,
+        t!(
+            "mirror.pull.pulled",
+            downloaded = &downloads.len().to_string(),
+            deleted = &deletes.len().to_string(),
+            url = &remote
+        ),
+    );
+    Ok(())
+}
@@ -5,68 +5,118 @@ use rust_i18n::t;
 use serde::de::Visitor;
 use serde::{Deserialize, Serialize};
 use std::cell::{Ref, RefCell, RefMut};
-use std::{collections::HashMap, path::PathBuf};
-
-use crate::env::get_app_data_dir;
-use crate::env::get_group_dir;
+use std::fmt::Display;
+use std::rc::Rc;
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+};
+
+use crate::config::DMHistoryConfiguration;
+use crate::env::get_hostname;
 use crate::env::SpecDir;
 use crate::error::DMError;
 use crate::error::GroupErrorKind;
+use crate::error::ProfileErrorKind;
+
+use self::journal::Journal;
+use self::lock::DepositoryLock;
+use self::storage::Storage;
 
 pub mod profile;
 pub mod file;
 pub mod group;
 pub mod db;
+pub mod status;
+pub mod diff;
+pub mod lock;
+pub mod history;
+pub mod backup;
+pub mod git;
+pub mod sync;
+pub mod storage;
+pub mod mirror;
 mod updater;
-
+mod crypto;
+mod merge;
+mod template;
+mod journal;
+mod state;
+
+/// Changes of depository, applied at once when commit
+///
+/// Manifests and group directories are read from the storage of depository.
+/// Changes are journaled, which needs the storage on local file system.
 struct Transaction {
+    storage: Rc<dyn Storage>,
     group: RefCell<HashMap<String, TomlGroup>>,
     global: TomlGlobal,
+    /// Group directories to be renamed when commit, as `(old, new)`
+    renamed_group: Vec<(String, String)>,
+    /// Group directories to be deleted when commit
+    deleted_group: Vec<String>,
+    journal: RefCell<Journal>,
+    /// Latest backup before transaction starts, backups after it are created by transaction
+    last_backup: i64,
+    /// Retention policy of history, read when transaction starts
+    history: DMHistoryConfiguration,
+    /// Released after the journal is finished in `drop`
+    _lock: DepositoryLock,
+    /// Started by `start_shared`, which must not change depository
+    shared: bool,
 }
 
 impl Transaction {
-    fn lock() -> Result<()> {
-        let lock_file = get_app_data_dir()?.join(".lock");
-        if lock_file.exists() {
-            Err(DMError::LockError {
-                msg: t!("error.transcation.lock.msg"),
-                advice: t!(
-                    "error.transcation.lock.advice",
-                    lock = lock_file.to_str().unwrap()
-                ),
-            }
-            .into())
-        } else {
-            std::fs::write(lock_file, t!("lock.content")).into_diagnostic()?;
-            Ok(())
-        }
+    pub async fn start() -> Result<Self> {
+        let lock = DepositoryLock::acquire()?;
+        Journal::recover().wrap_err(t!("error.ctx.transcation.recover"))?;
+        Self::with_lock(lock, false).await
     }
 
-    fn unlock() -> Result<()> {
-        let lock_file = get_app_data_dir()?.join(".lock");
-        if lock_file.exists() {
-            std::fs::remove_file(lock_file).into_diagnostic()?;
+    /// Start a transaction which only reads depository, so that it could run
+    /// along with other readers. It is never committed.
+    ///
+    /// If an interrupted transaction is left, it is recovered by a normal
+    /// transaction instead.
+    pub async fn start_shared() -> Result<Self> {
+        let lock = DepositoryLock::acquire_shared()?;
+        if Journal::is_pending()? {
+            drop(lock);
+            return Self::start().await;
         }
-        Ok(())
+        Self::with_lock(lock, true).await
     }
 
-    pub fn start() -> Result<Self> {
-        Self::lock()?;
-        let global_toml_path = get_global_toml_path()?;
-        let global = if !global_toml_path.exists() {
-            TomlGlobal::default()
-        } else {
-            let toml = std::fs::read_to_string(global_toml_path).into_diagnostic()?;
-            toml_edit::de::from_str(&toml)
-                .into_diagnostic()
-                .wrap_err(t!("error.ctx.serde.deserializing"))?
-        };
+    async fn with_lock(lock: DepositoryLock, shared: bool) -> Result<Self> {
+        let storage: Rc<dyn Storage> = Rc::new(storage::depository()?);
+        let global = TomlGlobal::load(&*storage).await?;
         Ok(Self {
+            storage,
             group: RefCell::new(HashMap::new()),
             global,
+            renamed_group: vec![],
+            deleted_group: vec![],
+            journal: RefCell::new(Journal::new()?),
+            last_backup: db::query_last_backup_id()?,
+            history: crate::config::CONFIG.lock().await.history.clone(),
+            _lock: lock,
+            shared,
         })
     }
 
+    /// Storage of depository, where stored files of entries are kept
+    pub fn storage(&self) -> &Rc<dyn Storage> {
+        &self.storage
+    }
+
+    /// Location of `path` in storage on local file system
+    fn local_path(&self, path: &str) -> Result<PathBuf> {
+        self.storage
+            .local_path(path)
+            .ok_or_else(|| storage::not_local(path))
+            .into_diagnostic()
+    }
+
     pub fn global(&self) -> &TomlGlobal {
         &self.global
     }
@@ -75,40 +125,64 @@ impl Transaction {
         &mut self.global
     }
 
-    fn load_group_toml(&self, name: String) -> Result<()> {
-        let dir = get_group_dir(&name)?;
-        if !dir.exists() {
-            self.group
-                .borrow_mut()
-                .insert(name.clone(), TomlGroup::new(name));
-        } else {
-            let file = dir.join("manifest.toml");
-            if file.exists() {
-                let ins = toml_edit::de::from_str::<TomlGroup>(
-                    &std::fs::read_to_string(file).into_diagnostic()?,
-                )
+    async fn load_group_toml(&self, name: String) -> Result<()> {
+        let file = storage::group_path(&name, "manifest.toml");
+        let mut group = if self.storage.stat(&file).await?.is_some() {
+            let data = self.storage.read(&file).await?;
+            toml_edit::de::from_str::<TomlGroup>(&String::from_utf8_lossy(&data))
                 .into_diagnostic()
-                .wrap_err(t!("error.ctx.serde.deserializing"))?;
-                self.group.borrow_mut().insert(name, ins);
-            } else {
-                self.group
-                    .borrow_mut()
-                    .insert(name.clone(), TomlGroup::new(name));
-            }
+                .wrap_err(t!("error.ctx.serde.deserializing"))?
+        } else {
+            TomlGroup::new(name.clone())
+        };
+        for entry in group.files.iter_mut() {
+            self.migrate_entry_path(&name, entry).await?;
         }
+        self.group.borrow_mut().insert(name, group);
         Ok(())
     }
 
-    pub fn group(&self, name: &str) -> Result<Ref<TomlGroup>> {
+    /// Move entry added with an absolute depository path into `ROOT/`
+    ///
+    /// Unix paths used to be recorded as they are, so their stored files were
+    /// mixed up with the group directory. The stored file is moved at once
+    /// and restored if the transaction is not committed, so it can't be done
+    /// by a shared transaction.
+    async fn migrate_entry_path(&self, group_name: &str, entry: &mut TomlItemEntry) -> Result<()> {
+        let path = match entry.path.strip_prefix('/') {
+            Some(path) => path.to_string(),
+            None => return Ok(()),
+        };
+        if self.shared {
+            return Err(DMError::GroupError {
+                kind: GroupErrorKind::OldLayout,
+                msg: t!("error.group.old_layout.msg", name = group_name),
+                advice: Some(t!("error.group.old_layout.advice")),
+            })
+            .into_diagnostic();
+        }
+        let new_path = format!("ROOT/{}", path);
+        let from = storage::group_path(group_name, &path);
+        let to = storage::group_path(group_name, &new_path);
+        if self.storage.stat(&from).await?.is_some() {
+            self.protect(&self.local_path(&from)?)?;
+            self.protect(&self.local_path(&to)?)?;
+            self.storage.rename(&from, &to).await?;
+        }
+        entry.path = new_path;
+        Ok(())
+    }
+
+    pub async fn group(&self, name: &str) -> Result<Ref<'_, TomlGroup>> {
         if !self.group.borrow().contains_key(name) {
-            self.load_group_toml(name.to_string())?;
+            self.load_group_toml(name.to_string()).await?;
         }
         let borrow = self.group.borrow();
 
         if !borrow.contains_key(name) {
             return Err(DMError::GroupError {
                 kind: GroupErrorKind::NotExists,
-                msg: t!("error.group.not_exists"),
+                msg: t!("error.group.not_exists.msg", name = name),
                 advice: None,
             })
             .into_diagnostic();
@@ -117,16 +191,16 @@ impl Transaction {
         Ok(r)
     }
 
-    pub fn group_mut(&mut self, name: &str) -> Result<RefMut<TomlGroup>> {
+    pub async fn group_mut(&mut self, name: &str) -> Result<RefMut<'_, TomlGroup>> {
         if !self.group.borrow().contains_key(name) {
-            self.load_group_toml(name.to_string())?;
+            self.load_group_toml(name.to_string()).await?;
         }
         let borrow = self.group.borrow_mut();
 
         if !borrow.contains_key(name) {
             return Err(DMError::GroupError {
                 kind: GroupErrorKind::NotExists,
-                msg: t!("error.group.not_exists"),
+                msg: t!("error.group.not_exists.msg", name = name),
                 advice: None,
             })
             .into_diagnostic();
@@ -135,7 +209,7 @@ impl Transaction {
         Ok(r)
     }
 
-    pub fn create_group(&mut self, name: &str) -> Result<RefMut<TomlGroup>> {
+    pub fn create_group(&mut self, name: &str) -> Result<RefMut<'_, TomlGroup>> {
         let mut borrow = self.group.borrow_mut();
         if borrow.contains_key(name) || self.global.registery.group.contains(&name.to_string()) {
             Err(DMError::GroupError {
@@ -151,43 +225,247 @@ impl Transaction {
         Ok(RefMut::map(borrow, |map| map.get_mut(name).unwrap()))
     }
 
-    pub fn commit(mut self) -> Result<()> {
-        let global_toml_path = get_global_toml_path()?;
+    fn check_group_exists(&self, name: &str) -> Result<()> {
+        if !self.global.registery.group.contains(&name.to_string()) {
+            Err(DMError::GroupError {
+                kind: GroupErrorKind::NotExists,
+                msg: t!("error.group.not_exists.msg", name = name),
+                advice: None,
+            })
+            .into_diagnostic()?;
+        }
+        Ok(())
+    }
+
+    /// Remove group from registery and all profiles, its directory is deleted when commit
+    pub fn delete_group(&mut self, name: &str) -> Result<()> {
+        self.check_group_exists(name)?;
+        self.global.registery.group.retain(|group| group != name);
+        for profile in self.global.registery.profile.iter_mut() {
+            profile.group.retain(|group| group != name);
+        }
+        self.group.borrow_mut().remove(name);
+        self.deleted_group.push(name.to_string());
+        Ok(())
+    }
+
+    /// Rename group in registery and all profiles, its directory is moved when commit
+    pub async fn rename_group(&mut self, name: &str, new_name: &str) -> Result<()> {
+        self.check_group_exists(name)?;
+        if self.global.registery.group.contains(&new_name.to_string()) {
+            Err(DMError::GroupError {
+                kind: GroupErrorKind::DuplicateCreate,
+                msg: t!("error.group.duplicate.msg", name = new_name),
+                advice: None,
+            })
+            .into_diagnostic()?;
+        }
+        let mut group = self.group(name).await?.clone();
+        group.name = new_name.to_string();
+        let rename = |list: &mut Vec<String>| {
+            for item in list.iter_mut().filter(|item| *item == name) {
+                *item = new_name.to_string();
+            }
+        };
+        rename(&mut self.global.registery.group);
+        for profile in self.global.registery.profile.iter_mut() {
+            rename(&mut profile.group);
+        }
+        let mut borrow = self.group.borrow_mut();
+        borrow.remove(name);
+        borrow.insert(new_name.to_string(), group);
+        self.renamed_group
+            .push((name.to_string(), new_name.to_string()));
+        Ok(())
+    }
+
+    /// Keep the original content of `path` in depository before modifying it,
+    /// it is restored if the transaction is not committed
+    pub fn protect(&self, path: &Path) -> Result<()> {
+        self.journal.borrow_mut().protect(path)
+    }
+
+    /// Discard loaded state and read it again from depository
+    async fn reload(&mut self) -> Result<()> {
+        self.global = TomlGlobal::load(&*self.storage).await?;
+        self.group.get_mut().clear();
+        self.renamed_group.clear();
+        self.deleted_group.clear();
+        Ok(())
+    }
+
+    pub fn commit(self) -> Result<()> {
+        let mut journal = self.journal.borrow_mut();
+        let mut changes = history::Changes {
+            backups: backup::backups_after(self.last_backup)?,
+            ..Default::default()
+        };
+        // Move group directories before writing manifest into them
+        for (name, new_name) in &self.renamed_group {
+            let from = self.local_path(&storage::group_dir(name))?;
+            let to = self.local_path(&storage::group_dir(new_name))?;
+            journal.stage_rename(&from, &to);
+            changes.renamed.push((from, to));
+            state::stage_rename_group(&mut journal, name, new_name)?;
+        }
         // Save global configuration
-        std::fs::write(
-            global_toml_path,
-            toml_edit::ser::to_string_pretty(&self.global)
-                .into_diagnostic()
-                .wrap_err(t!("error.ctx.serde.serializing"))?,
-        )
-        .into_diagnostic()?;
+        let global_toml_path = self.local_path(storage::GLOBAL_PATH)?;
+        let value = toml_edit::ser::to_string_pretty(&self.global)
+            .into_diagnostic()
+            .wrap_err(t!("error.ctx.serde.serializing"))?;
+        journal.stage_write(&global_toml_path, value.as_bytes())?;
+        changes.manifests.push(history::ManifestChange {
+            path: global_toml_path.clone(),
+            original: global_toml_path,
+            content: value,
+        });
         // Save group manifest
         for (name, v) in self.group.borrow().iter() {
             let value = toml_edit::ser::to_string_pretty(v)
                 .into_diagnostic()
                 .wrap_err(t!("error.ctx.serde.serializing"))?;
-            let dir = get_group_dir(name)?;
-            if !dir.exists() {
-                std::fs::create_dir_all(&dir).into_diagnostic()?;
-            }
-            let manifest_path = dir.join("manifest.toml");
-            std::fs::write(manifest_path, value).into_diagnostic()?;
+            let manifest_path = self.local_path(&storage::group_path(name, "manifest.toml"))?;
+            journal.stage_write(&manifest_path, value.as_bytes())?;
+            let original_name = self
+                .renamed_group
+                .iter()
+                .find(|(_, new_name)| new_name == name)
+                .map_or(name, |(name, _)| name);
+            changes.manifests.push(history::ManifestChange {
+                path: manifest_path,
+                original: self.local_path(&storage::group_path(original_name, "manifest.toml"))?,
+                content: value,
+            });
         }
-        Self::unlock()?;
-        Ok(())
+        for name in &self.deleted_group {
+            let dir = self.local_path(&storage::group_dir(name))?;
+            journal.stage_remove(&dir);
+            changes.deleted.push(dir);
+            state::stage_forget_group(&mut journal, name)?;
+        }
+        history::record(&mut journal, changes, &self.history)?;
+        journal.commit()?;
+        // States of entries are keyed by group name
+        for (name, new_name) in &self.renamed_group {
+            state::rename_group(name, new_name)?;
+        }
+        for name in &self.deleted_group {
+            state::forget_group(name)?;
+        }
+        git::commit_transaction()
     }
 }
 
 impl Drop for Transaction {
     fn drop(&mut self) {
-        Self::unlock().unwrap();
+        // Nothing is left if committed. If rolling back fails, the journal
+        // is kept and recovered by next transaction.
+        let _ = self.journal.get_mut().abort();
     }
 }
 
 #[derive(Serialize, Deserialize)]
 struct TomlGlobalProfileEntry {
     name: String,
+    /// Profiles whose groups are inherited
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    parent: Vec<String>,
+    /// Rules to select this profile automatically, any of them matches is enough
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    rule: Vec<TomlProfileRule>,
     group: Vec<String>,
+    /// Remote depository synchronized by `dm sync` when this profile is used
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    remote: Option<TomlProfileRemote>,
+}
+
+/// A git repository which the depository is pushed to and pulled from
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TomlProfileRemote {
+    pub url: String,
+    /// Branch to synchronize, `main` by default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub branch: Option<String>,
+}
+
+impl TomlProfileRemote {
+    pub fn branch(&self) -> &str {
+        self.branch.as_deref().unwrap_or("main")
+    }
+}
+
+impl Display for TomlProfileRemote {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({})", self.url, self.branch())
+    }
+}
+
+/// Rule to match a machine, all given conditions must be satisfied
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct TomlProfileRule {
+    /// Glob pattern of hostname
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hostname: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub os: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub arch: Option<String>,
+    /// Name of environment variable which must be present
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub env: Option<String>,
+}
+
+impl TomlProfileRule {
+    pub fn is_empty(&self) -> bool {
+        self.hostname.is_none() && self.os.is_none() && self.arch.is_none() && self.env.is_none()
+    }
+
+    /// Whether current machine satisfies the rule, an empty rule matches nothing
+    pub fn matches(&self) -> Result<bool> {
+        if self.is_empty() {
+            return Ok(false);
+        }
+        if let Some(hostname) = &self.hostname {
+            let pattern = glob::Pattern::new(hostname)
+                .map_err(|err| DMError::ProfileError {
+                    kind: ProfileErrorKind::IlleagalOperation,
+                    msg: t!(
+                        "error.profile.bad_rule.msg",
+                        pattern = hostname,
+                        err = &err.to_string()
+                    ),
+                    advice: None,
+                })
+                .into_diagnostic()?;
+            if !pattern.matches(&get_hostname()) {
+                return Ok(false);
+            }
+        }
+        Ok(self.os.as_ref().is_none_or(|os| os == std::env::consts::OS)
+            && self
+                .arch
+                .as_ref()
+                .is_none_or(|arch| arch == std::env::consts::ARCH)
+            && self
+                .env
+                .as_ref()
+                .is_none_or(|env| std::env::var_os(env).is_some()))
+    }
+}
+
+impl Display for TomlProfileRule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let conditions: Vec<String> = [
+            ("hostname", &self.hostname),
+            ("os", &self.os),
+            ("arch", &self.arch),
+            ("env", &self.env),
+        ]
+        .iter()
+        .filter_map(|(key, value)| value.as_ref().map(|value| format!("{}={}", key, value)))
+        .collect();
+        write!(f, "{}", conditions.join(", "))
+    }
 }
 
 #[derive(Serialize, Deserialize)]
@@ -201,11 +479,28 @@ struct TomlGlobal {
     registery: TomlGlobalRegistery,
 }
 
+impl TomlGlobal {
+    /// Read global configuration from `storage` without locking depository
+    async fn load(storage: &dyn Storage) -> Result<Self> {
+        if storage.stat(storage::GLOBAL_PATH).await?.is_none() {
+            Ok(TomlGlobal::default())
+        } else {
+            let toml = storage.read(storage::GLOBAL_PATH).await?;
+            toml_edit::de::from_str(&String::from_utf8_lossy(&toml))
+                .into_diagnostic()
+                .wrap_err(t!("error.ctx.serde.deserializing"))
+        }
+    }
+}
+
 impl TomlGlobalProfileEntry {
     pub fn new(name: String) -> Self {
         Self {
             name,
+            parent: vec![],
+            rule: vec![],
             group: vec![],
+            remote: None,
         }
     }
 }
@@ -218,6 +513,7 @@ impl Default for TomlGlobalRegistery {
     }
 }
 
+#[allow(clippy::derivable_impls)]
 impl Default for TomlGlobal {
     fn default() -> Self {
         Self {
@@ -226,10 +522,6 @@ impl Default for TomlGlobal {
     }
 }
 
-fn get_global_toml_path() -> Result<PathBuf> {
-    Ok(get_app_data_dir()?.join("dm.toml"))
-}
-
 #[derive(Debug, Clone)]
 pub enum DMPath {
     Normal(String),
@@ -237,6 +529,7 @@ pub enum DMPath {
 }
 
 impl DMPath {
+    #[allow(clippy::useless_conversion)]
     pub fn parse(&self, env: &SpecDir) -> Result<PathBuf> {
         match self {
             DMPath::Normal(dir) => Ok(PathBuf::from(dir)),
@@ -313,12 +606,8 @@ impl<'de> Visitor<'de> for DMPathVisitor {
         A: serde::de::SeqAccess<'de>,
     {
         let mut data = vec![];
-        loop {
-            if let Some(v) = seq.next_element().unwrap() {
-                data.push(v)
-            } else {
-                break;
-            }
+        while let Some(v) = seq.next_element()? {
+            data.push(v)
         }
         Ok(DMPath::Dynamic(data))
     }
@@ -333,12 +622,21 @@ impl<'de> Deserialize<'de> for DMPath {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum ItemEntryKind {
     File,
     Dir,
 }
-#[derive(Serialize, Deserialize, Debug)]
+/// How the entry is deployed to its install path
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LinkMode {
+    #[default]
+    Copy,
+    Symlink,
+    Hardlink,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct TomlItemEntry {
     /// 标明是 File 还是 Dir
     kind: ItemEntryKind,
@@ -346,20 +644,67 @@ pub struct TomlItemEntry {
     path: String,
     /// 是否使用外部脚本进行同步/安装管理
     manaul: bool,
+    /// 复制文件，或是创建指向仓库的链接
+    #[serde(default)]
+    link: LinkMode,
+    /// 是否在仓库中使用 zstd 压缩存储
+    #[serde(default)]
+    compress: bool,
+    /// 是否在仓库中加密存储
+    #[serde(default)]
+    encrypt: bool,
+    /// 仓库中存储的是模板，安装时按本机环境渲染
+    #[serde(default)]
+    template: bool,
     /// 在不同平台下的安装路径
     install: HashMap<String, DMPath>,
+    /// 在不同平台下手动管理所用的脚本
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    script: HashMap<String, TomlScriptEntry>,
+}
+
+/// Scripts used by manual entry, paths are relative to the group directory
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct TomlScriptEntry {
+    install: Option<String>,
+    update: Option<String>,
+    diff: Option<String>,
+}
+
+impl TomlScriptEntry {
+    pub fn new(install: Option<String>, update: Option<String>, diff: Option<String>) -> Self {
+        Self {
+            install,
+            update,
+            diff,
+        }
+    }
 }
 
 impl TomlItemEntry {
-    pub fn new(kind: ItemEntryKind, path: String, manaul: bool) -> Self {
+    pub fn new(
+        kind: ItemEntryKind,
+        path: String,
+        manaul: bool,
+        link: LinkMode,
+        compress: bool,
+        encrypt: bool,
+        template: bool,
+    ) -> Self {
         Self {
             kind,
             path,
             manaul,
+            link,
+            compress,
+            encrypt,
+            template,
             install: HashMap::new(),
+            script: HashMap::new(),
         }
     }
     /// Get install path in current platform
+    #[allow(clippy::unnecessary_to_owned)]
     pub fn get_platform_install_path(&self) -> Option<&DMPath> {
         self.install.get(&std::env::consts::OS.to_string())
     }
@@ -367,12 +712,23 @@ impl TomlItemEntry {
     pub fn insert_platform_install_path(&mut self, path: DMPath) {
         self.install.insert(std::env::consts::OS.to_string(), path);
     }
+    /// Get manual scripts in current platform
+    pub fn get_platform_script(&self) -> Option<&TomlScriptEntry> {
+        self.script.get(std::env::consts::OS)
+    }
+    /// Set manual scripts in platform `os`, such as `linux` or `windows`
+    pub fn insert_script(&mut self, os: &str, script: TomlScriptEntry) {
+        self.script.insert(os.to_string(), script);
+    }
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 struct TomlGroup {
     name: String,
     description: Option<String>,
+    /// User-defined variables used by template entries
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    variables: BTreeMap<String, String>,
     files: Vec<TomlItemEntry>,
 }
 
@@ -381,6 +737,7 @@ impl TomlGroup {
         Self {
             name,
             description: None,
+            variables: BTreeMap::new(),
             files: vec![],
         }
     }
@@ -3,13 +3,62 @@ use rust_i18n::t;
 
 use crate::{
     config,
-    error::{DMError, ProfileErrorKind}, ui::Ui,
+    error::{DMError, ProfileErrorKind},
+    ui::Ui,
 };
 
-use super::{TomlGlobalProfileEntry, Transaction};
+use super::{
+    storage, TomlGlobal, TomlGlobalProfileEntry, TomlProfileRemote, TomlProfileRule, Transaction,
+};
+
+/// Why the current profile is selected
+#[derive(Debug)]
+pub enum ProfileSource {
+    /// Chosen by `dm profile use`
+    Config,
+    /// Selected by a rule of the profile
+    Rule(TomlProfileRule),
+    /// No profile matches current machine
+    Fallback,
+}
+
+#[derive(Debug)]
+pub struct CurrentProfile {
+    pub name: String,
+    pub source: ProfileSource,
+}
+
+/// Get the profile used on this machine
+///
+/// Profile set in configuration takes priority, then the first profile with
+/// a rule matching this machine, or `default` if nothing matches.
+pub async fn current_profile() -> Result<CurrentProfile> {
+    if let Some(name) = &config::CONFIG.lock().await.using_profile {
+        return Ok(CurrentProfile {
+            name: name.clone(),
+            source: ProfileSource::Config,
+        });
+    }
+    for profile in &TomlGlobal::load(&storage::depository()?).await?.registery.profile {
+        for rule in &profile.rule {
+            if rule.matches()? {
+                return Ok(CurrentProfile {
+                    name: profile.name.clone(),
+                    source: ProfileSource::Rule(rule.clone()),
+                });
+            }
+        }
+    }
+    Ok(CurrentProfile {
+        name: String::from("default"),
+        source: ProfileSource::Fallback,
+    })
+}
 
 pub async fn create_profile(name: String) -> Result<()> {
-    let mut transaction = Transaction::start().wrap_err(t!("error.ctx.transcation.init"))?;
+    let mut transaction = Transaction::start()
+        .await
+        .wrap_err(t!("error.ctx.transcation.init"))?;
 
     let profile_list = &mut transaction.global.registery.profile;
     if profile_list
@@ -29,8 +78,19 @@ pub async fn create_profile(name: String) -> Result<()> {
         .wrap_err(t!("error.ctx.transcation.commit"))?;
     Ok(())
 }
-pub async fn use_profile(name: String) -> Result<()> {
-    let transaction = Transaction::start().wrap_err(t!("error.ctx.transcation.init"))?;
+/// Use profile `name` on this machine, or select profile by rules if `None`
+pub async fn use_profile(name: Option<String>) -> Result<()> {
+    let name = match name {
+        Some(name) => name,
+        None => {
+            let mut config_guard = config::CONFIG.lock().await;
+            config_guard.using_profile = None;
+            return config_guard.save().wrap_err(t!("error.ctx.config.save"));
+        }
+    };
+    let transaction = Transaction::start_shared()
+        .await
+        .wrap_err(t!("error.ctx.transcation.init"))?;
     if transaction
         .global
         .registery
@@ -40,7 +100,7 @@ pub async fn use_profile(name: String) -> Result<()> {
         .is_some()
     {
         let mut config_guard = config::CONFIG.lock().await;
-        config_guard.using_profile = name;
+        config_guard.using_profile = Some(name);
         config_guard.save().wrap_err(t!("error.ctx.config.save"))
     } else {
         Err(DMError::ProfileError {
@@ -62,7 +122,7 @@ pub async fn delete(ui_handle:&dyn Ui, name: String, confirm_all: bool) -> Resul
         })
         .into_diagnostic()?;
     }
-    if config::CONFIG.lock().await.using_profile == name {
+    if current_profile().await?.name == name {
         Err(DMError::ProfileError {
             kind: ProfileErrorKind::IlleagalOperation,
             msg: t!("error.profile.delete_using.msg"),
@@ -70,7 +130,9 @@ pub async fn delete(ui_handle:&dyn Ui, name: String, confirm_all: bool) -> Resul
         })
         .into_diagnostic()?;
     }
-    let mut transaction = Transaction::start().wrap_err(t!("error.ctx.transcation.init"))?;
+    let mut transaction = Transaction::start()
+        .await
+        .wrap_err(t!("error.ctx.transcation.init"))?;
     if let Some(idx) = transaction
         .global
         .registery
@@ -84,6 +146,9 @@ pub async fn delete(ui_handle:&dyn Ui, name: String, confirm_all: bool) -> Resul
             return Ok(());
         }
         transaction.global.registery.profile.remove(idx);
+        for profile in transaction.global.registery.profile.iter_mut() {
+            profile.parent.retain(|parent| parent != &name);
+        }
         transaction
             .commit()
             .wrap_err(t!("error.ctx.transcation.commit"))?;
@@ -97,3 +162,286 @@ pub async fn delete(ui_handle:&dyn Ui, name: String, confirm_all: bool) -> Resul
         .into_diagnostic()
     }
 }
+
+fn profile_mut<'a>(
+    transaction: &'a mut Transaction,
+    name: &str,
+) -> Result<&'a mut TomlGlobalProfileEntry> {
+    transaction
+        .global_mut()
+        .registery
+        .profile
+        .iter_mut()
+        .find(|entry| entry.name == name)
+        .ok_or(DMError::ProfileError {
+            kind: ProfileErrorKind::NotExists,
+            msg: t!("error.profile.not_exists.msg", name = name),
+            advice: None,
+        })
+        .into_diagnostic()
+}
+
+/// Add group to profile
+pub async fn attach_group(name: String, group: String) -> Result<()> {
+    let mut transaction = Transaction::start()
+        .await
+        .wrap_err(t!("error.ctx.transcation.init"))?;
+    transaction.check_group_exists(&group)?;
+    let profile = profile_mut(&mut transaction, &name)?;
+    if profile.group.contains(&group) {
+        Err(DMError::ProfileError {
+            kind: ProfileErrorKind::IlleagalOperation,
+            msg: t!("error.profile.attached.msg", name = &name, group = &group),
+            advice: None,
+        })
+        .into_diagnostic()?;
+    }
+    profile.group.push(group);
+    transaction
+        .commit()
+        .wrap_err(t!("error.ctx.transcation.commit"))
+}
+
+/// Remove group from profile, the group itself is kept
+pub async fn detach_group(name: String, group: String) -> Result<()> {
+    let mut transaction = Transaction::start()
+        .await
+        .wrap_err(t!("error.ctx.transcation.init"))?;
+    let profile = profile_mut(&mut transaction, &name)?;
+    match profile.group.iter().position(|entry| entry == &group) {
+        Some(idx) => {
+            profile.group.remove(idx);
+        }
+        None => Err(DMError::ProfileError {
+            kind: ProfileErrorKind::IlleagalOperation,
+            msg: t!(
+                "error.profile.not_attached.msg",
+                name = &name,
+                group = &group
+            ),
+            advice: None,
+        })
+        .into_diagnostic()?,
+    }
+    transaction
+        .commit()
+        .wrap_err(t!("error.ctx.transcation.commit"))
+}
+
+/// Let profile `name` inherit groups from profile `parent`
+pub async fn inherit(name: String, parent: String) -> Result<()> {
+    let mut transaction = Transaction::start()
+        .await
+        .wrap_err(t!("error.ctx.transcation.init"))?;
+    find_profile(&transaction, &parent)?;
+    let profile = profile_mut(&mut transaction, &name)?;
+    if profile.parent.contains(&parent) {
+        Err(DMError::ProfileError {
+            kind: ProfileErrorKind::IlleagalOperation,
+            msg: t!(
+                "error.profile.inherited.msg",
+                name = &name,
+                parent = &parent
+            ),
+            advice: None,
+        })
+        .into_diagnostic()?;
+    }
+    profile.parent.push(parent);
+    // Refuse to save an inheritance cycle
+    profile_groups(&transaction, &name)?;
+    transaction
+        .commit()
+        .wrap_err(t!("error.ctx.transcation.commit"))
+}
+
+/// Stop inheriting groups from profile `parent`
+pub async fn disinherit(name: String, parent: String) -> Result<()> {
+    let mut transaction = Transaction::start()
+        .await
+        .wrap_err(t!("error.ctx.transcation.init"))?;
+    let profile = profile_mut(&mut transaction, &name)?;
+    match profile.parent.iter().position(|entry| entry == &parent) {
+        Some(idx) => {
+            profile.parent.remove(idx);
+        }
+        None => Err(DMError::ProfileError {
+            kind: ProfileErrorKind::IlleagalOperation,
+            msg: t!(
+                "error.profile.not_inherited.msg",
+                name = &name,
+                parent = &parent
+            ),
+            advice: None,
+        })
+        .into_diagnostic()?,
+    }
+    transaction
+        .commit()
+        .wrap_err(t!("error.ctx.transcation.commit"))
+}
+
+/// Add a rule to select profile `name` automatically, or remove all rules if `rule` is `None`
+pub async fn set_rule(name: String, rule: Option<TomlProfileRule>) -> Result<()> {
+    let mut transaction = Transaction::start()
+        .await
+        .wrap_err(t!("error.ctx.transcation.init"))?;
+    let profile = profile_mut(&mut transaction, &name)?;
+    match rule {
+        Some(rule) => {
+            if rule.is_empty() {
+                Err(DMError::ProfileError {
+                    kind: ProfileErrorKind::IlleagalOperation,
+                    msg: t!("error.profile.empty_rule.msg"),
+                    advice: None,
+                })
+                .into_diagnostic()?;
+            }
+            // Check the hostname pattern before saving
+            rule.matches()?;
+            profile.rule.push(rule);
+        }
+        None => profile.rule.clear(),
+    }
+    transaction
+        .commit()
+        .wrap_err(t!("error.ctx.transcation.commit"))
+}
+
+/// Set the remote synchronized by `dm sync` for profile `name`, or remove it if `remote` is `None`
+pub async fn set_remote(name: String, remote: Option<TomlProfileRemote>) -> Result<()> {
+    let mut transaction = Transaction::start()
+        .await
+        .wrap_err(t!("error.ctx.transcation.init"))?;
+    profile_mut(&mut transaction, &name)?.remote = remote;
+    transaction
+        .commit()
+        .wrap_err(t!("error.ctx.transcation.commit"))
+}
+
+/// Remote of the profile used on this machine
+pub(super) async fn current_remote() -> Result<Option<TomlProfileRemote>> {
+    let name = current_profile().await?.name;
+    Ok(TomlGlobal::load(&storage::depository()?).await?
+        .registery
+        .profile
+        .into_iter()
+        .find(|entry| entry.name == name)
+        .and_then(|entry| entry.remote))
+}
+
+#[derive(Debug)]
+pub struct ProfileGroupSummary {
+    pub name: String,
+    /// Profile which the group is attached to, differs from the shown one if inherited
+    pub origin: String,
+    /// Count of files in group
+    pub files: usize,
+}
+
+#[derive(Debug)]
+pub struct ProfileSummary {
+    pub name: String,
+    pub using: bool,
+    pub parent: Vec<String>,
+    pub rule: Vec<TomlProfileRule>,
+    pub remote: Option<TomlProfileRemote>,
+    pub groups: Vec<ProfileGroupSummary>,
+}
+
+/// Show groups used by profile `name`, or current profile if `None`
+pub async fn show_profile(name: Option<String>) -> Result<ProfileSummary> {
+    let using_profile = current_profile().await?.name;
+    let name = name.unwrap_or_else(|| using_profile.clone());
+    let transaction = Transaction::start_shared()
+        .await
+        .wrap_err(t!("error.ctx.transcation.init"))?;
+    let mut groups = vec![];
+    for (group, origin) in profile_groups_with_origin(&transaction, &name)? {
+        let files = transaction.group(&group).await?.files.len();
+        groups.push(ProfileGroupSummary {
+            name: group,
+            origin,
+            files,
+        });
+    }
+    Ok(ProfileSummary {
+        using: name == using_profile,
+        parent: find_profile(&transaction, &name)?.parent.clone(),
+        rule: find_profile(&transaction, &name)?.rule.clone(),
+        remote: find_profile(&transaction, &name)?.remote.clone(),
+        name,
+        groups,
+    })
+}
+
+fn find_profile<'a>(
+    transaction: &'a Transaction,
+    name: &str,
+) -> Result<&'a TomlGlobalProfileEntry> {
+    transaction
+        .global()
+        .registery
+        .profile
+        .iter()
+        .find(|entry| entry.name == name)
+        .ok_or(DMError::ProfileError {
+            kind: ProfileErrorKind::NotExists,
+            msg: t!("error.profile.not_exists.msg", name = name),
+            advice: None,
+        })
+        .into_diagnostic()
+}
+
+/// Collect groups of profile `name` and its parents depth-first, parents go first
+///
+/// The origin of a group is the nearest profile attaching it.
+/// `path` holds the profiles being resolved, to detect inheritance cycle.
+fn resolve_groups(
+    transaction: &Transaction,
+    name: &str,
+    path: &mut Vec<String>,
+    groups: &mut Vec<(String, String)>,
+) -> Result<()> {
+    if path.iter().any(|entry| entry == name) {
+        path.push(name.to_string());
+        Err(DMError::ProfileError {
+            kind: ProfileErrorKind::CyclicInherit,
+            msg: t!("error.profile.cyclic.msg", path = &path.join(" -> ")),
+            advice: Some(t!("error.profile.cyclic.advice")),
+        })
+        .into_diagnostic()?;
+    }
+    let profile = find_profile(transaction, name)?;
+    path.push(name.to_string());
+    for parent in &profile.parent {
+        resolve_groups(transaction, parent, path, groups)?;
+    }
+    path.pop();
+    for group in &profile.group {
+        match groups.iter_mut().find(|(entry, _)| entry == group) {
+            Some((_, origin)) => *origin = name.to_string(),
+            None => groups.push((group.clone(), name.to_string())),
+        }
+    }
+    Ok(())
+}
+
+/// Get groups used by profile `name` with the profile each group comes from,
+/// including groups inherited from parents
+pub(super) fn profile_groups_with_origin(
+    transaction: &Transaction,
+    name: &str,
+) -> Result<Vec<(String, String)>> {
+    let mut groups = vec![];
+    resolve_groups(transaction, name, &mut vec![], &mut groups)?;
+    Ok(groups)
+}
+
+/// Get groups used by profile `name`, including groups inherited from parents
+pub(super) fn profile_groups(transaction: &Transaction, name: &str) -> Result<Vec<String>> {
+    Ok(profile_groups_with_origin(transaction, name)?
+        .into_iter()
+        .map(|(group, _)| group)
+        .collect())
+}
@@ -0,0 +1,214 @@
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use miette::{IntoDiagnostic, Result};
+use sha2::{Digest, Sha256};
+
+use crate::env::get_app_data_dir;
+
+use super::{
+    db::{self, FileState},
+    journal::{remove_path, Journal},
+};
+
+fn get_group_base_dir(group_name: &str) -> Result<PathBuf> {
+    Ok(get_app_data_dir()?.join("base").join(group_name))
+}
+
+/// Copy of the live file at last synchronization, used as base of three-way merge
+fn get_base_path(group_name: &str, entry_path: &str) -> Result<PathBuf> {
+    Ok(get_group_base_dir(group_name)?.join(entry_path))
+}
+
+/// Items in directory `path` except symbolic links, which are not kept in depository
+fn read_dir(path: &Path) -> Result<Vec<PathBuf>> {
+    let mut items = vec![];
+    for item in std::fs::read_dir(path).into_diagnostic()? {
+        let item = item.into_diagnostic()?;
+        if !item.file_type().into_diagnostic()?.is_symlink() {
+            items.push(item.path());
+        }
+    }
+    items.sort();
+    Ok(items)
+}
+
+/// Size and modified time of a file, or total size and the latest modified time of a directory
+fn stat(path: &Path) -> Result<(i64, i64)> {
+    let metadata = std::fs::metadata(path).into_diagnostic()?;
+    let mtime = metadata
+        .modified()
+        .into_diagnostic()?
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_nanos() as i64);
+    if !metadata.is_dir() {
+        return Ok((metadata.len() as i64, mtime));
+    }
+    let (mut size, mut latest) = (0, mtime);
+    for item in read_dir(path)? {
+        let (item_size, item_mtime) = stat(&item)?;
+        size += item_size;
+        latest = latest.max(item_mtime);
+    }
+    Ok((size, latest))
+}
+
+/// Feed content of `path` into `hasher`, files in directory are fed in order with their names
+fn hash_path(hasher: &mut Sha256, path: &Path) -> Result<()> {
+    if path.is_dir() {
+        for item in read_dir(path)? {
+            hasher.update(item.file_name().unwrap().to_string_lossy().as_bytes());
+            hasher.update([0]);
+            hash_path(hasher, &item)?;
+        }
+    } else {
+        let mut file = std::fs::File::open(path).into_diagnostic()?;
+        let mut buf = [0; 8192];
+        loop {
+            let len = file.read(&mut buf).into_diagnostic()?;
+            if len == 0 {
+                break;
+            }
+            hasher.update(&buf[..len]);
+        }
+    }
+    Ok(())
+}
+
+fn to_hex(hasher: Sha256) -> String {
+    hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// SHA-256 of `data` in hex, the same as the hash of a file holding it
+pub(super) fn hash_bytes(data: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(data);
+    to_hex(hasher)
+}
+
+/// Current state of `path`, `None` if it does not exist
+fn file_state(path: &Path) -> Result<Option<FileState>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let (size, mtime) = stat(path)?;
+    let mut hasher = Sha256::new();
+    hash_path(&mut hasher, path)?;
+    let hash = to_hex(hasher);
+    Ok(Some(FileState { hash, size, mtime }))
+}
+
+/// Whether `path` is not modified since `state` is recorded
+///
+/// Content is hashed only if size or modified time changes.
+fn is_untouched(path: &Path, state: &FileState) -> Result<bool> {
+    if !path.exists() {
+        return Ok(false);
+    }
+    if stat(path)? == (state.size, state.mtime) {
+        return Ok(true);
+    }
+    Ok(file_state(path)?.is_some_and(|current| current.hash == state.hash))
+}
+
+/// Record that the live file and the stored file of entry are synchronized
+///
+/// Nothing is recorded if any of them is missing. Content of the live file
+/// is kept as merge base if it is a regular file and `keep_base` is set,
+/// which must not be for encrypted entries as the base is kept in plain.
+pub(super) fn record_sync(
+    group_name: &str,
+    entry_path: &str,
+    live: &Path,
+    stored: &Path,
+    keep_base: bool,
+) -> Result<()> {
+    let (live_state, stored_state) = match (file_state(live)?, file_state(stored)?) {
+        (Some(live), Some(stored)) => (live, stored),
+        _ => return forget(group_name, entry_path),
+    };
+    let base = get_base_path(group_name, entry_path)?;
+    remove_path(&base)?;
+    if keep_base && live.is_file() {
+        std::fs::create_dir_all(base.parent().unwrap()).into_diagnostic()?;
+        std::fs::copy(live, &base).into_diagnostic()?;
+    }
+    db::update_entry_state(group_name, entry_path, &live_state, &stored_state)
+}
+
+/// Remove the recorded state of entry
+pub(super) fn forget(group_name: &str, entry_path: &str) -> Result<()> {
+    remove_path(&get_base_path(group_name, entry_path)?)?;
+    db::delete_entry_state(group_name, entry_path)
+}
+
+/// Move merge bases of group `name` to `new_name` when `journal` commits
+///
+/// Recorded states are moved by `rename_group` after that.
+pub(super) fn stage_rename_group(journal: &mut Journal, name: &str, new_name: &str) -> Result<()> {
+    let (from, to) = (get_group_base_dir(name)?, get_group_base_dir(new_name)?);
+    journal.stage_remove(&to);
+    if from.exists() {
+        journal.stage_rename(&from, &to);
+    }
+    Ok(())
+}
+
+pub(super) fn rename_group(name: &str, new_name: &str) -> Result<()> {
+    db::rename_group_state(name, new_name)
+}
+
+/// Remove merge bases of group `name` when `journal` commits
+///
+/// Recorded states are removed by `forget_group` after that.
+pub(super) fn stage_forget_group(journal: &mut Journal, name: &str) -> Result<()> {
+    journal.stage_remove(&get_group_base_dir(name)?);
+    Ok(())
+}
+
+pub(super) fn forget_group(name: &str) -> Result<()> {
+    db::delete_group_state(name)
+}
+
+/// Content of the live file at last synchronization
+pub(super) fn read_base(group_name: &str, entry_path: &str) -> Result<Option<Vec<u8>>> {
+    let base = get_base_path(group_name, entry_path)?;
+    if !base.is_file() {
+        return Ok(None);
+    }
+    Ok(Some(std::fs::read(base).into_diagnostic()?))
+}
+
+/// Which sides of entry are modified since last synchronization, as `(live, stored)`
+///
+/// Returns `None` if the entry is never synchronized.
+pub(super) fn changed_sides(
+    group_name: &str,
+    entry_path: &str,
+    live: &Path,
+    stored: &Path,
+) -> Result<Option<(bool, bool)>> {
+    Ok(match db::query_entry_state(group_name, entry_path)? {
+        Some((live_state, stored_state)) => Some((
+            !is_untouched(live, &live_state)?,
+            !is_untouched(stored, &stored_state)?,
+        )),
+        None => None,
+    })
+}
+
+/// Whether neither the live file nor the stored file of entry is modified
+/// since last synchronization
+pub(super) fn is_synced(
+    group_name: &str,
+    entry_path: &str,
+    live: &Path,
+    stored: &Path,
+) -> Result<bool> {
+    Ok(changed_sides(group_name, entry_path, live, stored)? == Some((false, false)))
+}
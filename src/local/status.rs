@@ -0,0 +1,106 @@
+use std::path::PathBuf;
+
+use miette::{Context, Result};
+use rust_i18n::t;
+
+use crate::ui::Ui;
+
+use super::{
+    file::{resolve_entry_path, sync_status},
+    Transaction,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryStatus {
+    /// Live file is the same as the stored one
+    Clean,
+    /// Only live file is modified since last synchronization
+    LocalModified,
+    /// Only stored file is modified since last synchronization
+    DepositoryModified,
+    /// Both files are modified since last synchronization, or it is unknown
+    /// which one is modified
+    Conflicted,
+    /// Stored file exists but not installed on this machine
+    MissingOnDisk,
+    /// Live file exists but the stored one is lost
+    MissingInDepository,
+    /// Entry has no install path for current platform, so it is neither
+    /// installed nor updated on this machine
+    NoInstallPath,
+}
+
+impl EntryStatus {
+    /// Whether the entry is out of sync with depository
+    pub fn is_drift(&self) -> bool {
+        matches!(
+            self,
+            EntryStatus::LocalModified
+                | EntryStatus::DepositoryModified
+                | EntryStatus::Conflicted
+                | EntryStatus::MissingOnDisk
+                | EntryStatus::MissingInDepository
+        )
+    }
+}
+
+#[derive(Debug)]
+pub struct EntryReport {
+    /// Path in depository
+    pub path: String,
+    /// Install path in current platform
+    pub live: Option<PathBuf>,
+    pub status: EntryStatus,
+}
+
+#[derive(Debug)]
+pub struct GroupReport {
+    pub name: String,
+    pub entries: Vec<EntryReport>,
+}
+
+impl GroupReport {
+    pub fn is_drift(&self) -> bool {
+        self.entries.iter().any(|entry| entry.status.is_drift())
+    }
+}
+
+/// Check every entry of the groups in current profile without changing anything
+pub async fn status(ui_handle: &dyn Ui) -> Result<Vec<GroupReport>> {
+    let transaction = Transaction::start_shared()
+        .await
+        .wrap_err(t!("error.ctx.transcation.init"))?;
+    let groups = super::group::select_groups(&transaction, None).await?;
+
+    let mut reports = vec![];
+    for group_name in groups {
+        let group = transaction.group(&group_name).await?.clone();
+        let mut entries = vec![];
+        for entry in &group.files {
+            let (live, status) =
+                match resolve_entry_path(&**transaction.storage(), entry, &group_name)? {
+                    None => (None, EntryStatus::NoInstallPath),
+                    Some((live, stored)) => {
+                        let status = if !stored.exists() {
+                            EntryStatus::MissingInDepository
+                        } else if !live.exists() {
+                            EntryStatus::MissingOnDisk
+                        } else {
+                            sync_status(ui_handle, &transaction, entry, &group).await?
+                        };
+                        (Some(live), status)
+                    }
+                };
+            entries.push(EntryReport {
+                path: entry.path.clone(),
+                live,
+                status,
+            });
+        }
+        reports.push(GroupReport {
+            name: group_name,
+            entries,
+        });
+    }
+    Ok(reports)
+}
@@ -0,0 +1,122 @@
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use async_trait::async_trait;
+use miette::{IntoDiagnostic, Result};
+
+use super::{Storage, StorageStat};
+
+/// A directory on local file system
+pub struct LocalStorage {
+    root: PathBuf,
+}
+
+impl LocalStorage {
+    pub fn new(root: PathBuf) -> Self {
+        Self { root }
+    }
+
+    /// Location of `path` on local file system
+    pub fn path(&self, path: &str) -> PathBuf {
+        self.root.join(path)
+    }
+}
+
+/// Size and modified time of a file, which changes whenever it is written
+fn file_stat(metadata: &std::fs::Metadata) -> StorageStat {
+    let mtime = metadata
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |time| time.as_nanos());
+    StorageStat {
+        size: metadata.len(),
+        version: Some(format!("{}-{}", metadata.len(), mtime)),
+    }
+}
+
+async fn collect_files(
+    dir: &Path,
+    prefix: &str,
+    files: &mut Vec<(String, StorageStat)>,
+) -> Result<()> {
+    let mut items = tokio::fs::read_dir(dir).await.into_diagnostic()?;
+    while let Some(item) = items.next_entry().await.into_diagnostic()? {
+        let name = item.file_name().to_string_lossy().to_string();
+        let path = if prefix.is_empty() {
+            name
+        } else {
+            format!("{}/{}", prefix, name)
+        };
+        let metadata = tokio::fs::metadata(item.path()).await.into_diagnostic()?;
+        if metadata.is_dir() {
+            Box::pin(collect_files(&item.path(), &path, files)).await?;
+        } else {
+            files.push((path, file_stat(&metadata)));
+        }
+    }
+    Ok(())
+}
+
+#[async_trait(?Send)]
+impl Storage for LocalStorage {
+    async fn read(&self, path: &str) -> Result<Vec<u8>> {
+        tokio::fs::read(self.path(path)).await.into_diagnostic()
+    }
+
+    async fn write(&self, path: &str, data: &[u8]) -> Result<()> {
+        let file = self.path(path);
+        tokio::fs::create_dir_all(file.parent().unwrap())
+            .await
+            .into_diagnostic()?;
+        tokio::fs::write(file, data).await.into_diagnostic()
+    }
+
+    async fn list(&self, path: &str) -> Result<Vec<(String, StorageStat)>> {
+        let path = path.trim_matches('/');
+        let mut files = vec![];
+        if self.path(path).is_dir() {
+            collect_files(&self.path(path), path, &mut files).await?;
+        }
+        files.sort_by(|(a, _), (b, _)| a.cmp(b));
+        Ok(files)
+    }
+
+    async fn delete(&self, path: &str) -> Result<()> {
+        let file = self.path(path);
+        match tokio::fs::symlink_metadata(&file).await {
+            Ok(metadata) if metadata.is_dir() => {
+                tokio::fs::remove_dir_all(file).await.into_diagnostic()
+            }
+            Ok(_) => tokio::fs::remove_file(file).await.into_diagnostic(),
+            Err(_) => Ok(()),
+        }
+    }
+
+    async fn rename(&self, from: &str, to: &str) -> Result<()> {
+        let to = self.path(to);
+        tokio::fs::create_dir_all(to.parent().unwrap())
+            .await
+            .into_diagnostic()?;
+        tokio::fs::rename(self.path(from), to)
+            .await
+            .into_diagnostic()
+    }
+
+    async fn create_dir(&self, path: &str) -> Result<()> {
+        tokio::fs::create_dir_all(self.path(path))
+            .await
+            .into_diagnostic()
+    }
+
+    async fn stat(&self, path: &str) -> Result<Option<StorageStat>> {
+        Ok(tokio::fs::metadata(self.path(path))
+            .await
+            .ok()
+            .map(|metadata| file_stat(&metadata)))
+    }
+
+    fn local_path(&self, path: &str) -> Option<PathBuf> {
+        Some(self.path(path))
+    }
+}
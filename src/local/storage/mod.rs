@@ -0,0 +1,123 @@
+use std::path::PathBuf;
+
+use async_trait::async_trait;
+use miette::{IntoDiagnostic, Result};
+use rust_i18n::t;
+
+use crate::{env::get_app_data_dir, error::DMError};
+
+use self::local::LocalStorage;
+
+pub mod local;
+pub mod webdav;
+
+/// Metadata of a file in storage
+#[derive(Debug, Clone)]
+pub struct StorageStat {
+    pub size: u64,
+    /// Changes whenever the content changes, such as ETag of WebDAV,
+    /// `None` if storage does not support it
+    pub version: Option<String>,
+}
+
+/// Condition of writing a file, so that changes made by others are not overwritten
+#[derive(Debug, Clone, Copy)]
+pub enum WriteCondition<'a> {
+    Always,
+    /// The file must not exist
+    Missing,
+    /// The file must be at this version
+    Version(&'a str),
+}
+
+impl WriteCondition<'_> {
+    /// Fail unless the file of `path` at `stat` satisfies the condition
+    pub fn check(&self, path: &str, stat: Option<&StorageStat>) -> Result<()> {
+        let satisfied = match self {
+            Self::Always => true,
+            Self::Missing => stat.is_none(),
+            Self::Version(version) => {
+                stat.and_then(|stat| stat.version.as_deref()) == Some(*version)
+            }
+        };
+        if !satisfied {
+            Err(DMError::StorageError {
+                msg: t!("error.storage.changed.msg", path = path),
+                advice: Some(t!("error.storage.changed.advice")),
+            })
+            .into_diagnostic()?;
+        }
+        Ok(())
+    }
+}
+
+/// Place where files of depository are kept
+///
+/// Paths are relative to the root of storage and separated by `/`.
+#[async_trait(?Send)]
+pub trait Storage {
+    /// Read the whole content of file `path`
+    async fn read(&self, path: &str) -> Result<Vec<u8>>;
+    /// Create or replace file `path`, parent directories are created if missing
+    async fn write(&self, path: &str, data: &[u8]) -> Result<()>;
+    /// Write file `path` only if `condition` is satisfied, returns the new
+    /// version of it if storage reports one
+    ///
+    /// It is checked before writing by default, storage checking it along with
+    /// writing should override it.
+    async fn write_if(
+        &self,
+        path: &str,
+        data: &[u8],
+        condition: WriteCondition<'_>,
+    ) -> Result<Option<String>> {
+        condition.check(path, self.stat(path).await?.as_ref())?;
+        self.write(path, data).await?;
+        Ok(self.stat(path).await?.and_then(|stat| stat.version))
+    }
+    /// All files under directory `path` recursively, `""` for the root
+    async fn list(&self, path: &str) -> Result<Vec<(String, StorageStat)>>;
+    /// Remove file or directory `path`, nothing happens if it does not exist
+    async fn delete(&self, path: &str) -> Result<()>;
+    /// Move file or directory `from` to `to`, which must not exist
+    async fn rename(&self, from: &str, to: &str) -> Result<()>;
+    /// Create directory `path` and its ancestors if they are missing
+    async fn create_dir(&self, path: &str) -> Result<()>;
+    /// Metadata of file `path`, `None` if it does not exist
+    async fn stat(&self, path: &str) -> Result<Option<StorageStat>>;
+    /// Location of `path` on local file system, `None` if storage is not local
+    ///
+    /// Links, scripts and plain directories are only supported by local storage.
+    fn local_path(&self, _path: &str) -> Option<PathBuf> {
+        None
+    }
+}
+
+/// Storage of depository on this machine
+///
+/// Its root is the app data directory, so that the global configuration is
+/// stored beside group directories.
+pub fn depository() -> Result<LocalStorage> {
+    Ok(LocalStorage::new(get_app_data_dir()?))
+}
+
+/// Global configuration in depository storage
+pub const GLOBAL_PATH: &str = "dm.toml";
+
+/// Directory of group in depository storage
+pub fn group_dir(group_name: &str) -> String {
+    format!("depository/{}", group_name)
+}
+
+/// Path of file `path` of group in depository storage
+pub fn group_path(group_name: &str, path: &str) -> String {
+    format!("{}/{}", group_dir(group_name), path)
+}
+
+/// Error of a storage not on local file system, where `path` is required to be local
+pub fn not_local(path: &str) -> DMError {
+    DMError::StorageError {
+        msg: t!("error.storage.not_local.msg", path = path),
+        advice: None,
+    }
+}
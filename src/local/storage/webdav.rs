@@ -0,0 +1,371 @@
+use std::cell::RefCell;
+use std::collections::HashSet;
+
+use async_trait::async_trait;
+use miette::{Context, IntoDiagnostic, Result};
+use percent_encoding::percent_decode_str;
+use quick_xml::{events::Event, Reader};
+use reqwest::{
+    header::{CONTENT_TYPE, ETAG, IF_MATCH, IF_NONE_MATCH},
+    Client, Method, RequestBuilder, Response, StatusCode, Url,
+};
+use rust_i18n::t;
+
+use crate::{config::DMWebDavConfiguration, error::DMError};
+
+use super::{Storage, StorageStat, WriteCondition};
+
+/// Properties requested by PROPFIND
+const PROPFIND_BODY: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<d:propfind xmlns:d="DAV:">
+  <d:prop><d:resourcetype/><d:getcontentlength/><d:getetag/></d:prop>
+</d:propfind>"#;
+
+/// A resource in the multi-status response of PROPFIND
+#[derive(Default)]
+struct PropEntry {
+    href: String,
+    is_collection: bool,
+    size: u64,
+    etag: Option<String>,
+}
+
+/// Parse the body of `207 Multi-Status`, namespace prefixes are ignored
+fn parse_multistatus(xml: &str) -> Result<Vec<PropEntry>> {
+    let mut reader = Reader::from_str(xml);
+    reader.trim_text(true);
+    let mut entries = vec![];
+    let mut entry = PropEntry::default();
+    let mut element = vec![];
+    loop {
+        match reader.read_event().into_diagnostic()? {
+            Event::Start(tag) => {
+                element = tag.local_name().as_ref().to_vec();
+                if element == b"collection" {
+                    entry.is_collection = true;
+                }
+            }
+            Event::Empty(tag) if tag.local_name().as_ref() == b"collection" => {
+                entry.is_collection = true;
+            }
+            Event::Text(text) => {
+                let text = text.unescape().into_diagnostic()?.to_string();
+                match element.as_slice() {
+                    b"href" => entry.href = text,
+                    b"getetag" => entry.etag = Some(text),
+                    b"getcontentlength" => entry.size = text.parse().unwrap_or(0),
+                    _ => {}
+                }
+            }
+            Event::End(tag) => {
+                if tag.local_name().as_ref() == b"response" {
+                    entries.push(std::mem::take(&mut entry));
+                }
+                element.clear();
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(entries)
+}
+
+/// A collection on WebDAV server, files are compared by ETag
+pub struct WebDavStorage {
+    client: Client,
+    /// URL of the collection, always ends with `/`
+    base: Url,
+    username: Option<String>,
+    password: Option<String>,
+    /// Collections known to exist, they are not created again
+    created: RefCell<HashSet<String>>,
+}
+
+impl WebDavStorage {
+    pub fn new(config: &DMWebDavConfiguration) -> Result<Self> {
+        let mut base = Url::parse(&config.url)
+            .ok()
+            .filter(|url| ["http", "https"].contains(&url.scheme()))
+            .ok_or_else(|| DMError::StorageError {
+                msg: t!("error.storage.invalid_url.msg", url = &config.url),
+                advice: Some(t!("error.storage.invalid_url.advice")),
+            })
+            .into_diagnostic()?;
+        if !base.path().ends_with('/') {
+            base.set_path(&format!("{}/", base.path()));
+        }
+        Ok(Self {
+            client: Client::new(),
+            base,
+            username: config.username.clone(),
+            password: config.password.clone(),
+            created: RefCell::new(HashSet::new()),
+        })
+    }
+
+    /// URL of `path`, which ends with `/` if it is a collection
+    fn url(&self, path: &str, is_collection: bool) -> Url {
+        let mut url = self.base.clone();
+        {
+            // Base is an http URL, so it always has path segments
+            let mut segments = url.path_segments_mut().unwrap();
+            segments.pop_if_empty();
+            segments.extend(path.split('/').filter(|segment| !segment.is_empty()));
+            if is_collection {
+                segments.push("");
+            }
+        }
+        url
+    }
+
+    /// Path relative to the collection of `href` in response, `None` if it is outside
+    fn relative_path(&self, href: &str) -> Option<String> {
+        let decode = |path: &str| percent_decode_str(path).decode_utf8_lossy().to_string();
+        let path = decode(self.base.join(href).ok()?.path());
+        path.strip_prefix(&decode(self.base.path()))
+            .map(|path| path.trim_end_matches('/').to_string())
+    }
+
+    fn request(&self, method: Method, url: Url) -> RequestBuilder {
+        let builder = self.client.request(method, url);
+        match &self.username {
+            Some(username) => builder.basic_auth(username, self.password.as_ref()),
+            None => builder,
+        }
+    }
+
+    /// Send request, fails unless it succeeds or its status is one of `allowed`
+    async fn send(&self, builder: RequestBuilder, allowed: &[StatusCode]) -> Result<Response> {
+        let request = builder.build().into_diagnostic()?;
+        let (method, url) = (request.method().to_string(), request.url().to_string());
+        let response = self
+            .client
+            .execute(request)
+            .await
+            .into_diagnostic()
+            .wrap_err(t!(
+                "error.ctx.storage.request",
+                method = &method,
+                url = &url
+            ))?;
+        let status = response.status();
+        if !status.is_success() && !allowed.contains(&status) {
+            let advice = matches!(status, StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN)
+                .then(|| t!("error.storage.request.advice_auth"));
+            Err(DMError::StorageError {
+                msg: t!(
+                    "error.storage.request.msg",
+                    method = &method,
+                    url = &url,
+                    status = &status.to_string()
+                ),
+                advice,
+            })
+            .into_diagnostic()?;
+        }
+        Ok(response)
+    }
+
+    /// Resources of `path` and its children if `depth` is 1, `None` if it does not exist
+    async fn propfind(
+        &self,
+        path: &str,
+        is_collection: bool,
+        depth: u8,
+    ) -> Result<Option<Vec<(String, PropEntry)>>> {
+        let builder = self
+            .request(
+                Method::from_bytes(b"PROPFIND").unwrap(),
+                self.url(path, is_collection),
+            )
+            .header("Depth", depth.to_string())
+            .header(CONTENT_TYPE, "application/xml; charset=utf-8")
+            .body(PROPFIND_BODY);
+        let response = self.send(builder, &[StatusCode::NOT_FOUND]).await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let body = response.text().await.into_diagnostic()?;
+        let entries = parse_multistatus(&body).wrap_err(t!("error.ctx.storage.parse"))?;
+        Ok(Some(
+            entries
+                .into_iter()
+                .filter_map(|entry| Some((self.relative_path(&entry.href)?, entry)))
+                .collect(),
+        ))
+    }
+
+    /// Create collection `path` and its ancestors if they are missing
+    async fn create_collection(&self, path: &str) -> Result<()> {
+        let segments: Vec<&str> = path.split('/').collect();
+        for end in 1..=segments.len() {
+            let dir = segments[..end].join("/");
+            if self.created.borrow().contains(&dir) {
+                continue;
+            }
+            let builder = self.request(Method::from_bytes(b"MKCOL").unwrap(), self.url(&dir, true));
+            // 405 Method Not Allowed means it exists already
+            self.send(builder, &[StatusCode::METHOD_NOT_ALLOWED])
+                .await?;
+            self.created.borrow_mut().insert(dir);
+        }
+        Ok(())
+    }
+}
+
+#[async_trait(?Send)]
+impl Storage for WebDavStorage {
+    async fn read(&self, path: &str) -> Result<Vec<u8>> {
+        let builder = self.request(Method::GET, self.url(path, false));
+        let response = self.send(builder, &[]).await?;
+        Ok(response.bytes().await.into_diagnostic()?.to_vec())
+    }
+
+    async fn write(&self, path: &str, data: &[u8]) -> Result<()> {
+        self.write_if(path, data, WriteCondition::Always).await?;
+        Ok(())
+    }
+
+    /// Condition is sent as `If-Match` or `If-None-Match`, and the new ETag
+    /// is taken from the response if server returns it
+    async fn write_if(
+        &self,
+        path: &str,
+        data: &[u8],
+        condition: WriteCondition<'_>,
+    ) -> Result<Option<String>> {
+        if let Some((parent, _)) = path.rsplit_once('/') {
+            self.create_collection(parent).await?;
+        }
+        let builder = self
+            .request(Method::PUT, self.url(path, false))
+            .body(data.to_vec());
+        let builder = match condition {
+            WriteCondition::Always => builder,
+            WriteCondition::Missing => builder.header(IF_NONE_MATCH, "*"),
+            WriteCondition::Version(etag) => builder.header(IF_MATCH, etag),
+        };
+        let response = self
+            .send(builder, &[StatusCode::PRECONDITION_FAILED])
+            .await?;
+        if response.status() == StatusCode::PRECONDITION_FAILED {
+            Err(DMError::StorageError {
+                msg: t!("error.storage.changed.msg", path = path),
+                advice: Some(t!("error.storage.changed.advice")),
+            })
+            .into_diagnostic()?;
+        }
+        Ok(response
+            .headers()
+            .get(ETAG)
+            .and_then(|etag| etag.to_str().ok())
+            .map(String::from))
+    }
+
+    async fn list(&self, path: &str) -> Result<Vec<(String, StorageStat)>> {
+        let mut files = vec![];
+        let mut dirs = vec![path.trim_matches('/').to_string()];
+        while let Some(dir) = dirs.pop() {
+            let entries = match self.propfind(&dir, true, 1).await? {
+                Some(entries) => entries,
+                None => continue,
+            };
+            for (path, entry) in entries {
+                if path == dir {
+                    continue;
+                }
+                if entry.is_collection {
+                    dirs.push(path);
+                } else {
+                    let stat = StorageStat {
+                        size: entry.size,
+                        version: entry.etag,
+                    };
+                    files.push((path, stat));
+                }
+            }
+        }
+        files.sort_by(|(a, _), (b, _)| a.cmp(b));
+        Ok(files)
+    }
+
+    async fn delete(&self, path: &str) -> Result<()> {
+        let builder = self.request(Method::DELETE, self.url(path, false));
+        self.send(builder, &[StatusCode::NOT_FOUND]).await?;
+        // Deleted collections have to be created again
+        self.created.borrow_mut().clear();
+        Ok(())
+    }
+
+    /// Collection is moved as a whole by `MOVE`
+    async fn rename(&self, from: &str, to: &str) -> Result<()> {
+        if let Some((parent, _)) = to.rsplit_once('/') {
+            self.create_collection(parent).await?;
+        }
+        let builder = self
+            .request(Method::from_bytes(b"MOVE").unwrap(), self.url(from, false))
+            .header("Destination", self.url(to, false).as_str())
+            .header("Overwrite", "F");
+        self.send(builder, &[]).await?;
+        // Moved collections do not exist at the old place
+        self.created.borrow_mut().clear();
+        Ok(())
+    }
+
+    async fn create_dir(&self, path: &str) -> Result<()> {
+        self.create_collection(path.trim_matches('/')).await
+    }
+
+    async fn stat(&self, path: &str) -> Result<Option<StorageStat>> {
+        Ok(self
+            .propfind(path, false, 0)
+            .await?
+            .and_then(|entries| entries.into_iter().next())
+            .map(|(_, entry)| StorageStat {
+                size: entry.size,
+                version: entry.etag,
+            }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_prefixed_multistatus() {
+        let xml = r#"<?xml version="1.0"?>
+<D:multistatus xmlns:D="DAV:">
+  <D:response>
+    <D:href>/dav/</D:href>
+    <D:propstat><D:prop><D:resourcetype><D:collection/></D:resourcetype></D:prop></D:propstat>
+  </D:response>
+  <D:response>
+    <D:href>/dav/a%20b.txt</D:href>
+    <D:propstat><D:prop>
+      <D:resourcetype/><D:getcontentlength>3</D:getcontentlength><D:getetag>&quot;1&quot;</D:getetag>
+    </D:prop></D:propstat>
+  </D:response>
+</D:multistatus>"#;
+        let entries = parse_multistatus(xml).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert!(entries[0].is_collection);
+        assert!(!entries[1].is_collection);
+        assert_eq!(entries[1].size, 3);
+        assert_eq!(entries[1].etag.as_deref(), Some("\"1\""));
+
+        let storage = WebDavStorage::new(&DMWebDavConfiguration {
+            url: "http://localhost/dav".to_string(),
+            username: None,
+            password: None,
+        })
+        .unwrap();
+        assert_eq!(storage.relative_path(&entries[0].href).unwrap(), "");
+        assert_eq!(storage.relative_path(&entries[1].href).unwrap(), "a b.txt");
+        assert!(storage.relative_path("/other/a.txt").is_none());
+        assert_eq!(
+            storage.url("dir/a b.txt", false).as_str(),
+            "http://localhost/dav/dir/a%20b.txt"
+        );
+    }
+}
@@ -0,0 +1,221 @@
+use miette::{Context, IntoDiagnostic, Result};
+use rust_i18n::t;
+
+use crate::{
+    env::get_app_data_dir,
+    error::DMError,
+    ui::{MsgLevel, Ui},
+};
+
+use super::{
+    git::{self, check, git_output, run_git, succeeds},
+    history,
+    journal::Journal,
+    lock::DepositoryLock,
+    merge, profile, TomlGlobal, TomlGroup, TomlProfileRemote,
+};
+
+/// Kind of a manifest in repository, they are merged key by key
+enum Manifest {
+    Global,
+    Group,
+}
+
+impl Manifest {
+    fn of(path: &str) -> Option<Self> {
+        let parts: Vec<&str> = path.split('/').collect();
+        match parts.as_slice() {
+            ["dm.toml"] => Some(Self::Global),
+            ["depository", _, "manifest.toml"] => Some(Self::Group),
+            _ => None,
+        }
+    }
+
+    /// Check merged manifest could be loaded, and format it as dm writes it
+    fn normalize(&self, text: &str) -> Option<String> {
+        match self {
+            Self::Global => toml_edit::de::from_str::<TomlGlobal>(text)
+                .ok()
+                .and_then(|value| toml_edit::ser::to_string_pretty(&value).ok()),
+            Self::Group => toml_edit::de::from_str::<TomlGroup>(text)
+                .ok()
+                .and_then(|value| toml_edit::ser::to_string_pretty(&value).ok()),
+        }
+    }
+}
+
+fn lines(args: &[&str]) -> Result<Vec<String>> {
+    Ok(String::from_utf8_lossy(&run_git(args)?.stdout)
+        .lines()
+        .map(String::from)
+        .collect())
+}
+
+/// Content of `path` at `rev`, `None` if it does not exist
+fn show(rev: &str, path: &str) -> Result<Option<String>> {
+    let output = git_output(&["show", &format!("{}:{}", rev, path)])?;
+    Ok(output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).to_string()))
+}
+
+/// Files changed since `base`, or all files if histories are unrelated
+fn changed_files(base: Option<&str>, rev: &str) -> Result<Vec<String>> {
+    match base {
+        Some(base) => lines(&["diff", "--name-only", base, rev]),
+        None => lines(&["ls-tree", "-r", "--name-only", rev]),
+    }
+}
+
+/// Merge manifest `path` changed on both sides and stage it, returns conflicted keys
+fn merge_manifest(base: Option<&str>, path: &str, manifest: Manifest) -> Result<Vec<String>> {
+    let base = match base {
+        Some(base) => show(base, path)?,
+        None => None,
+    };
+    let (ours, theirs) = (show("HEAD", path)?, show("FETCH_HEAD", path)?);
+    let merged = merge::merge_document(base.as_deref(), ours.as_deref(), theirs.as_deref())?;
+    if !merged.conflicts.is_empty() {
+        return Ok(merged
+            .conflicts
+            .into_iter()
+            .map(|key| format!("{}: {}", path, key))
+            .collect());
+    }
+    let file = get_app_data_dir()?.join(path);
+    match merged.text {
+        Some(text) => {
+            let text = match manifest.normalize(&text) {
+                Some(text) => text,
+                None => return Ok(vec![path.to_string()]),
+            };
+            std::fs::create_dir_all(file.parent().unwrap()).into_diagnostic()?;
+            std::fs::write(&file, text).into_diagnostic()?;
+            run_git(&["add", "--", path])?;
+        }
+        None => {
+            run_git(&["rm", "--quiet", "--force", "--ignore-unmatch", "--", path])?;
+        }
+    }
+    Ok(vec![])
+}
+
+/// Merge fetched commits into current branch
+///
+/// Manifests changed on both sides are merged key by key instead of line by
+/// line. The merge is aborted if any conflict remains.
+fn pull(ui_handle: &dyn Ui, remote: &TomlProfileRemote) -> Result<()> {
+    if succeeds(&["merge-base", "--is-ancestor", "FETCH_HEAD", "HEAD"])? {
+        ui_handle.msg(MsgLevel::Info, t!("sync.up_to_date"));
+        return Ok(());
+    }
+    if succeeds(&["merge-base", "--is-ancestor", "HEAD", "FETCH_HEAD"])? {
+        run_git(&["merge", "--quiet", "--ff-only", "FETCH_HEAD"])?;
+        ui_handle.msg(MsgLevel::Info, t!("sync.pulled"));
+        return Ok(());
+    }
+
+    let output = git_output(&["merge-base", "HEAD", "FETCH_HEAD"])?;
+    let base = output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string());
+    let args = [
+        "merge",
+        "--quiet",
+        "--no-commit",
+        "--no-ff",
+        "--allow-unrelated-histories",
+        "FETCH_HEAD",
+    ];
+    let output = git_output(&args)?;
+    // Conflicts are resolved below, it only fails if merge is not started
+    if !succeeds(&["rev-parse", "--quiet", "--verify", "MERGE_HEAD"])? {
+        check(&args, output)?;
+    }
+    let theirs = changed_files(base.as_deref(), "FETCH_HEAD")?;
+    let mut conflicts = vec![];
+    for path in changed_files(base.as_deref(), "HEAD")? {
+        if !theirs.contains(&path) {
+            continue;
+        }
+        if let Some(manifest) = Manifest::of(&path) {
+            conflicts.extend(merge_manifest(base.as_deref(), &path, manifest)?);
+        }
+    }
+    for path in lines(&["diff", "--name-only", "--diff-filter=U"])? {
+        if !conflicts.iter().any(|conflict| conflict.starts_with(&path)) {
+            conflicts.push(path);
+        }
+    }
+    if !conflicts.is_empty() {
+        run_git(&["merge", "--abort"])?;
+        Err(DMError::SyncError {
+            msg: t!(
+                "error.sync.conflict.msg",
+                count = &conflicts.len().to_string(),
+                conflicts = &conflicts.join("\n")
+            ),
+            advice: Some(t!("error.sync.conflict.advice")),
+        })
+        .into_diagnostic()?;
+    }
+    git::commit(&format!(
+        "Merge branch '{}' of {}",
+        remote.branch(),
+        remote.url
+    ))?;
+    ui_handle.msg(MsgLevel::Info, t!("sync.merged"));
+    Ok(())
+}
+
+/// Pull changes from the remote of current profile, or `url` if it is given,
+/// then push local changes to it
+///
+/// The depository is version-controlled by git from now on if it is not.
+/// Pulled changes are not recorded in history, run `dm status` and
+/// `dm install` to apply them to this machine.
+pub async fn sync(ui_handle: &dyn Ui, url: Option<String>) -> Result<()> {
+    let remote = match url {
+        Some(url) => TomlProfileRemote { url, branch: None },
+        None => match profile::current_remote().await? {
+            Some(remote) => remote,
+            None => {
+                let name = profile::current_profile().await?.name;
+                Err(DMError::SyncError {
+                    msg: t!("error.sync.no_remote.msg", name = &name),
+                    advice: Some(t!("error.sync.no_remote.advice", name = &name)),
+                })
+                .into_diagnostic()?
+            }
+        },
+    };
+    let _lock = DepositoryLock::acquire()?;
+    Journal::recover().wrap_err(t!("error.ctx.transcation.recover"))?;
+    if !git::is_enabled()? {
+        run_git(&["init", "--quiet"])?;
+    }
+    git::commit_all(&history::command_line())?;
+
+    let branch = remote.branch();
+    let args = ["ls-remote", "--exit-code", "--heads", &remote.url, branch];
+    let output = git_output(&args)?;
+    // Exit code 2 means the branch does not exist yet
+    if output.status.code() != Some(2) {
+        check(&args, output)?;
+        run_git(&["fetch", "--quiet", &remote.url, branch])?;
+        pull(ui_handle, &remote)?;
+    }
+    run_git(&[
+        "push",
+        "--quiet",
+        &remote.url,
+        &format!("HEAD:refs/heads/{}", branch),
+    ])?;
+    ui_handle.msg(
+        MsgLevel::Info,
+        t!("sync.pushed", remote = &remote.to_string()),
+    );
+    Ok(())
+}
@@ -0,0 +1,69 @@
+use std::collections::BTreeMap;
+
+use miette::{IntoDiagnostic, Result};
+use minijinja::{Environment, UndefinedBehavior};
+use rust_i18n::t;
+use serde::Serialize;
+
+use crate::{
+    env::{get_hostname, SpecDir},
+    error::DMError,
+};
+
+/// Variables which can be used in template entries
+///
+/// ```jinja
+/// {% if os == "windows" %}{{ dirs.config_dir }}\app{% else %}{{ vars.app_dir }}{% endif %}
+/// ```
+#[derive(Serialize)]
+struct TemplateContext<'a> {
+    hostname: String,
+    os: &'static str,
+    arch: &'static str,
+    family: &'static str,
+    profile: String,
+    group: &'a str,
+    /// Platform-specific standard locations, e.g. `dirs.home_dir`
+    dirs: BTreeMap<&'static str, String>,
+    /// User-defined variables from the `[variables]` table of group manifest
+    vars: &'a BTreeMap<String, String>,
+}
+
+/// Render template `source` of an entry in group `group_name`
+///
+/// Undefined variables are treated as errors instead of empty strings.
+pub(super) async fn render(
+    source: &[u8],
+    group_name: &str,
+    variables: &BTreeMap<String, String>,
+) -> Result<String> {
+    let source = std::str::from_utf8(source)
+        .map_err(|_| DMError::TemplateError {
+            msg: t!("error.template.not_text.msg"),
+            advice: None,
+        })
+        .into_diagnostic()?;
+    let context = TemplateContext {
+        hostname: get_hostname(),
+        os: std::env::consts::OS,
+        arch: std::env::consts::ARCH,
+        family: std::env::consts::FAMILY,
+        profile: super::profile::current_profile().await?.name,
+        group: group_name,
+        dirs: SpecDir::new()?
+            .platform_dirs()
+            .map(|(name, path)| (name, path.to_string_lossy().to_string()))
+            .collect(),
+        vars: variables,
+    };
+
+    let mut env = Environment::new();
+    env.set_undefined_behavior(UndefinedBehavior::Strict);
+    env.set_keep_trailing_newline(true);
+    env.render_str(source, context)
+        .map_err(|err| DMError::TemplateError {
+            msg: t!("error.template.render.msg", err = &format!("{:#}", err)),
+            advice: Some(t!("error.template.render.advice")),
+        })
+        .into_diagnostic()
+}
@@ -1,119 +1,902 @@
 use async_trait::async_trait;
-use miette::{IntoDiagnostic, Result};
-use std::path::PathBuf;
-use tokio::{
-    fs::File,
-    io::{AsyncReadExt, BufReader},
+use miette::{Context, IntoDiagnostic, Result};
+use rust_i18n::t;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Display;
+use std::io::{Cursor, Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::rc::Rc;
+
+use crate::{
+    error::DMError,
+    platform,
+    ui::{MsgLevel, Ui},
+};
+
+use super::{
+    backup::backup_file,
+    crypto::Keys,
+    journal::remove_path,
+    merge,
+    storage::{self, Storage, StorageStat},
+    template, ItemEntryKind, LinkMode, TomlGroup, TomlItemEntry,
 };
 
-use super::TomlItemEntry;
+/// Stored file of an entry, which is read and written through storage of depository
+pub(super) struct Stored {
+    storage: Rc<dyn Storage>,
+    /// Path in storage
+    path: String,
+}
+
+impl Stored {
+    pub fn new(storage: Rc<dyn Storage>, path: String) -> Self {
+        Self { storage, path }
+    }
+
+    /// Stored file of entry in group, which is kept in `storage` of depository
+    pub fn of(storage: &Rc<dyn Storage>, group_name: &str, entry: &TomlItemEntry) -> Self {
+        Self::new(
+            storage.clone(),
+            storage::group_path(group_name, &entry.path),
+        )
+    }
+
+    pub async fn exists(&self) -> Result<bool> {
+        Ok(self.storage.stat(&self.path).await?.is_some())
+    }
+
+    pub async fn read(&self) -> Result<Vec<u8>> {
+        self.storage.read(&self.path).await
+    }
+
+    pub async fn write(&self, data: &[u8]) -> Result<()> {
+        self.storage.write(&self.path, data).await
+    }
 
-async fn backup_file(path: &PathBuf) -> Result<()> {
-    let mut bak_path = path.clone();
-    bak_path.set_extension(
-        path.extension()
-            .map(|x| x.to_str().unwrap())
-            .unwrap_or("")
-            .to_string()
-            + ".bak",
-    );
-    if bak_path.exists() {
-        tokio::fs::remove_file(&bak_path).await.into_diagnostic()?;
+    pub async fn delete(&self) -> Result<()> {
+        self.storage.delete(&self.path).await
+    }
+
+    /// Files under the stored directory, relative to it
+    pub async fn list(&self) -> Result<Vec<(PathBuf, StorageStat)>> {
+        let prefix = format!("{}/", self.path);
+        Ok(self
+            .storage
+            .list(&self.path)
+            .await?
+            .into_iter()
+            .filter_map(|(path, stat)| {
+                let path = path.strip_prefix(&prefix)?;
+                Some((PathBuf::from(path), stat))
+            })
+            .collect())
+    }
+
+    /// Stored file `file` under the stored directory
+    pub fn child(&self, file: &Path) -> Self {
+        let file: Vec<_> = file
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect();
+        Self::new(
+            self.storage.clone(),
+            format!("{}/{}", self.path, file.join("/")),
+        )
+    }
+
+    /// Move the stored file into backup store before it is replaced, only
+    /// files in local storage are backed up
+    pub async fn backup(&self) -> Result<()> {
+        match self.storage.local_path(&self.path) {
+            Some(path) if path.symlink_metadata().is_ok() => backup_file(&path).await,
+            _ => Ok(()),
+        }
+    }
+
+    /// Location on local file system, fails if storage is not local
+    pub fn local_path(&self) -> Result<PathBuf> {
+        self.storage
+            .local_path(&self.path)
+            .ok_or_else(|| storage::not_local(&self.path))
+            .into_diagnostic()
+    }
+}
+
+impl Display for Stored {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.storage.local_path(&self.path) {
+            Some(path) => write!(f, "{}", path.to_string_lossy()),
+            None => write!(f, "{}", self.path),
+        }
     }
-    tokio::fs::rename(path, bak_path).await.into_diagnostic()?;
-    Ok(())
 }
 
-#[async_trait]
+#[async_trait(?Send)]
 pub trait Updater {
-    async fn is_diff(
-        &mut self,
-        entry: &TomlItemEntry,
-        src: &PathBuf,
-        dst: &PathBuf,
-    ) -> Result<bool>;
-    async fn update(&mut self, entry: &TomlItemEntry, src: &PathBuf, dst: &PathBuf) -> Result<()>;
-    async fn install(&mut self, entry: &TomlItemEntry, src: &PathBuf, dst: &PathBuf) -> Result<()>;
+    /// Check whether the live file `src` and the stored file `dst` have different content
+    async fn is_diff(&mut self, entry: &TomlItemEntry, src: &Path, dst: &Stored) -> Result<bool>;
+    /// Copy the live file `src` into depository `dst`
+    async fn update(&mut self, entry: &TomlItemEntry, src: &Path, dst: &Stored) -> Result<()>;
+    /// Copy the depository file `src` to install location `dst`
+    async fn install(&mut self, entry: &TomlItemEntry, src: &Stored, dst: &Path) -> Result<()>;
 }
 
-struct ManualUpdater;
+/// Updater driven by the scripts declared in entry for current platform
+///
+/// The script runs in the group directory with these environment variables:
+/// `DM_SRC`, `DM_DST`, `DM_GROUP`, `DM_GROUP_DIR`, `DM_OS` and `DM_ACTION`.
+/// If a script is not declared, it works like a normal updater instead.
+///
+/// The diff script should exit with 0 if files are same, or 1 if they differ.
+/// Scripts run on local file system, so the storage of depository must be local.
+struct ManualUpdater<'a> {
+    group_name: String,
+    storage: Rc<dyn Storage>,
+    ui_handle: &'a dyn Ui,
+}
 
-#[async_trait]
-impl Updater for ManualUpdater {
-    async fn is_diff(&mut self, _: &TomlItemEntry, src: &PathBuf, dst: &PathBuf) -> Result<bool> {
-        todo!()
+impl ManualUpdater<'_> {
+    async fn run_script(&self, script: &str, action: &str, src: &Path, dst: &Path) -> Result<i32> {
+        let dir = storage::group_dir(&self.group_name);
+        let group_dir = self
+            .storage
+            .local_path(&dir)
+            .ok_or_else(|| storage::not_local(&dir))
+            .into_diagnostic()?;
+        let script_path = group_dir.join(script);
+        if !script_path.exists() {
+            Err(DMError::ScriptError {
+                msg: t!("error.script.not_found.msg", script = script),
+                advice: Some(t!(
+                    "error.script.not_found.advice",
+                    dir = &group_dir.to_string_lossy()
+                )),
+            })
+            .into_diagnostic()?;
+        }
+        let output = platform::script_command(&script_path)
+            .current_dir(&group_dir)
+            .env("DM_SRC", src)
+            .env("DM_DST", dst)
+            .env("DM_GROUP", &self.group_name)
+            .env("DM_GROUP_DIR", &group_dir)
+            .env("DM_OS", std::env::consts::OS)
+            .env("DM_ACTION", action)
+            .output()
+            .await
+            .into_diagnostic()
+            .wrap_err(t!("error.ctx.script.run", script = script))?;
+        for line in String::from_utf8_lossy(&output.stdout).lines() {
+            self.ui_handle.msg(MsgLevel::Info, line.to_string());
+        }
+        for line in String::from_utf8_lossy(&output.stderr).lines() {
+            self.ui_handle.msg(MsgLevel::Warn, line.to_string());
+        }
+        // Killed by signal is taken as failure
+        Ok(output.status.code().unwrap_or(-1))
+    }
+
+    fn script_failed(script: &str, code: i32) -> DMError {
+        DMError::ScriptError {
+            msg: t!(
+                "error.script.failed.msg",
+                script = script,
+                code = &code.to_string()
+            ),
+            advice: None,
+        }
+    }
+}
+
+#[async_trait(?Send)]
+impl Updater for ManualUpdater<'_> {
+    async fn is_diff(&mut self, entry: &TomlItemEntry, src: &Path, dst: &Stored) -> Result<bool> {
+        match entry.get_platform_script().and_then(|s| s.diff.as_ref()) {
+            Some(script) => match self
+                .run_script(script, "diff", src, &dst.local_path()?)
+                .await?
+            {
+                0 => Ok(false),
+                1 => Ok(true),
+                code => Err(Self::script_failed(script, code)).into_diagnostic(),
+            },
+            None => NormalUpdater.is_diff(entry, src, dst).await,
+        }
     }
 
-    async fn update(&mut self, _: &TomlItemEntry, src: &PathBuf, dst: &PathBuf) -> Result<()> {
-        todo!()
+    async fn update(&mut self, entry: &TomlItemEntry, src: &Path, dst: &Stored) -> Result<()> {
+        match entry.get_platform_script().and_then(|s| s.update.as_ref()) {
+            Some(script) => match self
+                .run_script(script, "update", src, &dst.local_path()?)
+                .await?
+            {
+                0 => Ok(()),
+                code => Err(Self::script_failed(script, code)).into_diagnostic(),
+            },
+            None => NormalUpdater.update(entry, src, dst).await,
+        }
     }
-    async fn install(&mut self, _: &TomlItemEntry, src: &PathBuf, dst: &PathBuf) -> Result<()> {
-        todo!()
+    async fn install(&mut self, entry: &TomlItemEntry, src: &Stored, dst: &Path) -> Result<()> {
+        match entry.get_platform_script().and_then(|s| s.install.as_ref()) {
+            Some(script) => match self
+                .run_script(script, "install", &src.local_path()?, dst)
+                .await?
+            {
+                0 => Ok(()),
+                code => Err(Self::script_failed(script, code)).into_diagnostic(),
+            },
+            None => NormalUpdater.install(entry, src, dst).await,
+        }
     }
 }
+
+/// Difference between two directory trees, all paths are relative to the tree root
+#[derive(Debug, Default)]
+pub struct DirDiff {
+    /// Files only exist in source tree
+    pub added: Vec<PathBuf>,
+    /// Files only exist in destination tree
+    pub removed: Vec<PathBuf>,
+    /// Files exist in both trees but have different content
+    pub changed: Vec<PathBuf>,
+}
+
+impl DirDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// Collect all files in directory `root` recursively, paths are relative to `root`
+///
+/// Symbolic links inside it are skipped rather than followed, since they
+/// can't be kept by every storage and may point out of the directory.
+fn walk_dir(root: &Path) -> Result<BTreeSet<PathBuf>> {
+    let mut files = BTreeSet::new();
+    if !root.is_dir() {
+        return Ok(files);
+    }
+    let mut stack = vec![root.to_path_buf()];
+    while let Some(dir) = stack.pop() {
+        for item in std::fs::read_dir(&dir).into_diagnostic()? {
+            let item = item.into_diagnostic()?;
+            let file_type = item.file_type().into_diagnostic()?;
+            if file_type.is_symlink() {
+                continue;
+            }
+            if file_type.is_dir() {
+                stack.push(item.path());
+            } else {
+                files.insert(item.path().strip_prefix(root).unwrap().to_path_buf());
+            }
+        }
+    }
+    Ok(files)
+}
+
+/// Compare live directory `live` with stored directory `stored` recursively
+///
+/// Files in both trees are only read if they have the same size.
+pub async fn diff_dir(live: &Path, stored: &Stored) -> Result<DirDiff> {
+    let live_files = walk_dir(live)?;
+    let stored_files: BTreeMap<_, _> = stored.list().await?.into_iter().collect();
+    let mut diff = DirDiff {
+        added: live_files
+            .iter()
+            .filter(|file| !stored_files.contains_key(*file))
+            .cloned()
+            .collect(),
+        removed: stored_files
+            .keys()
+            .filter(|file| !live_files.contains(*file))
+            .cloned()
+            .collect(),
+        changed: vec![],
+    };
+    for (file, stat) in &stored_files {
+        if !live_files.contains(file) {
+            continue;
+        }
+        let live_file = live.join(file);
+        let changed = tokio::fs::metadata(&live_file)
+            .await
+            .into_diagnostic()?
+            .len()
+            != stat.size
+            || tokio::fs::read(&live_file).await.into_diagnostic()?
+                != stored.child(file).read().await?;
+        if changed {
+            diff.changed.push(file.clone());
+        }
+    }
+    Ok(diff)
+}
+
+/// Write `data` to the live file `dst`, the old one is backed up
+///
+/// Nothing is done if `dst` is a regular file with the same content.
+async fn write_live(dst: &Path, data: &[u8]) -> Result<()> {
+    tokio::fs::create_dir_all(dst.parent().unwrap())
+        .await
+        .into_diagnostic()?;
+    if dst
+        .symlink_metadata()
+        .is_ok_and(|metadata| metadata.is_file())
+        && tokio::fs::read(dst).await.into_diagnostic()? == data
+    {
+        return Ok(());
+    }
+    // A link is backed up itself rather than written through, even if it is broken
+    if dst.symlink_metadata().is_ok() {
+        backup_file(dst).await?;
+    }
+    tokio::fs::write(dst, data).await.into_diagnostic()
+}
+
 struct NormalUpdater;
 
-#[async_trait]
+#[async_trait(?Send)]
 impl Updater for NormalUpdater {
-    /// 逐位比较文件
-    async fn is_diff(&mut self, _: &TomlItemEntry, src: &PathBuf, dst: &PathBuf) -> Result<bool> {
-        let src = File::open(src).await.into_diagnostic()?;
-        let dst = File::open(dst).await.into_diagnostic()?;
-        if src.metadata().await.into_diagnostic()?.len()
-            != dst.metadata().await.into_diagnostic()?.len()
-        {
-            return Ok(true);
+    /// 逐位比较文件，目录则递归比较其中所有文件
+    async fn is_diff(&mut self, entry: &TomlItemEntry, src: &Path, dst: &Stored) -> Result<bool> {
+        match entry.kind {
+            ItemEntryKind::File => {
+                Ok(tokio::fs::read(src).await.into_diagnostic()? != dst.read().await?)
+            }
+            ItemEntryKind::Dir => Ok(!diff_dir(src, dst).await?.is_empty()),
         }
-        let mut reader1 = BufReader::new(src);
-        let mut reader2 = BufReader::new(dst);
+    }
 
-        let mut buf1 = [0; 1024];
-        let mut buf2 = [0; 1024];
-        loop {
-            let n1 = reader1.read(&mut buf1).await.into_diagnostic()?;
-            let n2 = reader2.read(&mut buf2).await.into_diagnostic()?;
-            if n1 != n2 {
-                break Ok(true);
+    /// Directory in depository is mirrored to the live one, files removed from
+    /// the live directory are removed from depository too. Changed files are
+    /// backed up before being overwritten.
+    async fn update(&mut self, entry: &TomlItemEntry, src: &Path, dst: &Stored) -> Result<()> {
+        match entry.kind {
+            ItemEntryKind::File => {
+                let data = tokio::fs::read(src).await.into_diagnostic()?;
+                dst.backup().await?;
+                dst.write(&data).await
             }
-            if n1 == 0 {
-                break Ok(false);
+            ItemEntryKind::Dir => {
+                let diff = diff_dir(src, dst).await?;
+                for file in &diff.changed {
+                    dst.child(file).backup().await?;
+                }
+                for file in diff.added.iter().chain(diff.changed.iter()) {
+                    let data = tokio::fs::read(src.join(file)).await.into_diagnostic()?;
+                    dst.child(file).write(&data).await?;
+                }
+                for file in &diff.removed {
+                    dst.child(file).delete().await?;
+                }
+                // Directories left empty are removed too, the deepest first
+                let dirs: BTreeSet<_> = diff
+                    .removed
+                    .iter()
+                    .flat_map(|file| file.ancestors().skip(1))
+                    .filter(|dir| !dir.as_os_str().is_empty())
+                    .collect();
+                for dir in dirs.into_iter().rev() {
+                    let dir = dst.child(dir);
+                    if dir.list().await?.is_empty() {
+                        dir.delete().await?;
+                    }
+                }
+                dst.storage.create_dir(&dst.path).await
             }
-            if buf1 != buf2 {
-                break Ok(true);
+        }
+    }
+
+    /// Files which only exist in the live directory are kept untouched,
+    /// changed files are backed up before being overwritten
+    async fn install(&mut self, entry: &TomlItemEntry, src: &Stored, dst: &Path) -> Result<()> {
+        match entry.kind {
+            ItemEntryKind::File => write_live(dst, &src.read().await?).await,
+            ItemEntryKind::Dir => {
+                // Compared from the stored side, files added in depository are removed ones
+                let diff = diff_dir(dst, src).await?;
+                for file in diff.removed.iter().chain(diff.changed.iter()) {
+                    write_live(&dst.join(file), &src.child(file).read().await?).await?;
+                }
+                tokio::fs::create_dir_all(dst).await.into_diagnostic()?;
+                Ok(())
             }
         }
     }
+}
 
-    async fn update(&mut self, _: &TomlItemEntry, src: &PathBuf, dst: &PathBuf) -> Result<()> {
-        tokio::fs::create_dir_all(dst.parent().unwrap())
-            .await
-            .into_diagnostic()?;
-        if dst.exists() {
-            backup_file(dst).await?;
+/// Updater which deploys entry as links into depository instead of copies
+///
+/// Hard link can't be made for directory, so every file inside it is linked instead
+struct LinkUpdater;
+
+impl LinkUpdater {
+    /// Whether `live` is already linked to `stored`
+    fn is_linked(entry: &TomlItemEntry, live: &Path, stored: &Path) -> Result<bool> {
+        match entry.link {
+            LinkMode::Symlink => {
+                Ok(live.is_symlink() && std::fs::read_link(live).into_diagnostic()? == stored)
+            }
+            LinkMode::Hardlink => {
+                if live.is_symlink() || !live.exists() || !stored.exists() {
+                    return Ok(false);
+                }
+                match entry.kind {
+                    ItemEntryKind::File => same_file::is_same_file(live, stored).into_diagnostic(),
+                    ItemEntryKind::Dir => {
+                        let files = walk_dir(stored)?;
+                        if files != walk_dir(live)? {
+                            return Ok(false);
+                        }
+                        for file in &files {
+                            if !same_file::is_same_file(live.join(file), stored.join(file))
+                                .into_diagnostic()?
+                            {
+                                return Ok(false);
+                            }
+                        }
+                        Ok(true)
+                    }
+                }
+            }
+            LinkMode::Copy => Ok(false),
         }
-        tokio::fs::copy(src, dst).await.into_diagnostic()?;
-        Ok(())
     }
-    async fn install(&mut self, _: &TomlItemEntry, src: &PathBuf, dst: &PathBuf) -> Result<()> {
-        let parent_dir = dst.parent().unwrap();
+
+    /// Create links at `live` pointing to `stored`, `live` must not exist
+    fn link(entry: &TomlItemEntry, stored: &Path, live: &Path) -> Result<()> {
+        let parent_dir = live.parent().unwrap();
         if !parent_dir.exists() {
-            tokio::fs::create_dir_all(parent_dir)
-                .await
-                .into_diagnostic()?;
+            std::fs::create_dir_all(parent_dir).into_diagnostic()?;
+        }
+        match (entry.link, &entry.kind) {
+            (LinkMode::Symlink, ItemEntryKind::File) => {
+                platform::symlink_file_specify(stored, live).into_diagnostic()
+            }
+            (LinkMode::Symlink, ItemEntryKind::Dir) => {
+                platform::symlink_dir_specify(stored, live).into_diagnostic()
+            }
+            (LinkMode::Hardlink, ItemEntryKind::File) => {
+                std::fs::hard_link(stored, live).into_diagnostic()
+            }
+            (LinkMode::Hardlink, ItemEntryKind::Dir) => {
+                std::fs::create_dir_all(live).into_diagnostic()?;
+                for file in walk_dir(stored)? {
+                    let link = live.join(&file);
+                    std::fs::create_dir_all(link.parent().unwrap()).into_diagnostic()?;
+                    std::fs::hard_link(stored.join(&file), link).into_diagnostic()?;
+                }
+                Ok(())
+            }
+            (LinkMode::Copy, _) => unreachable!(),
+        }
+    }
+
+    /// Replace `live` with links pointing to `stored`
+    ///
+    /// Links are made at a temporary name beside `live` first, so that `live`
+    /// is untouched if they can't be made. Then `live` is backed up and the
+    /// links are moved into its place.
+    async fn replace(entry: &TomlItemEntry, stored: &Path, live: &Path) -> Result<()> {
+        let name = live.file_name().unwrap().to_string_lossy();
+        let temp = live.with_file_name(format!(".{}.dm-link", name));
+        if temp.symlink_metadata().is_ok() {
+            remove_path(&temp)?;
+        }
+        if let Err(err) = Self::link(entry, stored, &temp) {
+            let _ = remove_path(&temp);
+            return Err(err);
+        }
+        if live.symlink_metadata().is_ok() {
+            if let Err(err) = backup_file(live).await {
+                let _ = remove_path(&temp);
+                return Err(err);
+            }
+        }
+        std::fs::rename(&temp, live).into_diagnostic()
+    }
+}
+
+#[async_trait(?Send)]
+impl Updater for LinkUpdater {
+    /// 检查链接是否仍然指向仓库
+    async fn is_diff(&mut self, entry: &TomlItemEntry, src: &Path, dst: &Stored) -> Result<bool> {
+        Ok(!Self::is_linked(entry, src, &dst.local_path()?)?)
+    }
+
+    /// Nothing to do if the link is intact, otherwise the live content is taken
+    /// into depository and the link is made again
+    async fn update(&mut self, entry: &TomlItemEntry, src: &Path, dst: &Stored) -> Result<()> {
+        let stored = dst.local_path()?;
+        if Self::is_linked(entry, src, &stored)? {
+            return Ok(());
         }
+        NormalUpdater.update(entry, src, dst).await?;
+        Self::replace(entry, &stored, src).await
+    }
 
-        if dst.exists() {
-            backup_file(dst).await?;
+    async fn install(&mut self, entry: &TomlItemEntry, src: &Stored, dst: &Path) -> Result<()> {
+        let stored = src.local_path()?;
+        if Self::is_linked(entry, dst, &stored)? {
+            return Ok(());
         }
-        tokio::fs::copy(src, dst).await.into_diagnostic()?;
+        Self::replace(entry, &stored, dst).await
+    }
+}
 
+/// Compare two streams byte by byte
+fn is_stream_diff<R1: Read, R2: Read>(mut src: R1, mut dst: R2) -> Result<bool> {
+    let read_full = |reader: &mut dyn Read, buf: &mut [u8]| -> Result<usize> {
+        let mut len = 0;
+        while len < buf.len() {
+            let n = reader.read(&mut buf[len..]).into_diagnostic()?;
+            if n == 0 {
+                break;
+            }
+            len += n;
+        }
+        Ok(len)
+    };
+    let mut buf1 = [0; 1024];
+    let mut buf2 = [0; 1024];
+    loop {
+        let n1 = read_full(&mut src, &mut buf1)?;
+        let n2 = read_full(&mut dst, &mut buf2)?;
+        if n1 != n2 || buf1[..n1] != buf2[..n2] {
+            break Ok(true);
+        }
+        if n1 == 0 {
+            break Ok(false);
+        }
+    }
+}
+
+/// Open the stored file of entry as a stream of plain content, decrypting
+/// and decompressing it if needed
+async fn open_stored(entry: &TomlItemEntry, stored: &Stored) -> Result<Box<dyn Read>> {
+    let mut reader: Box<dyn Read> = Box::new(Cursor::new(stored.read().await?));
+    if entry.encrypt {
+        reader = Box::new(Keys::load().await?.decrypt(reader)?);
+    }
+    if entry.compress {
+        reader = Box::new(zstd::Decoder::new(reader).into_diagnostic()?);
+    }
+    Ok(reader)
+}
+
+/// Write plain content into the stored file of entry, compressing and
+/// encrypting it if needed
+async fn write_stored<F>(entry: &TomlItemEntry, stored: &Stored, write: F) -> Result<()>
+where
+    F: FnOnce(&mut dyn Write) -> Result<()>,
+{
+    let keys = if entry.encrypt {
+        Some(Keys::load().await?)
+    } else {
+        None
+    };
+    let write_compressed = |output: &mut dyn Write| -> Result<()> {
+        if entry.compress {
+            let mut encoder = zstd::Encoder::new(output, 0).into_diagnostic()?;
+            write(&mut encoder)?;
+            encoder.finish().into_diagnostic()?;
+            Ok(())
+        } else {
+            write(output)
+        }
+    };
+    let mut data = vec![];
+    match keys {
+        Some(keys) => {
+            let mut writer = keys.encrypt(&mut data)?;
+            write_compressed(&mut writer)?;
+            writer.finish().into_diagnostic()?;
+        }
+        None => write_compressed(&mut data)?,
+    }
+    stored.write(&data).await
+}
+
+/// Replace the stored file of entry with plain content `data`, the old one is backed up
+pub(super) async fn write_stored_file(
+    entry: &TomlItemEntry,
+    stored: &Stored,
+    data: &[u8],
+) -> Result<()> {
+    stored.backup().await?;
+    write_stored(entry, stored, |output| {
+        output.write_all(data).into_diagnostic()
+    })
+    .await
+}
+
+/// Path of `item` relative to the directory packed in tarball `stored`
+///
+/// Absolute paths and paths with `..` are refused, as they could point out
+/// of the directory.
+fn archive_item_path<R: Read>(item: &tar::Entry<R>, stored: &Stored) -> Result<PathBuf> {
+    let path = item.path().into_diagnostic()?.to_path_buf();
+    if !path
+        .components()
+        .all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+    {
+        Err(DMError::StorageError {
+            msg: t!(
+                "error.storage.unsafe_path.msg",
+                archive = &stored.to_string(),
+                path = &path.to_string_lossy()
+            ),
+            advice: Some(t!("error.storage.unsafe_path.advice")),
+        })
+        .into_diagnostic()?;
+    }
+    Ok(path)
+}
+
+/// Compare directory `live` with the tarball `archive`, which is the stored file `stored`
+fn diff_archive(live: &Path, archive: Option<Box<dyn Read>>, stored: &Stored) -> Result<DirDiff> {
+    let live_files = walk_dir(live)?;
+    let mut stored_files = BTreeSet::new();
+    let mut diff = DirDiff::default();
+    if let Some(archive) = archive {
+        for item in tar::Archive::new(archive).entries().into_diagnostic()? {
+            let item = item.into_diagnostic()?;
+            if item.header().entry_type().is_dir() {
+                continue;
+            }
+            let path = archive_item_path(&item, stored)?;
+            if live_files.contains(&path)
+                && is_stream_diff(
+                    item,
+                    std::fs::File::open(live.join(&path)).into_diagnostic()?,
+                )?
+            {
+                diff.changed.push(path.clone());
+            }
+            stored_files.insert(path);
+        }
+    }
+    diff.added = live_files.difference(&stored_files).cloned().collect();
+    diff.removed = stored_files.difference(&live_files).cloned().collect();
+    Ok(diff)
+}
+
+/// Whether the stored file of entry is packed, i.e. compressed or encrypted
+fn is_packed(entry: &TomlItemEntry) -> bool {
+    entry.compress || entry.encrypt
+}
+
+/// Read the plain content of stored file, unpacking it if needed
+///
+/// Returns empty content if the file does not exist
+pub(super) async fn read_stored_file(entry: &TomlItemEntry, stored: &Stored) -> Result<Vec<u8>> {
+    if !stored.exists().await? {
+        Ok(vec![])
+    } else if is_packed(entry) {
+        let mut data = vec![];
+        open_stored(entry, stored)
+            .await?
+            .read_to_end(&mut data)
+            .into_diagnostic()?;
+        Ok(data)
+    } else {
+        stored.read().await
+    }
+}
+
+/// Compare live directory with the stored one, which may be packed
+pub(super) async fn diff_stored_dir(
+    entry: &TomlItemEntry,
+    live: &Path,
+    stored: &Stored,
+) -> Result<DirDiff> {
+    if !is_packed(entry) {
+        diff_dir(live, stored).await
+    } else if stored.exists().await? {
+        diff_archive(live, Some(open_stored(entry, stored).await?), stored)
+    } else {
+        diff_archive(live, None, stored)
+    }
+}
+
+/// Updater which stores entry compressed by zstd and/or encrypted by age,
+/// directory is packed into a tarball first
+///
+/// Nothing in plain is written to depository or temporary files.
+struct PackedUpdater;
+
+#[async_trait(?Send)]
+impl Updater for PackedUpdater {
+    /// 与解包后的数据流比较
+    async fn is_diff(&mut self, entry: &TomlItemEntry, src: &Path, dst: &Stored) -> Result<bool> {
+        match entry.kind {
+            ItemEntryKind::File => is_stream_diff(
+                std::fs::File::open(src).into_diagnostic()?,
+                open_stored(entry, dst).await?,
+            ),
+            ItemEntryKind::Dir => Ok(!diff_stored_dir(entry, src, dst).await?.is_empty()),
+        }
+    }
+
+    async fn update(&mut self, entry: &TomlItemEntry, src: &Path, dst: &Stored) -> Result<()> {
+        dst.backup().await?;
+        write_stored(entry, dst, |output| match entry.kind {
+            ItemEntryKind::File => {
+                std::io::copy(&mut std::fs::File::open(src).into_diagnostic()?, output)
+                    .into_diagnostic()?;
+                Ok(())
+            }
+            ItemEntryKind::Dir => {
+                let mut builder = tar::Builder::new(output);
+                for file in walk_dir(src)? {
+                    builder
+                        .append_path_with_name(src.join(&file), &file)
+                        .into_diagnostic()?;
+                }
+                builder.finish().into_diagnostic()
+            }
+        })
+        .await
+    }
+
+    /// Like normal updater, files which only exist in the live directory are kept
+    async fn install(&mut self, entry: &TomlItemEntry, src: &Stored, dst: &Path) -> Result<()> {
+        match entry.kind {
+            ItemEntryKind::File => write_live(dst, &read_stored_file(entry, src).await?).await?,
+            ItemEntryKind::Dir => {
+                let mut archive = tar::Archive::new(open_stored(entry, src).await?);
+                for item in archive.entries().into_diagnostic()? {
+                    let mut item = item.into_diagnostic()?;
+                    if item.header().entry_type().is_dir() {
+                        continue;
+                    }
+                    let target = dst.join(archive_item_path(&item, src)?);
+                    let mut data = vec![];
+                    item.read_to_end(&mut data).into_diagnostic()?;
+                    if target.exists() {
+                        if std::fs::read(&target).into_diagnostic()? == data {
+                            continue;
+                        }
+                        backup_file(&target).await?;
+                    }
+                    tokio::fs::create_dir_all(target.parent().unwrap())
+                        .await
+                        .into_diagnostic()?;
+                    tokio::fs::write(&target, data).await.into_diagnostic()?;
+                }
+                tokio::fs::create_dir_all(dst).await.into_diagnostic()?;
+            }
+        }
         Ok(())
     }
 }
 
-pub fn construct_updater(entry: &TomlItemEntry) -> Result<Box<dyn Updater>> {
+/// Updater of template entry, the stored file is rendered when installing
+///
+/// If the live file is edited after rendering, updating the template needs
+/// the user to decide whether to merge the edits back into the template.
+struct TemplateUpdater<'a> {
+    group_name: String,
+    variables: BTreeMap<String, String>,
+    ui_handle: &'a dyn Ui,
+}
+
+impl TemplateUpdater<'_> {
+    async fn render(&self, entry: &TomlItemEntry, stored: &Stored) -> Result<String> {
+        render_stored_template(entry, &self.group_name, &self.variables, stored).await
+    }
+
+    async fn write_template(entry: &TomlItemEntry, stored: &Stored, data: &[u8]) -> Result<()> {
+        stored.backup().await?;
+        write_stored(entry, stored, |output| {
+            output.write_all(data).into_diagnostic()
+        })
+        .await
+    }
+}
+
+#[async_trait(?Send)]
+impl Updater for TemplateUpdater<'_> {
+    /// 与渲染结果比较
+    async fn is_diff(&mut self, entry: &TomlItemEntry, src: &Path, dst: &Stored) -> Result<bool> {
+        let rendered = self.render(entry, dst).await?;
+        Ok(tokio::fs::read(src).await.into_diagnostic()? != rendered.as_bytes())
+    }
+
+    async fn update(&mut self, entry: &TomlItemEntry, src: &Path, dst: &Stored) -> Result<()> {
+        let live = tokio::fs::read(src).await.into_diagnostic()?;
+        if !dst.exists().await? {
+            return Self::write_template(entry, dst, &live).await;
+        }
+        let rendered = self.render(entry, dst).await?;
+        if live == rendered.as_bytes() {
+            return Ok(());
+        }
+
+        let edited = DMError::TemplateError {
+            msg: t!("error.template.edited.msg", path = &src.to_string_lossy()),
+            advice: Some(t!("error.template.edited.advice")),
+        };
+        let options = [
+            t!("template.update.abort"),
+            t!("template.update.merge"),
+            t!("template.update.replace"),
+        ];
+        let choice = self.ui_handle.choose(
+            Some(&t!("template.update.prompt", path = &src.to_string_lossy())),
+            options.iter().map(String::as_str).collect(),
+        )?;
+        match choice {
+            1 => {
+                let live = String::from_utf8(live)
+                    .map_err(|_| DMError::TemplateError {
+                        msg: t!("error.template.not_text.msg"),
+                        advice: None,
+                    })
+                    .into_diagnostic()?;
+                let source =
+                    String::from_utf8(read_stored_file(entry, dst).await?).into_diagnostic()?;
+                let merged = merge::merge(&rendered, &live, &source);
+                if !merged.is_clean() {
+                    Err(DMError::TemplateError {
+                        msg: t!(
+                            "error.template.merge_conflict.msg",
+                            count = &merged.conflicts.to_string()
+                        ),
+                        advice: Some(t!("error.template.edited.advice")),
+                    })
+                    .into_diagnostic()?;
+                }
+                Self::write_template(entry, dst, merged.text.as_bytes()).await
+            }
+            2 => Self::write_template(entry, dst, &live).await,
+            _ => Err(edited).into_diagnostic(),
+        }
+    }
+
+    async fn install(&mut self, entry: &TomlItemEntry, src: &Stored, dst: &Path) -> Result<()> {
+        let rendered = self.render(entry, src).await?;
+        if dst.exists() && tokio::fs::read(dst).await.into_diagnostic()? == rendered.as_bytes() {
+            return Ok(());
+        }
+        write_live(dst, rendered.as_bytes()).await
+    }
+}
+
+/// Render the stored template of entry with variables of its group
+pub(super) async fn render_stored_template(
+    entry: &TomlItemEntry,
+    group_name: &str,
+    variables: &BTreeMap<String, String>,
+    stored: &Stored,
+) -> Result<String> {
+    let source = read_stored_file(entry, stored).await?;
+    template::render(&source, group_name, variables).await
+}
+
+pub(super) fn construct_updater<'a>(
+    entry: &TomlItemEntry,
+    group: &TomlGroup,
+    ui_handle: &'a dyn Ui,
+    storage: &Rc<dyn Storage>,
+) -> Result<Box<dyn Updater + 'a>> {
     if entry.manaul {
-        Ok(Box::new(ManualUpdater))
+        Ok(Box::new(ManualUpdater {
+            group_name: group.name.clone(),
+            storage: storage.clone(),
+            ui_handle,
+        }))
+    } else if entry.template {
+        Ok(Box::new(TemplateUpdater {
+            group_name: group.name.clone(),
+            variables: group.variables.clone(),
+            ui_handle,
+        }))
+    } else if entry.link != LinkMode::Copy {
+        Ok(Box::new(LinkUpdater))
+    } else if is_packed(entry) {
+        Ok(Box::new(PackedUpdater))
     } else {
         Ok(Box::new(NormalUpdater))
     }
@@ -1,9 +1,16 @@
 mod uicli;
 
+use std::process::ExitCode;
+
 use dm::config;
-use miette::{IntoDiagnostic, Result};
+use miette::IntoDiagnostic;
 rust_i18n::i18n!("locales");
 
+/// Exit with `code` without reporting an error, the command has printed its result
+#[derive(Debug, thiserror::Error, miette::Diagnostic)]
+#[error("exit with code {0}")]
+struct Exit(u8);
+
 async fn apply_locales() {
     let locale = &config::CONFIG.lock().await.locale;
     rust_i18n::set_locale(locale);
@@ -16,7 +23,9 @@ mod cli {
     pub mod local {
         pub mod profile {
             use clap::{arg, ArgAction, ArgMatches, Command};
+            use dm::local::{TomlProfileRemote, TomlProfileRule};
             use miette::{Context, Result};
+            use owo_colors::OwoColorize;
             use rust_i18n::t;
 
             use crate::uicli;
@@ -34,7 +43,51 @@ mod cli {
                         Command::new("use")
                             .alias("u")
                             .about(t!("profile.use.help"))
-                            .arg(arg!(<NAME>).help(t!("profile.use.arg_name"))),
+                            .arg(
+                                arg!([NAME])
+                                    .help(t!("profile.use.arg_name"))
+                                    .required_unless_present("auto"),
+                            )
+                            .arg(
+                                arg!(-a - -auto)
+                                    .help(t!("profile.use.arg_auto"))
+                                    .action(ArgAction::SetTrue)
+                                    .conflicts_with("NAME"),
+                            ),
+                    )
+                    .subcommand(
+                        Command::new("rule")
+                            .about(t!("profile.rule.help"))
+                            .arg(arg!(<NAME>).help(t!("profile.rule.arg_name")))
+                            .arg(
+                                arg!(--hostname <PATTERN>).help(t!("profile.rule.arg_hostname")),
+                            )
+                            .arg(arg!(--os <OS>).help(t!("profile.rule.arg_os")))
+                            .arg(arg!(--arch <ARCH>).help(t!("profile.rule.arg_arch")))
+                            .arg(arg!(--env <VAR>).help(t!("profile.rule.arg_env")))
+                            .arg(
+                                arg!(--clear)
+                                    .help(t!("profile.rule.arg_clear"))
+                                    .action(ArgAction::SetTrue)
+                                    .conflicts_with_all(["hostname", "os", "arch", "env"]),
+                            ),
+                    )
+                    .subcommand(
+                        Command::new("remote")
+                            .about(t!("profile.remote.help"))
+                            .arg(arg!(<NAME>).help(t!("profile.remote.arg_name")))
+                            .arg(
+                                arg!([URL])
+                                    .help(t!("profile.remote.arg_url"))
+                                    .required_unless_present("clear"),
+                            )
+                            .arg(arg!(--branch <BRANCH>).help(t!("profile.remote.arg_branch")))
+                            .arg(
+                                arg!(--clear)
+                                    .help(t!("profile.remote.arg_clear"))
+                                    .action(ArgAction::SetTrue)
+                                    .conflicts_with_all(["URL", "branch"]),
+                            ),
                     )
                     .subcommand(
                         Command::new("delete")
@@ -47,6 +100,78 @@ mod cli {
                                     .action(ArgAction::SetTrue),
                             ),
                     )
+                    .subcommand(
+                        Command::new("attach")
+                            .about(t!("profile.attach.help"))
+                            .arg(arg!(<NAME>).help(t!("profile.attach.arg_name")))
+                            .arg(arg!(<GROUP>).help(t!("profile.attach.arg_group"))),
+                    )
+                    .subcommand(
+                        Command::new("detach")
+                            .about(t!("profile.detach.help"))
+                            .arg(arg!(<NAME>).help(t!("profile.detach.arg_name")))
+                            .arg(arg!(<GROUP>).help(t!("profile.detach.arg_group"))),
+                    )
+                    .subcommand(
+                        Command::new("inherit")
+                            .about(t!("profile.inherit.help"))
+                            .arg(arg!(<NAME>).help(t!("profile.inherit.arg_name")))
+                            .arg(arg!(<PARENT>).help(t!("profile.inherit.arg_parent")))
+                            .arg(
+                                arg!(-r - -remove)
+                                    .help(t!("profile.inherit.arg_remove"))
+                                    .action(ArgAction::SetTrue),
+                            ),
+                    )
+                    .subcommand(
+                        Command::new("show")
+                            .alias("s")
+                            .about(t!("profile.show.help"))
+                            .arg(arg!([NAME]).help(t!("profile.show.arg_name"))),
+                    )
+            }
+
+            async fn exec_show(matches: &ArgMatches) -> Result<()> {
+                let name = matches.get_one::<String>("NAME").cloned();
+                let profile = dm::local::profile::show_profile(name).await?;
+                if profile.using {
+                    println!(
+                        "{} {}",
+                        profile.name.bold(),
+                        t!("profile.show.using").dimmed()
+                    );
+                } else {
+                    println!("{}", profile.name.bold());
+                }
+                for rule in &profile.rule {
+                    println!("{}", t!("profile.show.rule", rule = &rule.to_string()));
+                }
+                if let Some(remote) = &profile.remote {
+                    println!(
+                        "{}",
+                        t!("profile.show.remote", remote = &remote.to_string())
+                    );
+                }
+                if !profile.parent.is_empty() {
+                    println!(
+                        "{}",
+                        t!("profile.show.parent", parent = &profile.parent.join(", "))
+                    );
+                }
+                for group in &profile.groups {
+                    let files = t!("profile.show.files", count = &group.files.to_string());
+                    if group.origin == profile.name {
+                        println!("\t{}\t{}", group.name, files);
+                    } else {
+                        println!(
+                            "\t{}\t{}\t{}",
+                            group.name,
+                            files,
+                            t!("profile.show.inherited", origin = &group.origin).dimmed()
+                        );
+                    }
+                }
+                Ok(())
             }
 
             async fn exec(matches: &ArgMatches) -> Result<()> {
@@ -56,16 +181,67 @@ mod cli {
                         .await
                         .wrap_err(t!("error.ctx.cmd.profile.create"))
                 } else if let Some(matches) = matches.subcommand_matches("use") {
-                    let name = matches.get_one::<String>("NAME").unwrap().clone();
+                    let name = matches.get_one::<String>("NAME").cloned();
                     dm::local::profile::use_profile(name)
                         .await
                         .wrap_err(t!("error.ctx.cmd.profile.checkout"))
+                } else if let Some(matches) = matches.subcommand_matches("rule") {
+                    let name = matches.get_one::<String>("NAME").unwrap().clone();
+                    let rule = if matches.get_flag("clear") {
+                        None
+                    } else {
+                        Some(TomlProfileRule {
+                            hostname: matches.get_one::<String>("hostname").cloned(),
+                            os: matches.get_one::<String>("os").cloned(),
+                            arch: matches.get_one::<String>("arch").cloned(),
+                            env: matches.get_one::<String>("env").cloned(),
+                        })
+                    };
+                    dm::local::profile::set_rule(name, rule)
+                        .await
+                        .wrap_err(t!("error.ctx.cmd.profile.rule"))
+                } else if let Some(matches) = matches.subcommand_matches("remote") {
+                    let name = matches.get_one::<String>("NAME").unwrap().clone();
+                    let remote = matches
+                        .get_one::<String>("URL")
+                        .map(|url| TomlProfileRemote {
+                            url: url.clone(),
+                            branch: matches.get_one::<String>("branch").cloned(),
+                        });
+                    dm::local::profile::set_remote(name, remote)
+                        .await
+                        .wrap_err(t!("error.ctx.cmd.profile.remote"))
                 } else if let Some(matches) = matches.subcommand_matches("delete") {
                     let name = matches.get_one::<String>("NAME").unwrap().clone();
                     let confirm = matches.get_flag("yes");
                     dm::local::profile::delete(&uicli::Cli, name, confirm)
                         .await
                         .wrap_err(t!("error.ctx.cmd.profile.delete"))
+                } else if let Some(matches) = matches.subcommand_matches("attach") {
+                    let name = matches.get_one::<String>("NAME").unwrap().clone();
+                    let group = matches.get_one::<String>("GROUP").unwrap().clone();
+                    dm::local::profile::attach_group(name, group)
+                        .await
+                        .wrap_err(t!("error.ctx.cmd.profile.attach"))
+                } else if let Some(matches) = matches.subcommand_matches("detach") {
+                    let name = matches.get_one::<String>("NAME").unwrap().clone();
+                    let group = matches.get_one::<String>("GROUP").unwrap().clone();
+                    dm::local::profile::detach_group(name, group)
+                        .await
+                        .wrap_err(t!("error.ctx.cmd.profile.detach"))
+                } else if let Some(matches) = matches.subcommand_matches("inherit") {
+                    let name = matches.get_one::<String>("NAME").unwrap().clone();
+                    let parent = matches.get_one::<String>("PARENT").unwrap().clone();
+                    if matches.get_flag("remove") {
+                        dm::local::profile::disinherit(name, parent).await
+                    } else {
+                        dm::local::profile::inherit(name, parent).await
+                    }
+                    .wrap_err(t!("error.ctx.cmd.profile.inherit"))
+                } else if let Some(matches) = matches.subcommand_matches("show") {
+                    exec_show(matches)
+                        .await
+                        .wrap_err(t!("error.ctx.cmd.profile.show"))
                 } else {
                     Ok(())
                 }
@@ -78,20 +254,75 @@ mod cli {
         pub mod group {
             use clap::{arg, ArgAction, ArgMatches, Command};
             use miette::{Context, Result};
+            use owo_colors::OwoColorize;
             use rust_i18n::t;
 
+            use crate::uicli;
+
             pub fn args() -> Command {
-                Command::new("group").about(t!("group.about")).subcommand(
-                    Command::new("create")
-                        .alias("c")
-                        .about(t!("group.create.help"))
-                        .arg(arg!(<NAME>).help(t!("group.create.arg_name")))
-                        .arg(
-                            arg!(-n - -nouse)
-                                .help(t!("group.create.arg_nouse"))
-                                .action(ArgAction::SetTrue),
-                        ),
-                )
+                Command::new("group")
+                    .about(t!("group.about"))
+                    .subcommand(
+                        Command::new("create")
+                            .alias("c")
+                            .about(t!("group.create.help"))
+                            .arg(arg!(<NAME>).help(t!("group.create.arg_name")))
+                            .arg(
+                                arg!(-n - -nouse)
+                                    .help(t!("group.create.arg_nouse"))
+                                    .action(ArgAction::SetTrue),
+                            ),
+                    )
+                    .subcommand(
+                        Command::new("delete")
+                            .aliases(["d", "rm"])
+                            .about(t!("group.delete.help"))
+                            .arg(arg!(<NAME>).help(t!("group.delete.arg_name")))
+                            .arg(
+                                arg!(-y - -yes)
+                                    .help(t!("group.delete.arg_yes"))
+                                    .action(ArgAction::SetTrue),
+                            ),
+                    )
+                    .subcommand(
+                        Command::new("rename")
+                            .alias("mv")
+                            .about(t!("group.rename.help"))
+                            .arg(arg!(<NAME>).help(t!("group.rename.arg_name")))
+                            .arg(arg!(<NEW_NAME>).help(t!("group.rename.arg_new_name"))),
+                    )
+                    .subcommand(
+                        Command::new("describe")
+                            .about(t!("group.describe.help"))
+                            .arg(arg!(<NAME>).help(t!("group.describe.arg_name")))
+                            .arg(
+                                arg!(<DESCRIPTION>).help(t!("group.describe.arg_description")),
+                            ),
+                    )
+                    .subcommand(
+                        Command::new("list")
+                            .alias("ls")
+                            .about(t!("group.list.help")),
+                    )
+            }
+
+            async fn exec_list() -> Result<()> {
+                for group in dm::local::group::list_group().await? {
+                    println!(
+                        "{}\t{}",
+                        group.name.bold(),
+                        t!(
+                            "group.list.summary",
+                            files = &group.files.to_string(),
+                            profiles = &group.profiles.join(", ")
+                        )
+                        .dimmed()
+                    );
+                    if let Some(description) = &group.description {
+                        println!("\t{}", description);
+                    }
+                }
+                Ok(())
             }
 
             async fn exec(matches: &ArgMatches) -> Result<()> {
@@ -101,6 +332,26 @@ mod cli {
                     dm::local::group::create_group(name, no_use)
                         .await
                         .wrap_err(t!("error.ctx.cmd.group.create"))
+                } else if let Some(matches) = matches.subcommand_matches("delete") {
+                    let name = matches.get_one::<String>("NAME").unwrap().clone();
+                    let confirm = matches.get_flag("yes");
+                    dm::local::group::delete_group(&uicli::Cli, name, confirm)
+                        .await
+                        .wrap_err(t!("error.ctx.cmd.group.delete"))
+                } else if let Some(matches) = matches.subcommand_matches("rename") {
+                    let name = matches.get_one::<String>("NAME").unwrap().clone();
+                    let new_name = matches.get_one::<String>("NEW_NAME").unwrap().clone();
+                    dm::local::group::rename_group(name, new_name)
+                        .await
+                        .wrap_err(t!("error.ctx.cmd.group.rename"))
+                } else if let Some(matches) = matches.subcommand_matches("describe") {
+                    let name = matches.get_one::<String>("NAME").unwrap().clone();
+                    let description = matches.get_one::<String>("DESCRIPTION").unwrap().clone();
+                    dm::local::group::describe_group(name, description)
+                        .await
+                        .wrap_err(t!("error.ctx.cmd.group.describe"))
+                } else if matches.subcommand_matches("list").is_some() {
+                    exec_list().await.wrap_err(t!("error.ctx.cmd.group.list"))
                 } else {
                     Ok(())
                 }
@@ -115,6 +366,10 @@ mod cli {
             use std::path::PathBuf;
 
             use clap::{arg, value_parser, ArgAction, ArgMatches, Command};
+            use dm::local::{
+                file::{AddOptions, ManualScripts, RemoveOptions},
+                LinkMode,
+            };
             use miette::{Context, Result};
             use rust_i18n::t;
 
@@ -123,22 +378,44 @@ mod cli {
             async fn exec_add(matches: &ArgMatches) -> Result<()> {
                 let path = matches.get_one::<PathBuf>("PATH").unwrap();
                 let group_name = matches.get_one::<String>("GROUP").unwrap();
-                let try_recongize = matches.get_flag("recongize");
-                let manual_install = matches.get_flag("manual");
-
-                dm::local::file::add_file(
-                    &uicli::Cli,
-                    path,
-                    group_name,
-                    try_recongize,
-                    manual_install,
-                )
-                .await
+                let options = AddOptions {
+                    try_recongize: matches.get_flag("recongize"),
+                    manaul_install: matches.get_flag("manual"),
+                    scripts: ManualScripts {
+                        os: matches.get_one::<String>("script-os").cloned(),
+                        install: matches.get_one::<PathBuf>("install-script").cloned(),
+                        update: matches.get_one::<PathBuf>("update-script").cloned(),
+                        diff: matches.get_one::<PathBuf>("diff-script").cloned(),
+                    },
+                    link: if matches.get_flag("symbolic") {
+                        LinkMode::Symlink
+                    } else if matches.get_flag("link") {
+                        LinkMode::Hardlink
+                    } else {
+                        LinkMode::Copy
+                    },
+                    compress: matches.get_flag("compress"),
+                    encrypt: matches.get_flag("encrypt"),
+                    template: matches.get_flag("template"),
+                };
+
+                dm::local::file::add_file(&uicli::Cli, path, group_name, options).await
+            }
+            async fn exec_remove(matches: &ArgMatches) -> Result<()> {
+                let path = matches.get_one::<PathBuf>("PATH").unwrap();
+                let group_name = matches.get_one::<String>("GROUP").unwrap();
+                let options = RemoveOptions {
+                    delete_stored: matches.get_flag("delete-stored"),
+                    delete_installed: matches.get_flag("delete-installed"),
+                };
+
+                dm::local::file::remove_file(&uicli::Cli, path, group_name, options).await
             }
             async fn exec_update(matches: &ArgMatches) -> Result<()> {
                 let group_name = matches.get_one::<String>("GROUP").unwrap();
+                let force = matches.get_flag("force");
 
-                dm::local::group::update_group(&uicli::Cli, group_name.to_owned()).await
+                dm::local::group::update_group(&uicli::Cli, group_name.to_owned(), force).await
             }
             pub async fn try_match_add(matches: &ArgMatches) -> Option<Result<()>> {
                 Some(
@@ -147,6 +424,13 @@ mod cli {
                         .wrap_err(t!("error.ctx.cmd.add")),
                 )
             }
+            pub async fn try_match_remove(matches: &ArgMatches) -> Option<Result<()>> {
+                Some(
+                    exec_remove(matches.subcommand_matches("remove")?)
+                        .await
+                        .wrap_err(t!("error.ctx.cmd.remove")),
+                )
+            }
             pub async fn try_match_update(matches: &ArgMatches) -> Option<Result<()>> {
                 Some(
                     exec_update(matches.subcommand_matches("update")?)
@@ -155,11 +439,64 @@ mod cli {
                 )
             }
 
+            async fn exec_install(matches: &ArgMatches) -> Result<()> {
+                let group_name = matches.get_one::<String>("GROUP").cloned();
+                let force = matches.get_flag("force");
+
+                dm::local::group::install_group(&uicli::Cli, group_name, force).await
+            }
+            pub async fn try_match_install(matches: &ArgMatches) -> Option<Result<()>> {
+                Some(
+                    exec_install(matches.subcommand_matches("install")?)
+                        .await
+                        .wrap_err(t!("error.ctx.cmd.install")),
+                )
+            }
+
+            pub fn args_install() -> Command {
+                Command::new("install")
+                    .alias("in")
+                    .about(t!("file.install.help"))
+                    .arg(arg!([GROUP]).help(t!("file.install.arg_name")))
+                    .arg(
+                        arg!(-f --force)
+                            .help(t!("file.install.arg_force"))
+                            .action(ArgAction::SetTrue),
+                    )
+            }
+
             pub fn args_update() -> Command {
                 Command::new("update")
                     .alias("u")
                     .about(t!("file.update.help"))
                     .arg(arg!(<GROUP>).help(t!("file.update.arg_name")))
+                    .arg(
+                        arg!(-f --force)
+                            .help(t!("file.update.arg_force"))
+                            .action(ArgAction::SetTrue),
+                    )
+            }
+
+            pub fn args_remove() -> Command {
+                Command::new("remove")
+                    .alias("rm")
+                    .about(t!("file.remove.help"))
+                    .arg(arg!(<GROUP>).help(t!("file.remove.arg_name")))
+                    .arg(
+                        arg!(<PATH>)
+                            .help(t!("file.remove.arg_path"))
+                            .value_parser(value_parser!(PathBuf)),
+                    )
+                    .arg(
+                        arg!(-d - -"delete-stored")
+                            .help(t!("file.remove.arg_delete_stored"))
+                            .action(ArgAction::SetTrue),
+                    )
+                    .arg(
+                        arg!(--"delete-installed")
+                            .help(t!("file.remove.arg_delete_installed"))
+                            .action(ArgAction::SetTrue),
+                    )
             }
 
             pub fn args_add() -> Command {
@@ -175,7 +512,8 @@ mod cli {
                     .arg(
                         arg!(-c - -compress)
                             .help(t!("file.add.arg_compress"))
-                            .action(ArgAction::SetTrue),
+                            .action(ArgAction::SetTrue)
+                            .conflicts_with_all(["symbolic", "link"]),
                     )
                     .arg(
                         arg!(-s - -symbolic)
@@ -190,13 +528,43 @@ mod cli {
                     .arg(
                         arg!(-e - -encrypt)
                             .help(t!("file.add.arg_encrypt"))
-                            .action(ArgAction::SetTrue),
+                            .action(ArgAction::SetTrue)
+                            .conflicts_with_all(["symbolic", "link"]),
+                    )
+                    .arg(
+                        arg!(-t - -template)
+                            .help(t!("file.add.arg_template"))
+                            .action(ArgAction::SetTrue)
+                            .conflicts_with_all(["symbolic", "link", "manual"]),
                     )
                     .arg(
                         arg!(-m - -manual)
                             .help(t!("file.add.arg_manual_install"))
                             .action(ArgAction::SetTrue),
                     )
+                    .arg(
+                        arg!(--"install-script" <SCRIPT>)
+                            .help(t!("file.add.arg_install_script"))
+                            .value_parser(value_parser!(PathBuf))
+                            .requires("manual"),
+                    )
+                    .arg(
+                        arg!(--"update-script" <SCRIPT>)
+                            .help(t!("file.add.arg_update_script"))
+                            .value_parser(value_parser!(PathBuf))
+                            .requires("manual"),
+                    )
+                    .arg(
+                        arg!(--"diff-script" <SCRIPT>)
+                            .help(t!("file.add.arg_diff_script"))
+                            .value_parser(value_parser!(PathBuf))
+                            .requires("manual"),
+                    )
+                    .arg(
+                        arg!(--"script-os" <OS>)
+                            .help(t!("file.add.arg_script_os"))
+                            .requires("manual"),
+                    )
                     .arg(
                         arg!(-r - -recongize)
                             .help(t!("file.add.arg_recongize"))
@@ -216,7 +584,7 @@ mod cli {
         }
 
         async fn exec(_matches: &ArgMatches) -> Result<()> {
-            println!("{}", dm::info::all_info()?);
+            println!("{}", dm::info::all_info().await?);
             Ok(())
         }
 
@@ -228,6 +596,446 @@ mod cli {
             )
         }
     }
+    pub mod status {
+        use clap::{ArgMatches, Command};
+        use dm::local::status::EntryStatus;
+        use miette::{Context, Result};
+        use owo_colors::OwoColorize;
+        use rust_i18n::t;
+
+        pub fn args() -> Command {
+            Command::new("status").alias("st").about(t!("status.help"))
+        }
+
+        async fn exec(_matches: &ArgMatches) -> Result<()> {
+            let reports = dm::local::status::status(&crate::uicli::Cli).await?;
+            for group in &reports {
+                println!("{}", group.name.bold());
+                for entry in &group.entries {
+                    let label = match entry.status {
+                        EntryStatus::Clean => t!("status.clean").green().to_string(),
+                        EntryStatus::LocalModified => {
+                            t!("status.local_modified").yellow().to_string()
+                        }
+                        EntryStatus::DepositoryModified => {
+                            t!("status.depository_modified").yellow().to_string()
+                        }
+                        EntryStatus::Conflicted => t!("status.conflicted").red().to_string(),
+                        EntryStatus::MissingOnDisk => {
+                            t!("status.missing_on_disk").red().to_string()
+                        }
+                        EntryStatus::MissingInDepository => {
+                            t!("status.missing_in_depository").red().to_string()
+                        }
+                        EntryStatus::NoInstallPath => t!("status.no_install_path").dimmed().to_string(),
+                    };
+                    match &entry.live {
+                        Some(live) => {
+                            println!("\t{}\t{} -> {}", label, entry.path, live.to_string_lossy())
+                        }
+                        None => println!("\t{}\t{}", label, entry.path),
+                    }
+                }
+            }
+            if reports.iter().any(|group| group.is_drift()) {
+                return Err(crate::Exit(1).into());
+            }
+            Ok(())
+        }
+
+        pub async fn try_match(matches: &ArgMatches) -> Option<Result<()>> {
+            Some(
+                exec(matches.subcommand_matches("status")?)
+                    .await
+                    .wrap_err(t!("error.ctx.cmd.status")),
+            )
+        }
+    }
+    pub mod diff {
+        use std::path::PathBuf;
+
+        use clap::{arg, value_parser, ArgMatches, Command};
+        use dm::local::diff::DiffContent;
+        use miette::{Context, Result};
+        use owo_colors::OwoColorize;
+        use rust_i18n::t;
+
+        pub fn args() -> Command {
+            Command::new("diff")
+                .alias("d")
+                .about(t!("diff.help"))
+                .arg(arg!([GROUP]).help(t!("diff.arg_group")))
+                .arg(
+                    arg!([PATH])
+                        .help(t!("diff.arg_path"))
+                        .value_parser(value_parser!(PathBuf)),
+                )
+        }
+
+        fn print_unified(text: &str) {
+            for line in text.lines() {
+                if line.starts_with("+++") || line.starts_with("---") {
+                    println!("{}", line.bold());
+                } else if line.starts_with("@@") {
+                    println!("{}", line.cyan());
+                } else if line.starts_with('+') {
+                    println!("{}", line.green());
+                } else if line.starts_with('-') {
+                    println!("{}", line.red());
+                } else {
+                    println!("{}", line);
+                }
+            }
+        }
+
+        async fn exec(matches: &ArgMatches) -> Result<()> {
+            let group = matches.get_one::<String>("GROUP").cloned();
+            let path = matches.get_one::<PathBuf>("PATH").cloned();
+            for diff in dm::local::diff::diff(group, path).await? {
+                println!(
+                    "{}",
+                    format!(
+                        "[{}] {} -> {}",
+                        diff.group,
+                        diff.path,
+                        diff.live.to_string_lossy()
+                    )
+                    .bold()
+                );
+                match &diff.content {
+                    DiffContent::Text(text) => print_unified(text),
+                    DiffContent::Binary => println!("{}", t!("diff.binary")),
+                    DiffContent::Dir(dir) => {
+                        for file in &dir.added {
+                            println!("\t{} {}", "+".green(), file.to_string_lossy().green());
+                        }
+                        for file in &dir.removed {
+                            println!("\t{} {}", "-".red(), file.to_string_lossy().red());
+                        }
+                        for file in &dir.changed {
+                            println!("\t{} {}", "~".yellow(), file.to_string_lossy().yellow());
+                        }
+                        println!(
+                            "{}",
+                            t!(
+                                "diff.dir_summary",
+                                added = &dir.added.len().to_string(),
+                                removed = &dir.removed.len().to_string(),
+                                changed = &dir.changed.len().to_string()
+                            )
+                        );
+                    }
+                }
+            }
+            Ok(())
+        }
+
+        pub async fn try_match(matches: &ArgMatches) -> Option<Result<()>> {
+            Some(
+                exec(matches.subcommand_matches("diff")?)
+                    .await
+                    .wrap_err(t!("error.ctx.cmd.diff")),
+            )
+        }
+    }
+    pub mod history {
+        use clap::{arg, ArgMatches, Command};
+        use miette::{Context, Result};
+        use owo_colors::OwoColorize;
+        use rust_i18n::t;
+
+        use crate::uicli;
+
+        pub fn args_log() -> Command {
+            Command::new("log").about(t!("history.log.help"))
+        }
+
+        pub fn args_undo() -> Command {
+            Command::new("undo").about(t!("history.undo.help")).arg(
+                arg!([ID])
+                    .help(t!("history.undo.arg_id"))
+                    .value_parser(clap::value_parser!(u64)),
+            )
+        }
+
+        async fn exec_log(_matches: &ArgMatches) -> Result<()> {
+            for record in dm::local::history::list_history().await? {
+                println!(
+                    "{} {} {}",
+                    record.id.to_string().yellow(),
+                    humantime::format_rfc3339_seconds(record.time()).dimmed(),
+                    record.command.bold()
+                );
+                let paths = [
+                    (t!("history.log.manifest"), &record.manifests),
+                    (t!("history.log.file"), &record.files),
+                    (t!("history.log.backup"), &record.backups),
+                ];
+                for (label, paths) in paths {
+                    for path in paths {
+                        println!("\t{}\t{}", label, path.to_string_lossy());
+                    }
+                }
+            }
+            Ok(())
+        }
+
+        async fn exec_undo(matches: &ArgMatches) -> Result<()> {
+            let id = matches.get_one::<u64>("ID").copied();
+            dm::local::history::undo(&uicli::Cli, id).await
+        }
+
+        pub async fn try_match_log(matches: &ArgMatches) -> Option<Result<()>> {
+            Some(
+                exec_log(matches.subcommand_matches("log")?)
+                    .await
+                    .wrap_err(t!("error.ctx.cmd.log")),
+            )
+        }
+
+        pub async fn try_match_undo(matches: &ArgMatches) -> Option<Result<()>> {
+            Some(
+                exec_undo(matches.subcommand_matches("undo")?)
+                    .await
+                    .wrap_err(t!("error.ctx.cmd.undo")),
+            )
+        }
+    }
+    pub mod backup {
+        use std::path::PathBuf;
+
+        use clap::{arg, ArgMatches, Command};
+        use miette::{Context, Result};
+        use owo_colors::OwoColorize;
+        use rust_i18n::t;
+
+        use crate::uicli;
+
+        pub fn args() -> Command {
+            Command::new("backup")
+                .about(t!("backup.help"))
+                .subcommand(
+                    Command::new("list")
+                        .alias("ls")
+                        .about(t!("backup.list.help"))
+                        .arg(
+                            arg!([PATH])
+                                .help(t!("backup.list.arg_path"))
+                                .value_parser(clap::value_parser!(PathBuf)),
+                        ),
+                )
+                .subcommand(
+                    Command::new("restore")
+                        .about(t!("backup.restore.help"))
+                        .arg(
+                            arg!(<ID>)
+                                .help(t!("backup.restore.arg_id"))
+                                .value_parser(clap::value_parser!(i64)),
+                        )
+                        .arg(
+                            arg!(--to <PATH>)
+                                .help(t!("backup.restore.arg_to"))
+                                .value_parser(clap::value_parser!(PathBuf)),
+                        ),
+                )
+                .subcommand(
+                    Command::new("prune")
+                        .about(t!("backup.prune.help"))
+                        .arg(
+                            arg!(--keep <COUNT>)
+                                .help(t!("backup.prune.arg_keep"))
+                                .value_parser(clap::value_parser!(usize)),
+                        )
+                        .arg(
+                            arg!(--"max-age" <DAYS>)
+                                .help(t!("backup.prune.arg_max_age"))
+                                .value_parser(clap::value_parser!(u64)),
+                        ),
+                )
+        }
+
+        async fn exec_list(matches: &ArgMatches) -> Result<()> {
+            let origin = matches.get_one::<PathBuf>("PATH").cloned();
+            for entry in dm::local::backup::list_backup(origin).await? {
+                println!(
+                    "{} {} {}\t{}",
+                    entry.id.to_string().yellow(),
+                    humantime::format_rfc3339_seconds(entry.time).dimmed(),
+                    entry.origin.to_string_lossy().bold(),
+                    t!("backup.list.size", size = &entry.size.to_string()).dimmed()
+                );
+            }
+            Ok(())
+        }
+
+        async fn exec_restore(matches: &ArgMatches) -> Result<()> {
+            let id = *matches.get_one::<i64>("ID").unwrap();
+            let target = matches.get_one::<PathBuf>("to").cloned();
+            dm::local::backup::restore_backup(&uicli::Cli, id, target).await
+        }
+
+        async fn exec_prune(matches: &ArgMatches) -> Result<()> {
+            let keep = matches.get_one::<usize>("keep").copied();
+            let max_age = matches.get_one::<u64>("max-age").copied();
+            let count = dm::local::backup::prune_backup(keep, max_age).await?;
+            println!("{}", t!("backup.prune.pruned", count = &count.to_string()));
+            Ok(())
+        }
+
+        async fn exec(matches: &ArgMatches) -> Result<()> {
+            if let Some(matches) = matches.subcommand_matches("list") {
+                exec_list(matches)
+                    .await
+                    .wrap_err(t!("error.ctx.cmd.backup.list"))
+            } else if let Some(matches) = matches.subcommand_matches("restore") {
+                exec_restore(matches)
+                    .await
+                    .wrap_err(t!("error.ctx.cmd.backup.restore"))
+            } else if let Some(matches) = matches.subcommand_matches("prune") {
+                exec_prune(matches)
+                    .await
+                    .wrap_err(t!("error.ctx.cmd.backup.prune"))
+            } else {
+                Ok(())
+            }
+        }
+
+        pub async fn try_match(matches: &ArgMatches) -> Option<Result<()>> {
+            Some(exec(matches.subcommand_matches("backup")?).await)
+        }
+    }
+    pub mod unlock {
+        use clap::{arg, ArgAction, ArgMatches, Command};
+        use miette::{Context, Result};
+        use rust_i18n::t;
+
+        use crate::uicli;
+
+        pub fn args() -> Command {
+            Command::new("unlock").about(t!("lock.unlock.help")).arg(
+                arg!(-f - -force)
+                    .help(t!("lock.unlock.arg_force"))
+                    .action(ArgAction::SetTrue),
+            )
+        }
+
+        async fn exec(matches: &ArgMatches) -> Result<()> {
+            let force = matches.get_flag("force");
+            dm::local::lock::unlock(&uicli::Cli, force).await
+        }
+
+        pub async fn try_match(matches: &ArgMatches) -> Option<Result<()>> {
+            Some(
+                exec(matches.subcommand_matches("unlock")?)
+                    .await
+                    .wrap_err(t!("error.ctx.cmd.unlock")),
+            )
+        }
+    }
+    pub mod sync {
+        use clap::{arg, ArgMatches, Command};
+        use miette::{Context, Result};
+        use rust_i18n::t;
+
+        use crate::uicli;
+
+        pub fn args() -> Command {
+            Command::new("sync")
+                .about(t!("sync.help"))
+                .arg(arg!([URL]).help(t!("sync.arg_url")))
+        }
+
+        async fn exec(matches: &ArgMatches) -> Result<()> {
+            let url = matches.get_one::<String>("URL").cloned();
+            dm::local::sync::sync(&uicli::Cli, url).await
+        }
+
+        pub async fn try_match(matches: &ArgMatches) -> Option<Result<()>> {
+            Some(
+                exec(matches.subcommand_matches("sync")?)
+                    .await
+                    .wrap_err(t!("error.ctx.cmd.sync")),
+            )
+        }
+    }
+    pub mod mirror {
+        use clap::{arg, ArgAction, ArgMatches, Command};
+        use miette::{Context, Result};
+        use rust_i18n::t;
+
+        use crate::uicli;
+
+        pub fn args() -> Command {
+            Command::new("mirror")
+                .about(t!("mirror.help"))
+                .subcommand(
+                    Command::new("push").about(t!("mirror.push.help")).arg(
+                        arg!(-f --force)
+                            .help(t!("mirror.push.arg_force"))
+                            .action(ArgAction::SetTrue),
+                    ),
+                )
+                .subcommand(
+                    Command::new("pull").about(t!("mirror.pull.help")).arg(
+                        arg!(-f --force)
+                            .help(t!("mirror.pull.arg_force"))
+                            .action(ArgAction::SetTrue),
+                    ),
+                )
+        }
+
+        async fn exec(matches: &ArgMatches) -> Result<()> {
+            if let Some(matches) = matches.subcommand_matches("push") {
+                dm::local::mirror::push(&uicli::Cli, matches.get_flag("force"))
+                    .await
+                    .wrap_err(t!("error.ctx.cmd.mirror.push"))
+            } else if let Some(matches) = matches.subcommand_matches("pull") {
+                dm::local::mirror::pull(&uicli::Cli, matches.get_flag("force"))
+                    .await
+                    .wrap_err(t!("error.ctx.cmd.mirror.pull"))
+            } else {
+                Ok(())
+            }
+        }
+
+        pub async fn try_match(matches: &ArgMatches) -> Option<Result<()>> {
+            Some(exec(matches.subcommand_matches("mirror")?).await)
+        }
+    }
+    pub mod git {
+        use clap::{arg, ArgMatches, Command};
+        use miette::{Context, Result};
+        use rust_i18n::t;
+
+        pub fn args() -> Command {
+            Command::new("git").about(t!("git.help")).arg(
+                arg!([ARGS] ...)
+                    .help(t!("git.arg_args"))
+                    .trailing_var_arg(true)
+                    .allow_hyphen_values(true),
+            )
+        }
+
+        async fn exec(matches: &ArgMatches) -> Result<()> {
+            let args = matches
+                .get_many::<String>("ARGS")
+                .map(|args| args.cloned().collect())
+                .unwrap_or_default();
+            let code = dm::local::git::git(args).await?;
+            if code != 0 {
+                return Err(crate::Exit(u8::try_from(code).unwrap_or(1)).into());
+            }
+            Ok(())
+        }
+
+        pub async fn try_match(matches: &ArgMatches) -> Option<Result<()>> {
+            Some(
+                exec(matches.subcommand_matches("git")?)
+                    .await
+                    .wrap_err(t!("error.ctx.cmd.git")),
+            )
+        }
+    }
     pub fn args() -> Command {
         command!()
             .name("dm")
@@ -236,23 +1044,55 @@ mod cli {
             .subcommand(crate::cli::local::group::args())
             .subcommand(crate::cli::info::args())
             .subcommand(crate::cli::local::file::args_add())
+            .subcommand(crate::cli::local::file::args_remove())
             .subcommand(crate::cli::local::file::args_update())
+            .subcommand(crate::cli::local::file::args_install())
+            .subcommand(crate::cli::status::args())
+            .subcommand(crate::cli::diff::args())
+            .subcommand(crate::cli::history::args_log())
+            .subcommand(crate::cli::history::args_undo())
+            .subcommand(crate::cli::backup::args())
+            .subcommand(crate::cli::unlock::args())
+            .subcommand(crate::cli::git::args())
+            .subcommand(crate::cli::sync::args())
+            .subcommand(crate::cli::mirror::args())
     }
 }
 
 #[tokio::main]
-async fn main() -> Result<()> {
+async fn main() -> ExitCode {
     apply_locales().await;
-    let matches = cli::args().get_matches_from(&mut wild::args_os());
+    let matches = cli::args().get_matches_from(wild::args_os());
     let matched = None
         .or(cli::local::profile::try_match(&matches).await)
         .or(cli::local::group::try_match(&matches).await)
         .or(cli::local::file::try_match_add(&matches).await)
+        .or(cli::local::file::try_match_remove(&matches).await)
         .or(cli::local::file::try_match_update(&matches).await)
+        .or(cli::local::file::try_match_install(&matches).await)
+        .or(cli::status::try_match(&matches).await)
+        .or(cli::diff::try_match(&matches).await)
+        .or(cli::history::try_match_log(&matches).await)
+        .or(cli::history::try_match_undo(&matches).await)
+        .or(cli::backup::try_match(&matches).await)
+        .or(cli::unlock::try_match(&matches).await)
+        .or(cli::git::try_match(&matches).await)
+        .or(cli::sync::try_match(&matches).await)
+        .or(cli::mirror::try_match(&matches).await)
         .or(cli::info::try_match(&matches).await);
-    if let None = matched {
-        return cli::args().print_long_help().into_diagnostic();
+    let result = if let Some(result) = matched {
+        result
     } else {
-        return matched.unwrap();
+        cli::args().print_long_help().into_diagnostic()
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(report) => match report.downcast_ref::<Exit>() {
+            Some(Exit(code)) => ExitCode::from(*code),
+            None => {
+                eprintln!("Error: {:?}", report);
+                ExitCode::FAILURE
+            }
+        },
     }
 }
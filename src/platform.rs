@@ -2,6 +2,8 @@ use std::io;
 use std::os;
 use std::path::Path;
 
+use tokio::process::Command;
+
 #[cfg(target_family = "windows")]
 pub fn symlink_file_specify<P: AsRef<Path>, Q: AsRef<Path>>(
     original: P,
@@ -10,10 +12,96 @@ pub fn symlink_file_specify<P: AsRef<Path>, Q: AsRef<Path>>(
     os::windows::fs::symlink_file(original, link)
 }
 
-#[cfg(target_family = "unix") ]
+#[cfg(target_family = "unix")]
 pub fn symlink_file_specify<P: AsRef<Path>, Q: AsRef<Path>>(
     original: P,
     link: Q,
 ) -> io::Result<()> {
     os::unix::fs::symlink(original, link)
 }
+
+#[cfg(target_family = "windows")]
+pub fn symlink_dir_specify<P: AsRef<Path>, Q: AsRef<Path>>(original: P, link: Q) -> io::Result<()> {
+    os::windows::fs::symlink_dir(original, link)
+}
+
+#[cfg(target_family = "unix")]
+pub fn symlink_dir_specify<P: AsRef<Path>, Q: AsRef<Path>>(original: P, link: Q) -> io::Result<()> {
+    os::unix::fs::symlink(original, link)
+}
+
+/// Create a new file which is only readable and writable by current user
+#[cfg(target_family = "windows")]
+pub fn create_private_file<P: AsRef<Path>>(path: P) -> io::Result<std::fs::File> {
+    std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(path)
+}
+
+/// Create a new file which is only readable and writable by current user
+#[cfg(target_family = "unix")]
+pub fn create_private_file<P: AsRef<Path>>(path: P) -> io::Result<std::fs::File> {
+    use os::unix::fs::OpenOptionsExt;
+    std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(path)
+}
+
+/// Whether a process with `pid` is running on this host
+#[cfg(target_family = "windows")]
+pub fn process_exists(pid: u32) -> bool {
+    Command::new("tasklist")
+        .args(["/FI", &format!("PID eq {}", pid), "/NH", "/FO", "CSV"])
+        .output()
+        .map(|output| String::from_utf8_lossy(&output.stdout).contains(&format!("\"{}\"", pid)))
+        // Assume it is running if that can't be told
+        .unwrap_or(true)
+}
+
+/// Whether a process with `pid` is running on this host
+#[cfg(target_family = "unix")]
+pub fn process_exists(pid: u32) -> bool {
+    let Ok(pid) = libc::pid_t::try_from(pid) else {
+        return false;
+    };
+    // Signal 0 only checks whether the process could be signaled
+    if unsafe { libc::kill(pid, 0) } == 0 {
+        return true;
+    }
+    io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+/// Command to run a script by the default shell of platform
+#[cfg(target_family = "windows")]
+pub fn script_command<P: AsRef<Path>>(script: P) -> Command {
+    let mut command = Command::new("cmd");
+    command.arg("/C").arg(script.as_ref());
+    command
+}
+
+/// Command to run a script by the default shell of platform
+#[cfg(target_family = "unix")]
+pub fn script_command<P: AsRef<Path>>(script: P) -> Command {
+    let mut command = Command::new("sh");
+    command.arg(script.as_ref());
+    command
+}
+
+/// Command to run a command line by the default shell of platform
+#[cfg(target_family = "windows")]
+pub fn shell_command(command_line: &str) -> Command {
+    let mut command = Command::new("cmd");
+    command.arg("/C").arg(command_line);
+    command
+}
+
+/// Command to run a command line by the default shell of platform
+#[cfg(target_family = "unix")]
+pub fn shell_command(command_line: &str) -> Command {
+    let mut command = Command::new("sh");
+    command.arg("-c").arg(command_line);
+    command
+}
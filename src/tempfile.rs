@@ -1,24 +1,35 @@
 use miette::{Context, IntoDiagnostic, Result};
 use rust_i18n::t;
-use std::fs::File;
 use std::path::{PathBuf, Path};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::time::{SystemTime, UNIX_EPOCH};
 
+/// Count of temporary files created by this process, keeps their names unique
+static COUNT: AtomicUsize = AtomicUsize::new(0);
+
 pub struct Tempfile {
     path: PathBuf,
 }
 
 impl Tempfile {
-    pub fn new() -> Result<Self> {
+    /// Create an empty temporary file whose name ends with `suffix`, e.g. an extension
+    ///
+    /// Only current user could access it.
+    pub fn new(suffix: &str) -> Result<Self> {
         let tmpdir = std::env::temp_dir();
-        let mut filename = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_millis()
-            .to_string();
+        let mut filename = format!(
+            "{}-{}-{}",
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_millis(),
+            std::process::id(),
+            COUNT.fetch_add(1, Ordering::Relaxed)
+        );
         filename.push_str(".dm.tmp");
+        filename.push_str(suffix);
         let p = tmpdir.join(filename);
-        File::create(&p)
+        crate::platform::create_private_file(&p)
             .into_diagnostic()
             .wrap_err(t!("error.ctx.io.temp"))?;
         Ok(Self{
@@ -40,7 +51,7 @@ impl AsRef<Path> for Tempfile {
 impl Drop for Tempfile {
     fn drop(&mut self) {
       if self.path.exists() {
-        std::fs::remove_file(&self.path);
+        let _ = std::fs::remove_file(&self.path);
       }
     }
 }
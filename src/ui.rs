@@ -6,6 +6,7 @@ pub trait Ui {
     fn msg(&self, level: MsgLevel, msg: String);
     fn input(&self, prompt: Option<&str>) -> Result<String>;
     fn choose(&self, prompt: Option<&str>, item: Vec<&str>)->Result<i32>;
+    #[allow(clippy::clone_on_copy)]
     fn input_i32(&self, prompt: Option<&str>) -> Result<i32> {
         loop {
             let text = self.input(prompt.clone())?;
@@ -16,6 +17,7 @@ pub trait Ui {
             }
         }
     }
+    #[allow(clippy::option_as_ref_deref)]
     fn input_yes_or_no(&self, prompt: Option<&str>, default: bool) -> Result<bool> {
         let addition = if default { "Y/n" } else { "y/N" };
         let prompt = prompt.map(|v| format!("{} [{}]", v, addition));
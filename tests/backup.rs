@@ -0,0 +1,108 @@
+mod common;
+
+use common::TestEnv;
+
+/// Ids of backups in `dm backup list`, the latest first
+fn backup_ids(env: &TestEnv) -> Vec<String> {
+    env.dm(&["backup", "list"])
+        .lines()
+        .filter_map(|line| line.split_whitespace().next().map(str::to_string))
+        .collect()
+}
+
+/// Track `name` in group `g`, then overwrite it by installing `versions` one
+/// by one, each of them is moved into backup store
+fn install_versions(env: &TestEnv, name: &str, versions: &[&str]) -> std::path::PathBuf {
+    let path = env.write(name, "stored");
+    env.dm(&["group", "create", "g"]);
+    env.dm(&["add", "g", path.to_str().unwrap()]);
+    for version in versions {
+        std::fs::write(&path, version).unwrap();
+        env.dm(&["install", "-f", "g"]);
+    }
+    path
+}
+
+#[test]
+fn retention_keeps_latest() {
+    let env = TestEnv::new("backup-retention");
+    env.set_config("[backup]\nkeep = 2\n");
+    install_versions(&env, "a.txt", &["v1", "v2", "v3"]);
+    assert_eq!(backup_ids(&env).len(), 2);
+
+    env.dm(&["backup", "prune", "--keep", "1"]);
+    let ids = backup_ids(&env);
+    assert_eq!(ids.len(), 1);
+    let store = env.data().join("backup");
+    let kept: Vec<_> = std::fs::read_dir(&store).unwrap().collect();
+    assert_eq!(kept.len(), 1);
+}
+
+#[test]
+fn restore_pruned_by_its_own_backup() {
+    let env = TestEnv::new("backup-restore-keep");
+    env.set_config("[backup]\nkeep = 1\n");
+    let path = install_versions(&env, "a.txt", &["v1"]);
+    let ids = backup_ids(&env);
+    assert_eq!(ids.len(), 1);
+
+    // Backing up the live file prunes the backup being restored
+    env.dm(&["backup", "restore", &ids[0]]);
+    assert_eq!(env.read(&path), "v1");
+    let ids = backup_ids(&env);
+    assert_eq!(ids.len(), 1);
+    env.dm(&["backup", "restore", &ids[0]]);
+    assert_eq!(env.read(&path), "stored");
+}
+
+#[test]
+fn restore_oldest() {
+    let env = TestEnv::new("backup-restore-oldest");
+    let path = install_versions(&env, "a.txt", &["v1", "v2", "v3", "v4", "v5"]);
+    let ids = backup_ids(&env);
+    assert_eq!(ids.len(), 5);
+
+    env.dm(&["backup", "restore", ids.last().unwrap()]);
+    assert_eq!(env.read(&path), "v1");
+    assert_eq!(backup_ids(&env).len(), 5);
+    assert!(!env.data().join("journal").exists());
+}
+
+#[test]
+fn restore_to_other_path() {
+    let env = TestEnv::new("backup-restore-to");
+    install_versions(&env, "a.txt", &["v1"]);
+    let ids = backup_ids(&env);
+    let target = env.home().join("dir/b.txt");
+    env.dm(&[
+        "backup",
+        "restore",
+        &ids[0],
+        "--to",
+        target.to_str().unwrap(),
+    ]);
+    assert_eq!(env.read(&target), "v1");
+}
+
+#[cfg(unix)]
+#[test]
+fn backup_broken_link() {
+    let env = TestEnv::new("backup-broken-link");
+    let path = env.write("a.txt", "stored");
+    env.dm(&["group", "create", "g"]);
+    env.dm(&["add", "g", path.to_str().unwrap()]);
+    std::fs::remove_file(&path).unwrap();
+    std::os::unix::fs::symlink(env.home().join("missing"), &path).unwrap();
+
+    env.dm(&["install", "-f", "g"]);
+    assert_eq!(env.read(&path), "stored");
+    let list = env.dm(&["backup", "list", path.to_str().unwrap()]);
+    assert_eq!(list.lines().count(), 1);
+    let ids = backup_ids(&env);
+    env.dm(&["backup", "restore", &ids[0]]);
+    assert!(path.symlink_metadata().unwrap().is_symlink());
+    assert_eq!(
+        std::fs::read_link(&path).unwrap(),
+        env.home().join("missing")
+    );
+}
@@ -0,0 +1,292 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+
+/// Credentials accepted by the server, `Basic` of `u:p`
+const AUTHORIZATION: &str = "Basic dTpw";
+const PREFIX: &str = "/dav/";
+
+#[derive(Default)]
+struct State {
+    /// Content and ETag of files
+    files: BTreeMap<String, (Vec<u8>, String)>,
+    collections: BTreeSet<String>,
+    next_etag: u64,
+    /// Requests served, as `<method> <path> <depth>`
+    log: Vec<String>,
+    /// File changed by others right before it is written next time
+    tamper: Option<String>,
+}
+
+impl State {
+    fn etag(&mut self) -> String {
+        self.next_etag += 1;
+        format!("\"{}\"", self.next_etag)
+    }
+
+    fn put(&mut self, path: &str, data: Vec<u8>) -> String {
+        let etag = self.etag();
+        self.files.insert(path.to_string(), (data, etag.clone()));
+        etag
+    }
+
+    fn is_collection(&self, path: &str) -> bool {
+        path.is_empty() || self.collections.contains(path)
+    }
+}
+
+/// A minimal WebDAV server on localhost, which serves a collection at `/dav/`
+/// with basic authentication `u:p`, and ETags on files
+pub struct DavServer {
+    pub port: u16,
+    state: Arc<Mutex<State>>,
+}
+
+struct Request {
+    method: String,
+    path: String,
+    headers: BTreeMap<String, String>,
+    body: Vec<u8>,
+}
+
+impl Request {
+    fn read(stream: &TcpStream) -> Option<Self> {
+        let mut reader = BufReader::new(stream);
+        let mut line = String::new();
+        reader.read_line(&mut line).ok()?;
+        let mut parts = line.split_whitespace();
+        let method = parts.next()?.to_string();
+        let target = parts.next()?.to_string();
+        let mut headers = BTreeMap::new();
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).ok()?;
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            }
+            let (name, value) = line.split_once(':')?;
+            headers.insert(name.to_lowercase(), value.trim().to_string());
+        }
+        let length = headers
+            .get("content-length")
+            .and_then(|length| length.parse().ok())
+            .unwrap_or(0);
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body).ok()?;
+        let path = decode(target.strip_prefix(PREFIX).unwrap_or(&target))
+            .trim_matches('/')
+            .to_string();
+        Some(Self {
+            method,
+            path,
+            headers,
+            body,
+        })
+    }
+}
+
+fn decode(path: &str) -> String {
+    let bytes = path.as_bytes();
+    let mut decoded = vec![];
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            decoded.push(u8::from_str_radix(&path[i + 1..i + 3], 16).unwrap());
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(decoded).unwrap()
+}
+
+fn respond(mut stream: TcpStream, status: &str, headers: &[(&str, String)], body: &[u8]) {
+    let mut response = format!(
+        "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n",
+        status,
+        body.len()
+    );
+    for (name, value) in headers {
+        response.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    response.push_str("\r\n");
+    let _ = stream.write_all(response.as_bytes());
+    let _ = stream.write_all(body);
+}
+
+fn prop_response(path: &str, state: &State) -> String {
+    let (href, props) = match state.files.get(path) {
+        Some((data, etag)) => (
+            format!("{}{}", PREFIX, path),
+            format!(
+                "<d:resourcetype/><d:getcontentlength>{}</d:getcontentlength><d:getetag>{}</d:getetag>",
+                data.len(),
+                etag.replace('"', "&quot;")
+            ),
+        ),
+        None if path.is_empty() => (PREFIX.to_string(), "<d:resourcetype><d:collection/></d:resourcetype>".to_string()),
+        None => (
+            format!("{}{}/", PREFIX, path),
+            "<d:resourcetype><d:collection/></d:resourcetype>".to_string(),
+        ),
+    };
+    format!(
+        "<d:response><d:href>{}</d:href><d:propstat><d:prop>{}</d:prop><d:status>HTTP/1.1 200 OK</d:status></d:propstat></d:response>",
+        href, props
+    )
+}
+
+fn parent(path: &str) -> &str {
+    path.rsplit_once('/').map_or("", |(parent, _)| parent)
+}
+
+fn handle(stream: TcpStream, state: &Mutex<State>) {
+    let request = match Request::read(&stream) {
+        Some(request) => request,
+        None => return,
+    };
+    let mut state = state.lock().unwrap();
+    let depth = request.headers.get("depth").cloned().unwrap_or_default();
+    state
+        .log
+        .push(format!("{} {} {}", request.method, request.path, depth));
+    if request.headers.get("authorization").map(String::as_str) != Some(AUTHORIZATION) {
+        return respond(stream, "401 Unauthorized", &[], b"");
+    }
+    let path = request.path.clone();
+    match request.method.as_str() {
+        "GET" => match state.files.get(&path) {
+            Some((data, etag)) => respond(stream, "200 OK", &[("ETag", etag.clone())], data),
+            None => respond(stream, "404 Not Found", &[], b""),
+        },
+        "PUT" => {
+            if !state.is_collection(parent(&path)) {
+                return respond(stream, "409 Conflict", &[], b"");
+            }
+            if state.tamper.as_deref() == Some(path.as_str()) {
+                state.tamper = None;
+                state.put(&path, b"changed by others".to_vec());
+            }
+            let current = state.files.get(&path).map(|(_, etag)| etag.clone());
+            let satisfied = match (
+                request.headers.get("if-match"),
+                request.headers.get("if-none-match"),
+            ) {
+                (Some(etag), _) => current.as_ref() == Some(etag),
+                (_, Some(_)) => current.is_none(),
+                _ => true,
+            };
+            if !satisfied {
+                return respond(stream, "412 Precondition Failed", &[], b"");
+            }
+            let etag = state.put(&path, request.body);
+            respond(stream, "201 Created", &[("ETag", etag)], b"");
+        }
+        "MKCOL" => {
+            if state.is_collection(&path) || state.files.contains_key(&path) {
+                respond(stream, "405 Method Not Allowed", &[], b"")
+            } else if !state.is_collection(parent(&path)) {
+                respond(stream, "409 Conflict", &[], b"")
+            } else {
+                state.collections.insert(path);
+                respond(stream, "201 Created", &[], b"")
+            }
+        }
+        "DELETE" => {
+            let prefix = format!("{}/", path);
+            let existed = state.files.remove(&path).is_some() || state.collections.remove(&path);
+            state.files.retain(|file, _| !file.starts_with(&prefix));
+            state.collections.retain(|dir| !dir.starts_with(&prefix));
+            if existed {
+                respond(stream, "204 No Content", &[], b"")
+            } else {
+                respond(stream, "404 Not Found", &[], b"")
+            }
+        }
+        "PROPFIND" => {
+            if !state.is_collection(&path) && !state.files.contains_key(&path) {
+                return respond(stream, "404 Not Found", &[], b"");
+            }
+            let mut responses = prop_response(&path, &state);
+            if depth == "1" && state.is_collection(&path) {
+                let children: Vec<String> = state
+                    .files
+                    .keys()
+                    .chain(state.collections.iter())
+                    .filter(|child| !child.is_empty() && parent(child) == path)
+                    .cloned()
+                    .collect();
+                for child in children {
+                    responses.push_str(&prop_response(&child, &state));
+                }
+            }
+            let body = format!(
+                "<?xml version=\"1.0\" encoding=\"utf-8\"?><d:multistatus xmlns:d=\"DAV:\">{}</d:multistatus>",
+                responses
+            );
+            respond(
+                stream,
+                "207 Multi-Status",
+                &[("Content-Type", "application/xml".to_string())],
+                body.as_bytes(),
+            )
+        }
+        _ => respond(stream, "405 Method Not Allowed", &[], b""),
+    }
+}
+
+impl DavServer {
+    pub fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let state = Arc::new(Mutex::new(State::default()));
+        let shared = state.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let state = shared.clone();
+                std::thread::spawn(move || handle(stream, &state));
+            }
+        });
+        Self { port, state }
+    }
+
+    pub fn url(&self) -> String {
+        format!("http://127.0.0.1:{}{}", self.port, PREFIX)
+    }
+
+    /// Section of configuration file to mirror to this server
+    pub fn config(&self, password: &str) -> String {
+        format!(
+            "[webdav]\nurl = \"{}\"\nusername = \"u\"\npassword = \"{}\"\n",
+            self.url(),
+            password
+        )
+    }
+
+    pub fn files(&self) -> Vec<String> {
+        self.state.lock().unwrap().files.keys().cloned().collect()
+    }
+
+    pub fn read(&self, path: &str) -> Option<Vec<u8>> {
+        let state = self.state.lock().unwrap();
+        state.files.get(path).map(|(data, _)| data.clone())
+    }
+
+    /// Change file `path` as if it is changed by others
+    pub fn write(&self, path: &str, data: &[u8]) {
+        self.state.lock().unwrap().put(path, data.to_vec());
+    }
+
+    /// Change file `path` right before it is written next time
+    pub fn tamper(&self, path: &str) {
+        self.state.lock().unwrap().tamper = Some(path.to_string());
+    }
+
+    /// Served requests, as `<method> <path> <depth>`
+    pub fn take_log(&self) -> Vec<String> {
+        std::mem::take(&mut self.state.lock().unwrap().log)
+    }
+}
@@ -0,0 +1,176 @@
+#![allow(dead_code)]
+
+pub mod dav;
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+
+/// An isolated machine for running `dm`, with its own home, app data and
+/// configuration under a temporary directory
+pub struct TestEnv {
+    pub root: PathBuf,
+}
+
+impl TestEnv {
+    pub fn new(name: &str) -> Self {
+        let root = std::env::temp_dir().join(format!("dm-test-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(root.join("home")).unwrap();
+        let env = Self { root };
+        env.set_config("");
+        env
+    }
+
+    pub fn home(&self) -> PathBuf {
+        self.root.join("home")
+    }
+
+    pub fn data(&self) -> PathBuf {
+        self.root.join("data")
+    }
+
+    /// Replace the configuration file, `locale` is always set
+    pub fn set_config(&self, extra: &str) {
+        std::fs::write(
+            self.root.join("dm.toml"),
+            format!("locale = \"en\"\n{}", extra),
+        )
+        .unwrap();
+    }
+
+    pub fn command(&self) -> Command {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_dm"));
+        command
+            .env("HOME", self.home())
+            .env("DM_DATA", self.data())
+            .env("DM_CONFIG_FILE", self.root.join("dm.toml"))
+            .env_remove("XDG_CONFIG_HOME")
+            .env_remove("XDG_DATA_HOME")
+            .stdin(Stdio::null());
+        command
+    }
+
+    /// Run `dm` with stdin closed, so that prompts take their defaults
+    pub fn run(&self, args: &[&str]) -> Output {
+        self.command().args(args).output().unwrap()
+    }
+
+    /// Run `dm` and panic with its output if it fails
+    pub fn dm(&self, args: &[&str]) -> String {
+        let output = self.run(args);
+        let stdout = strip_ansi(&String::from_utf8_lossy(&output.stdout));
+        assert!(
+            output.status.success(),
+            "dm {:?} failed\nstdout:\n{}\nstderr:\n{}",
+            args,
+            stdout,
+            String::from_utf8_lossy(&output.stderr)
+        );
+        stdout
+    }
+
+    /// Run `dm` with `input` written to its stdin
+    pub fn run_input(&self, args: &[&str], input: &str) -> Output {
+        let mut child = self
+            .command()
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        child
+            .stdin
+            .take()
+            .unwrap()
+            .write_all(input.as_bytes())
+            .unwrap();
+        child.wait_with_output().unwrap()
+    }
+
+    /// Run `dm` with `input` written to its stdin, and assert it succeeds
+    pub fn dm_input(&self, args: &[&str], input: &str) -> String {
+        let output = self.run_input(args, input);
+        let stdout = strip_ansi(&String::from_utf8_lossy(&output.stdout));
+        assert!(
+            output.status.success(),
+            "dm {:?} failed\nstdout:\n{}\nstderr:\n{}",
+            args,
+            stdout,
+            String::from_utf8_lossy(&output.stderr)
+        );
+        stdout
+    }
+
+    /// Stored file of group named `file_name`, which must be unique in group
+    pub fn stored(&self, group: &str, file_name: &str) -> PathBuf {
+        let mut found = vec![];
+        let mut stack = vec![self.data().join("depository").join(group)];
+        while let Some(dir) = stack.pop() {
+            for item in std::fs::read_dir(dir).unwrap() {
+                let path = item.unwrap().path();
+                if path.is_dir() {
+                    stack.push(path);
+                } else if path.file_name().unwrap() == file_name {
+                    found.push(path);
+                }
+            }
+        }
+        assert_eq!(found.len(), 1, "{} is not unique in {}", file_name, group);
+        found.pop().unwrap()
+    }
+
+    /// Write a file in home and return its path
+    pub fn write(&self, path: &str, content: &str) -> PathBuf {
+        let path = self.home().join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, content).unwrap();
+        path
+    }
+
+    pub fn read(&self, path: &Path) -> String {
+        std::fs::read_to_string(path).unwrap()
+    }
+}
+
+impl Drop for TestEnv {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.root);
+    }
+}
+
+/// Run git in `dir` with a fixed identity
+pub fn git(dir: &Path, args: &[&str]) -> String {
+    let output = Command::new("git")
+        .current_dir(dir)
+        .args(["-c", "user.name=test", "-c", "user.email=test@localhost"])
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "git {:?} failed: {}",
+        args,
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8_lossy(&output.stdout).to_string()
+}
+
+/// Remove color escape sequences from output
+pub fn strip_ansi(text: &str) -> String {
+    let mut result = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            for c in chars.by_ref() {
+                if c.is_ascii_alphabetic() {
+                    break;
+                }
+            }
+        } else {
+            result.push(c);
+        }
+    }
+    result
+}
@@ -0,0 +1,139 @@
+mod common;
+
+use common::TestEnv;
+
+/// Track `a.txt` in group `g` and return its live path and stored path
+fn setup(env: &TestEnv, content: &str) -> (std::path::PathBuf, std::path::PathBuf) {
+    let live = env.write("a.txt", content);
+    env.dm(&["group", "create", "g"]);
+    env.dm(&["add", "g", live.to_str().unwrap()]);
+    (live.clone(), env.stored("g", "a.txt"))
+}
+
+/// Status of `a.txt` reported by `dm status`
+fn status(env: &TestEnv) -> String {
+    // Exit code is 1 if anything differs
+    let output = env.run(&["status"]);
+    let stdout = common::strip_ansi(&String::from_utf8_lossy(&output.stdout));
+    let line = stdout.lines().find(|line| line.contains("a.txt")).unwrap();
+    line.trim().split('\t').next().unwrap().to_string()
+}
+
+#[test]
+fn classify_changes() {
+    let env = TestEnv::new("conflict-classify");
+    let (live, stored) = setup(&env, "base\n");
+    assert_eq!(status(&env), "clean");
+
+    std::fs::write(&live, "local\n").unwrap();
+    assert_eq!(status(&env), "modified locally");
+    std::fs::write(&live, "base\n").unwrap();
+    assert_eq!(status(&env), "clean");
+
+    std::fs::write(&stored, "stored\n").unwrap();
+    assert_eq!(status(&env), "modified in depository");
+
+    std::fs::write(&live, "local\n").unwrap();
+    assert_eq!(status(&env), "conflicted");
+}
+
+#[test]
+fn install_skips_clean_and_local() {
+    let env = TestEnv::new("conflict-install");
+    let (live, stored) = setup(&env, "base\n");
+    env.dm(&["install", "g"]);
+    assert!(env.dm(&["backup", "list"]).is_empty());
+
+    std::fs::write(&live, "local\n").unwrap();
+    env.dm(&["install", "g"]);
+    assert_eq!(env.read(&live), "local\n");
+
+    std::fs::write(&live, "base\n").unwrap();
+    std::fs::write(&stored, "stored\n").unwrap();
+    env.dm(&["install", "g"]);
+    assert_eq!(env.read(&live), "stored\n");
+    assert_eq!(env.dm(&["backup", "list"]).lines().count(), 1);
+
+    // Forced install of an identical file is not backed up again
+    env.dm(&["install", "-f", "g"]);
+    assert_eq!(env.dm(&["backup", "list"]).lines().count(), 1);
+}
+
+#[test]
+fn merge_conflicted() {
+    let env = TestEnv::new("conflict-merge");
+    let (live, stored) = setup(&env, "one\ntwo\nthree\n");
+    std::fs::write(&live, "ONE\ntwo\nthree\n").unwrap();
+    std::fs::write(&stored, "one\ntwo\nTHREE\n").unwrap();
+    assert_eq!(status(&env), "conflicted");
+
+    // Choose to merge both sides
+    env.dm_input(&["install", "g"], "3\n");
+    assert_eq!(env.read(&live), "ONE\ntwo\nTHREE\n");
+    assert_eq!(env.read(&stored), "ONE\ntwo\nTHREE\n");
+    assert_eq!(status(&env), "clean");
+    assert!(!env.data().join("journal").exists());
+
+    // Both sides are restored by undo
+    env.dm_input(&["undo"], "y\n");
+    assert_eq!(env.read(&live), "ONE\ntwo\nthree\n");
+    assert_eq!(env.read(&stored), "one\ntwo\nTHREE\n");
+}
+
+/// Configure a merge tool which logs its runs and the mode of temporary files into `log`
+fn set_merge_tool(env: &TestEnv, extra: &str) -> std::path::PathBuf {
+    let log = env.home().join("tool.log");
+    env.set_config(&format!(
+        "[merge]\ntool = '''stat -c %a \"$DM_LOCAL\" >> \"{}\"; printf 'merged\\n' > \"$DM_MERGED\"'''\n{}",
+        log.to_string_lossy(),
+        extra
+    ));
+    log
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn merge_tool_only_on_conflict() {
+    let env = TestEnv::new("conflict-tool");
+    let log = set_merge_tool(&env, "");
+    let (live, stored) = setup(&env, "one\ntwo\nthree\n");
+
+    // Merged cleanly without the tool
+    std::fs::write(&live, "ONE\ntwo\nthree\n").unwrap();
+    std::fs::write(&stored, "one\ntwo\nTHREE\n").unwrap();
+    env.dm_input(&["install", "g"], "3\n");
+    assert_eq!(env.read(&live), "ONE\ntwo\nTHREE\n");
+    assert!(!log.exists());
+
+    std::fs::write(&live, "ONE\nlocal\nTHREE\n").unwrap();
+    std::fs::write(&stored, "ONE\nstored\nTHREE\n").unwrap();
+    env.dm_input(&["install", "g"], "3\n");
+    assert_eq!(env.read(&live), "merged\n");
+    assert_eq!(env.read(&stored), "merged\n");
+    // Versions are only readable by current user
+    assert_eq!(env.read(&log), "600\n");
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn encrypted_not_passed_to_tool() {
+    let env = TestEnv::new("conflict-tool-encrypted");
+    let log = set_merge_tool(&env, "[encrypt]\npassphrase = \"secret\"\n");
+    let live = env.write("a.txt", "one\ntwo\nthree\n");
+    env.dm(&["group", "create", "g"]);
+    env.dm(&["add", "-e", "g", live.to_str().unwrap()]);
+    let stored = env.stored("g", "a.txt.age");
+    let old = std::fs::read(&stored).unwrap();
+
+    // Stored side goes back to the old version, while both sides change line 3
+    std::fs::write(&live, "one\ntwo\nTHREE\n").unwrap();
+    env.dm_input(&["update", "g"], "y\n");
+    std::fs::write(&stored, old).unwrap();
+    std::fs::write(&live, "one\ntwo\n3\n").unwrap();
+    assert_eq!(status(&env), "conflicted");
+
+    let output = env.run_input(&["install", "g"], "3\n");
+    assert!(!output.status.success());
+    assert!(!log.exists());
+    assert_eq!(env.read(&live), "one\ntwo\n3\n");
+}
@@ -0,0 +1,98 @@
+mod common;
+
+use std::path::{Path, PathBuf};
+
+use common::TestEnv;
+
+/// Header printed for an entry, with the entry path in depository
+fn header(env: &TestEnv, stored: &Path, live: &Path) -> String {
+    let entry = stored
+        .strip_prefix(env.data().join("depository/g"))
+        .unwrap();
+    format!(
+        "[g] {} -> {}",
+        entry.to_string_lossy(),
+        live.to_string_lossy()
+    )
+}
+
+/// Live path as it is resolved from entry
+fn live(env: &TestEnv, path: &str) -> PathBuf {
+    std::fs::canonicalize(env.home()).unwrap().join(path)
+}
+
+#[test]
+fn diff_file_and_dir() {
+    let env = TestEnv::new("diff-file-dir");
+    env.write("a.txt", "one\ntwo\nthree\n");
+    env.write("conf/x.txt", "x");
+    env.write("conf/y.txt", "y");
+    env.dm(&["group", "create", "g"]);
+    env.dm(&["add", "g", live(&env, "a.txt").to_str().unwrap()]);
+    env.dm(&["add", "g", live(&env, "conf").to_str().unwrap()]);
+    // Nothing is printed for clean entries
+    assert_eq!(env.dm(&["diff"]), "");
+
+    env.write("a.txt", "one\nTWO\nthree\n");
+    env.write("conf/x.txt", "X");
+    env.write("conf/z.txt", "z");
+    std::fs::remove_file(env.home().join("conf/y.txt")).unwrap();
+    let stored_file = env.stored("g", "a.txt");
+    let stored_dir = stored_file.with_file_name("conf");
+    let expected = format!(
+        "{}\n--- {}\n+++ {}\n@@ -1,3 +1,3 @@\n one\n-two\n+TWO\n three\n\
+         {}\n\t+ z.txt\n\t- y.txt\n\t~ x.txt\n1 added, 1 removed, 1 changed\n",
+        header(&env, &stored_file, &live(&env, "a.txt")),
+        stored_file.to_string_lossy(),
+        live(&env, "a.txt").to_string_lossy(),
+        header(&env, &stored_dir, &live(&env, "conf")),
+    );
+    assert_eq!(env.dm(&["diff", "g"]), expected);
+
+    // Only the entry of the given path is shown
+    let output = env.dm(&["diff", "g", live(&env, "conf").to_str().unwrap()]);
+    assert!(output.starts_with(&header(&env, &stored_dir, &live(&env, "conf"))));
+    assert!(!output.contains("a.txt"));
+}
+
+#[test]
+fn diff_binary_file() {
+    let env = TestEnv::new("diff-binary");
+    let path = live(&env, "bin");
+    std::fs::write(&path, [0xff, 0xfe, 0x00]).unwrap();
+    env.dm(&["group", "create", "g"]);
+    env.dm(&["add", "g", path.to_str().unwrap()]);
+    std::fs::write(&path, [0xff, 0x00]).unwrap();
+    let stored = env.stored("g", "bin");
+    assert_eq!(
+        env.dm(&["diff"]),
+        format!("{}\nBinary files differ\n", header(&env, &stored, &path))
+    );
+}
+
+#[test]
+fn diff_packed_entries() {
+    let env = TestEnv::new("diff-packed");
+    env.write("a.txt", "one\n");
+    env.write("conf/x.txt", "x");
+    env.dm(&["group", "create", "g"]);
+    env.dm(&["add", "-c", "g", live(&env, "a.txt").to_str().unwrap()]);
+    env.dm(&["add", "-c", "g", live(&env, "conf").to_str().unwrap()]);
+    assert_eq!(env.dm(&["diff"]), "");
+
+    env.write("a.txt", "two\n");
+    env.write("conf/x.txt", "X");
+    env.write("conf/y.txt", "y");
+    let stored_file = env.stored("g", "a.txt.zst");
+    let stored_dir = env.stored("g", "conf.tar.zst");
+    // Content is compared after decompressed
+    let expected = format!(
+        "{}\n--- {}\n+++ {}\n@@ -1 +1 @@\n-one\n+two\n\
+         {}\n\t+ y.txt\n\t~ x.txt\n1 added, 0 removed, 1 changed\n",
+        header(&env, &stored_file, &live(&env, "a.txt")),
+        stored_file.to_string_lossy(),
+        live(&env, "a.txt").to_string_lossy(),
+        header(&env, &stored_dir, &live(&env, "conf")),
+    );
+    assert_eq!(env.dm(&["diff"]), expected);
+}
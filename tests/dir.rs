@@ -0,0 +1,63 @@
+mod common;
+
+use common::TestEnv;
+
+#[test]
+fn update_and_install_dir() {
+    let env = TestEnv::new("dir-update");
+    env.write("conf/a.txt", "a");
+    env.write("conf/sub/b.txt", "b");
+    let live = env.home().join("conf");
+    env.dm(&["group", "create", "g"]);
+    env.dm(&["add", "g", live.to_str().unwrap()]);
+    let stored_a = env.stored("g", "a.txt");
+    let stored_b = env.stored("g", "b.txt");
+    assert_eq!(env.read(&stored_b), "b");
+
+    env.write("conf/a.txt", "changed");
+    env.write("conf/c.txt", "c");
+    std::fs::remove_file(live.join("sub/b.txt")).unwrap();
+    env.dm_input(&["update", "g"], "y\n");
+    assert_eq!(env.read(&stored_a), "changed");
+    // Only the overwritten file is backed up
+    let backups = env.dm(&["backup", "list"]);
+    assert_eq!(backups.lines().count(), 1, "{}", backups);
+    assert!(backups.contains("a.txt"), "{}", backups);
+    assert!(!stored_b.exists());
+    // The emptied directory is not left behind
+    assert!(!stored_b.parent().unwrap().exists());
+    assert_eq!(env.read(&env.stored("g", "c.txt")), "c");
+
+    // Files only in the live directory are kept on install
+    std::fs::remove_file(live.join("a.txt")).unwrap();
+    env.write("conf/d.txt", "d");
+    env.dm(&["install", "-f", "g"]);
+    assert_eq!(env.read(&live.join("a.txt")), "changed");
+    assert_eq!(env.read(&live.join("d.txt")), "d");
+}
+
+#[cfg(unix)]
+#[test]
+fn symlinks_in_dir_are_skipped() {
+    let env = TestEnv::new("dir-symlink");
+    let outside = env.write("secret.txt", "secret");
+    env.write("conf/a.txt", "a");
+    let live = env.home().join("conf");
+    std::os::unix::fs::symlink(&outside, live.join("link.txt")).unwrap();
+    std::os::unix::fs::symlink(live.join("missing"), live.join("broken")).unwrap();
+    std::os::unix::fs::symlink(env.home(), live.join("home")).unwrap();
+    env.dm(&["group", "create", "g"]);
+    env.dm(&["add", "g", live.to_str().unwrap()]);
+    let stored_a = env.stored("g", "a.txt");
+    let stored_dir = stored_a.parent().unwrap();
+    let mut items: Vec<_> = std::fs::read_dir(stored_dir)
+        .unwrap()
+        .map(|item| item.unwrap().file_name())
+        .collect();
+    items.sort();
+    assert_eq!(items, ["a.txt"]);
+
+    // Links don't count as changes either
+    let output = env.dm(&["status"]);
+    assert!(output.contains("clean"), "{}", output);
+}
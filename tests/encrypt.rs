@@ -0,0 +1,107 @@
+mod common;
+
+use std::path::{Path, PathBuf};
+
+use age::secrecy::ExposeSecret;
+use common::TestEnv;
+
+const SECRET: &str = "top-secret-token";
+
+/// Write a new age identity file and use it to encrypt entries
+fn use_new_identity(env: &TestEnv, name: &str) -> PathBuf {
+    let identity = age::x25519::Identity::generate();
+    let path = env.write(name, &format!("{}\n", identity.to_string().expose_secret()));
+    env.set_config(&format!(
+        "[encrypt]\nidentity = {:?}\n",
+        path.to_str().unwrap()
+    ));
+    path
+}
+
+/// All files under `dir` recursively
+fn all_files(dir: &Path) -> Vec<PathBuf> {
+    let mut files = vec![];
+    let mut stack = vec![dir.to_path_buf()];
+    while let Some(dir) = stack.pop() {
+        for item in std::fs::read_dir(dir).unwrap() {
+            let path = item.unwrap().path();
+            if path.is_dir() {
+                stack.push(path);
+            } else {
+                files.push(path);
+            }
+        }
+    }
+    files
+}
+
+/// Whether any file under `dir` holds `SECRET` in plain
+fn has_plaintext(dir: &Path) -> bool {
+    all_files(dir).iter().any(|file| {
+        std::fs::read(file)
+            .unwrap()
+            .windows(SECRET.len())
+            .any(|window| window == SECRET.as_bytes())
+    })
+}
+
+#[test]
+fn encrypted_round_trip() {
+    let env = TestEnv::new("encrypt-round-trip");
+    use_new_identity(&env, "key.txt");
+    let file = env.write("a.txt", &format!("{}\n", SECRET));
+    env.write("conf/b.txt", SECRET);
+    let dir = env.home().join("conf");
+    env.dm(&["group", "create", "g"]);
+    env.dm(&["add", "-e", "g", file.to_str().unwrap()]);
+    env.dm(&["add", "-e", "g", dir.to_str().unwrap()]);
+    let stored = env.stored("g", "a.txt.age");
+    assert!(std::fs::read(&stored)
+        .unwrap()
+        .starts_with(b"age-encryption.org/v1"));
+    assert!(!has_plaintext(&env.data()));
+
+    std::fs::remove_file(&file).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+    env.dm(&["install", "g"]);
+    assert_eq!(env.read(&file), format!("{}\n", SECRET));
+    assert_eq!(env.read(&dir.join("b.txt")), SECRET);
+
+    std::fs::write(&file, format!("{} changed\n", SECRET)).unwrap();
+    env.write("conf/c.txt", "c");
+    env.dm_input(&["update", "g"], "y\ny\n");
+    let status = env.dm(&["status"]);
+    assert!(
+        status.contains("clean") && !status.contains("modified"),
+        "{}",
+        status
+    );
+    assert!(!has_plaintext(&env.data()));
+
+    std::fs::remove_file(&file).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+    env.dm(&["install", "g"]);
+    assert_eq!(env.read(&file), format!("{} changed\n", SECRET));
+    assert_eq!(env.read(&dir.join("c.txt")), "c");
+}
+
+#[test]
+fn wrong_key_writes_nothing() {
+    let env = TestEnv::new("encrypt-wrong-key");
+    use_new_identity(&env, "key.txt");
+    let file = env.write("a.txt", &format!("{}\n", SECRET));
+    env.write("conf/b.txt", SECRET);
+    let dir = env.home().join("conf");
+    env.dm(&["group", "create", "g"]);
+    env.dm(&["add", "-e", "g", file.to_str().unwrap()]);
+    env.dm(&["add", "-e", "g", dir.to_str().unwrap()]);
+    std::fs::remove_file(&file).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    use_new_identity(&env, "other-key.txt");
+    let output = env.run(&["install", "-f", "g"]);
+    assert!(!output.status.success());
+    assert!(!file.exists());
+    assert!(!dir.join("b.txt").exists());
+    assert!(!has_plaintext(&env.root));
+}
@@ -0,0 +1,58 @@
+mod common;
+
+use common::{git, TestEnv};
+
+#[cfg(unix)]
+#[test]
+fn remove_through_linked_depository() {
+    let env = TestEnv::new("file-remove-link");
+    let live = env.write("a.txt", "a\n");
+    // Depository is reached through a link
+    let linked = env.home().parent().unwrap().join("linked-data");
+    std::fs::create_dir_all(env.data()).unwrap();
+    std::os::unix::fs::symlink(env.data(), &linked).unwrap();
+    let dm = |args: &[&str]| {
+        let output = env
+            .command()
+            .env("DM_DATA", &linked)
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(
+            output.status.success(),
+            "dm {:?} failed: {}",
+            args,
+            String::from_utf8_lossy(&output.stderr)
+        );
+    };
+    dm(&["group", "create", "g"]);
+    dm(&["add", "g", live.to_str().unwrap()]);
+
+    // The stored file is given by its real path
+    let stored = env.stored("g", "a.txt");
+    dm(&["remove", "g", stored.to_str().unwrap()]);
+    let manifest = env.read(&env.data().join("depository/g/manifest.toml"));
+    assert!(!manifest.contains("a.txt"));
+}
+
+#[test]
+fn remove_stored_and_undo() {
+    let env = TestEnv::new("file-remove-undo");
+    let live = env.write("a.txt", "a\n");
+    env.dm(&["group", "create", "g"]);
+    env.dm(&["add", "g", live.to_str().unwrap()]);
+    env.dm(&["git", "init", "--quiet"]);
+    let stored = env.stored("g", "a.txt");
+
+    env.dm(&["remove", "-d", "g", live.to_str().unwrap()]);
+    assert!(!stored.exists());
+    // Deletion is committed along with the manifest
+    assert!(git(&env.data(), &["status", "--porcelain"]).is_empty());
+
+    env.dm(&["undo"]);
+    assert_eq!(env.read(&stored), "a\n");
+    let output = env.run(&["status"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(output.status.code(), Some(0), "{}", stdout);
+    assert!(stdout.contains("clean"), "{}", stdout);
+}
@@ -0,0 +1,78 @@
+mod common;
+
+use common::{git, TestEnv};
+
+/// Subjects of commits in the depository repository, the latest first
+fn subjects(env: &TestEnv) -> Vec<String> {
+    git(&env.data(), &["log", "--format=%s"])
+        .lines()
+        .map(str::to_string)
+        .collect()
+}
+
+#[test]
+fn init_commits_depository() {
+    let env = TestEnv::new("git-init");
+    env.dm(&["group", "create", "g"]);
+    env.dm(&["git", "init", "--quiet"]);
+    assert!(env.data().join(".git").exists());
+    assert_eq!(subjects(&env).len(), 1);
+
+    let tracked = git(&env.data(), &["ls-files"]);
+    assert!(tracked.lines().any(|file| file == "dm.toml"));
+    assert!(tracked
+        .lines()
+        .any(|file| file == "depository/g/manifest.toml"));
+    // Caches and history are local to this machine
+    assert!(!tracked.contains("cache.db"));
+    assert!(!tracked.contains("history/"));
+    assert!(git(&env.data(), &["status", "--porcelain"]).is_empty());
+}
+
+#[test]
+fn commit_per_transaction() {
+    let env = TestEnv::new("git-commit");
+    env.dm(&["git", "init", "--quiet"]);
+    let count = subjects(&env).len();
+
+    let live = env.write("a.txt", "one");
+    env.dm(&["group", "create", "g"]);
+    env.dm(&["add", "g", live.to_str().unwrap()]);
+    std::fs::write(&live, "two").unwrap();
+    env.dm_input(&["update", "g"], "y\n");
+
+    let subjects = subjects(&env);
+    assert_eq!(subjects.len(), count + 3);
+    assert!(subjects[0].ends_with("update g"));
+    assert!(subjects[2].ends_with("group create g"));
+    let body = git(&env.data(), &["log", "-1", "--format=%b"]);
+    assert!(body.contains("Host: "));
+    assert!(git(&env.data(), &["status", "--porcelain"]).is_empty());
+
+    // Transactions changing nothing are not committed
+    env.dm_input(&["update", "g"], "y\n");
+    assert_eq!(self::subjects(&env).len(), count + 3);
+}
+
+#[test]
+fn passthrough_to_bare_remote() {
+    let env = TestEnv::new("git-passthrough");
+    let remote = env.root.join("remote.git");
+    git(
+        &env.root,
+        &["init", "--quiet", "--bare", remote.to_str().unwrap()],
+    );
+
+    env.dm(&["git", "init", "--quiet"]);
+    env.dm(&["group", "create", "g"]);
+    env.dm(&["git", "remote", "add", "origin", remote.to_str().unwrap()]);
+    env.dm(&["git", "push", "--quiet", "origin", "HEAD:refs/heads/main"]);
+    assert_eq!(
+        git(&remote, &["rev-parse", "main"]),
+        git(&env.data(), &["rev-parse", "HEAD"])
+    );
+
+    // Exit code of git is passed through
+    let output = env.run(&["git", "rev-parse", "--verify", "--quiet", "missing"]);
+    assert_eq!(output.status.code(), Some(1));
+}
@@ -0,0 +1,121 @@
+mod common;
+
+use common::TestEnv;
+
+#[test]
+fn create_in_missing_profile() {
+    let env = TestEnv::new("group-missing-profile");
+    env.set_config("using_profile = \"ghost\"");
+    let output = env.run(&["group", "create", "g"]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!stderr.contains("panicked"));
+    assert!(stderr.contains("ghost"));
+    // Nothing is created if it fails
+    assert!(!env.data().join("depository/g").exists());
+}
+
+/// Count of entry states recorded for `group`
+fn state_count(env: &TestEnv, group: &str) -> i64 {
+    let connect = rusqlite::Connection::open(env.data().join("cache.db")).unwrap();
+    connect
+        .query_row(
+            "SELECT COUNT(*) FROM entry_state WHERE group_name = ?1",
+            [group],
+            |row| row.get(0),
+        )
+        .unwrap()
+}
+
+#[test]
+fn rename_keeps_state() {
+    let env = TestEnv::new("group-rename-state");
+    let live = env.write("a.txt", "a\n");
+    env.dm(&["group", "create", "g"]);
+    env.dm(&["add", "g", live.to_str().unwrap()]);
+    env.dm(&["group", "rename", "g", "h"]);
+    assert_eq!(state_count(&env, "g"), 0);
+    assert_eq!(state_count(&env, "h"), 1);
+    assert!(!env.data().join("base/g").exists());
+    assert!(env.data().join("base/h").exists());
+
+    std::fs::write(&live, "b\n").unwrap();
+    let output = env.run(&["status"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("modified locally"), "{}", stdout);
+}
+
+#[test]
+fn delete_forgets_state() {
+    let env = TestEnv::new("group-delete-state");
+    let live = env.write("a.txt", "a\n");
+    env.dm(&["group", "create", "g"]);
+    env.dm(&["add", "g", live.to_str().unwrap()]);
+    assert_eq!(state_count(&env, "g"), 1);
+    env.dm(&["group", "delete", "-y", "g"]);
+    assert_eq!(state_count(&env, "g"), 0);
+    assert!(!env.data().join("base/g").exists());
+}
+
+#[test]
+fn group_lifecycle() {
+    let env = TestEnv::new("group-lifecycle");
+    let live = env.write("a.txt", "a\n");
+    env.dm(&["group", "create", "g"]);
+    env.dm(&["group", "create", "-n", "h"]);
+    env.dm(&["add", "g", live.to_str().unwrap()]);
+    env.dm(&["group", "describe", "g", "shell settings"]);
+    let list = env.dm(&["group", "list"]);
+    assert!(
+        list.contains("g\t1 file(s), used by: default\n\tshell settings\n"),
+        "{}",
+        list
+    );
+    assert!(list.contains("h\t0 file(s), used by: \n"), "{}", list);
+
+    env.dm(&["group", "describe", "g", ""]);
+    assert!(!env.dm(&["group", "list"]).contains("shell settings"));
+
+    env.dm(&["group", "rename", "g", "k"]);
+    let list = env.dm(&["group", "list"]);
+    assert!(list.contains("k\t1 file(s), used by: default"), "{}", list);
+    assert!(!list.lines().any(|line| line.starts_with("g\t")));
+    assert!(!env.data().join("depository/g").exists());
+    assert!(env.read(&env.stored("k", "a.txt")).contains('a'));
+    // Renaming onto an existing group is refused
+    assert!(!env.run(&["group", "rename", "k", "h"]).status.success());
+
+    env.dm(&["group", "delete", "-y", "h"]);
+    assert!(!env.data().join("depository/h").exists());
+    assert!(!env.dm(&["group", "list"]).contains("h\t"));
+    assert!(!env.run(&["group", "delete", "-y", "h"]).status.success());
+    // Installed files are kept
+    env.dm(&["group", "delete", "-y", "k"]);
+    assert_eq!(env.read(&live), "a\n");
+}
+
+#[cfg(unix)]
+#[test]
+fn list_along_with_readers() {
+    let env = TestEnv::new("group-list-shared");
+    let script = env.write("slow.sh", "sleep 2\nexit 0\n");
+    let live = env.write("a.txt", "a\n");
+    env.dm(&["group", "create", "g"]);
+    env.dm(&[
+        "add",
+        "-m",
+        "--diff-script",
+        script.to_str().unwrap(),
+        "g",
+        live.to_str().unwrap(),
+    ]);
+    let slow = env
+        .command()
+        .arg("status")
+        .stdout(std::process::Stdio::null())
+        .spawn()
+        .unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(500));
+    assert!(env.dm(&["group", "list"]).contains("g\t"));
+    assert!(slow.wait_with_output().unwrap().status.success());
+}
@@ -0,0 +1,31 @@
+mod common;
+
+use common::TestEnv;
+
+fn history_ids(env: &TestEnv) -> Vec<u64> {
+    let mut ids: Vec<u64> = std::fs::read_dir(env.data().join("history"))
+        .unwrap()
+        .map(|item| item.unwrap().file_name().to_str().unwrap().parse().unwrap())
+        .collect();
+    ids.sort();
+    ids
+}
+
+#[test]
+fn old_history_is_pruned() {
+    let env = TestEnv::new("history-prune");
+    env.set_config("[history]\nkeep = 2\n");
+    for name in ["a", "b", "c"] {
+        env.dm(&["group", "create", name]);
+    }
+    assert_eq!(history_ids(&env), [2, 3]);
+
+    // Ids go on after the pruned ones
+    env.dm(&["group", "create", "d"]);
+    assert_eq!(history_ids(&env), [3, 4]);
+    let output = env.run(&["undo", "2"]);
+    assert!(!output.status.success());
+    env.dm(&["undo", "4"]);
+    assert!(!env.data().join("depository/d").exists());
+    assert_eq!(history_ids(&env), [4, 5]);
+}
@@ -0,0 +1,65 @@
+mod common;
+
+use common::TestEnv;
+
+/// Track `a.txt` in group `g` and return its live path and stored path
+fn setup(env: &TestEnv, content: &str) -> (std::path::PathBuf, std::path::PathBuf) {
+    let live = env.write("a.txt", content);
+    env.dm(&["group", "create", "g"]);
+    env.dm(&["add", "g", live.to_str().unwrap()]);
+    (live.clone(), env.stored("g", "a.txt"))
+}
+
+/// Status of `a.txt` reported by `dm status`
+fn status(env: &TestEnv) -> String {
+    let output = env.run(&["status"]);
+    let stdout = common::strip_ansi(&String::from_utf8_lossy(&output.stdout));
+    let line = stdout.lines().find(|line| line.contains("a.txt")).unwrap();
+    line.trim().split('\t').next().unwrap().to_string()
+}
+
+#[test]
+fn migrate_absolute_entry_path() {
+    let env = TestEnv::new("install-migrate");
+    let (live, stored) = setup(&env, "base\n");
+    // Rewrite the entry into the layout used before paths were put under `ROOT/`
+    let group_dir = env.data().join("depository/g");
+    let rel = stored.strip_prefix(group_dir.join("ROOT")).unwrap();
+    let legacy = group_dir.join(rel);
+    std::fs::create_dir_all(legacy.parent().unwrap()).unwrap();
+    std::fs::rename(&stored, &legacy).unwrap();
+    let manifest = group_dir.join("manifest.toml");
+    let content = env.read(&manifest).replace("\"ROOT/", "\"/");
+    std::fs::write(&manifest, content).unwrap();
+
+    // Files are only moved by a transaction changing depository
+    let output = env.run(&["status"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("old layout"));
+    assert!(legacy.exists() && !stored.exists());
+
+    env.dm(&["install", "g"]);
+    assert!(!legacy.exists());
+    assert_eq!(env.read(&stored), "base\n");
+    assert!(env.read(&manifest).contains("\"ROOT/"));
+    assert_eq!(env.read(&live), "base\n");
+    assert_eq!(status(&env), "clean");
+}
+
+#[test]
+fn entry_of_other_platform() {
+    let env = TestEnv::new("install-other-os");
+    let (live, _) = setup(&env, "base\n");
+    let manifest = env.data().join("depository/g/manifest.toml");
+    let content = env
+        .read(&manifest)
+        .replace(&format!("\n{} = ", std::env::consts::OS), "\nplan9 = ");
+    std::fs::write(&manifest, content).unwrap();
+
+    std::fs::write(&live, "local\n").unwrap();
+    assert_eq!(status(&env), "no install path");
+    assert!(env.dm(&["update", "g"]).contains("no install path"));
+    env.dm(&["update", "-f", "g"]);
+    env.dm(&["install", "g"]);
+    assert_eq!(env.read(&live), "local\n");
+}
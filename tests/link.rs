@@ -0,0 +1,56 @@
+mod common;
+
+use common::TestEnv;
+
+/// Status of `a.txt` reported by `dm status`
+fn status(env: &TestEnv) -> String {
+    let output = env.run(&["status"]);
+    let stdout = common::strip_ansi(&String::from_utf8_lossy(&output.stdout));
+    let line = stdout.lines().find(|line| line.contains("a.txt")).unwrap();
+    line.trim().split('\t').next().unwrap().to_string()
+}
+
+#[test]
+fn link_replaced_by_copy() {
+    let env = TestEnv::new("link-replaced");
+    let live = env.write("a.txt", "a\n");
+    env.dm(&["group", "create", "g"]);
+    env.dm(&["add", "-s", "g", live.to_str().unwrap()]);
+    assert!(live.is_symlink());
+    assert_eq!(status(&env), "clean");
+    // Checked twice, so the second one would hit the cache
+    assert_eq!(status(&env), "clean");
+
+    std::fs::remove_file(&live).unwrap();
+    std::fs::write(&live, "a\n").unwrap();
+    assert_ne!(status(&env), "clean");
+}
+
+#[test]
+fn replaced_files_are_backed_up() {
+    let env = TestEnv::new("link-backup");
+    let live = env.write("a.txt", "a\n");
+    env.dm(&["group", "create", "g"]);
+    env.dm(&["add", "-s", "g", live.to_str().unwrap()]);
+    let stored = env.stored("g", "a.txt");
+    assert_eq!(std::fs::read_link(&live).unwrap(), stored);
+    assert_eq!(env.dm(&["backup", "list"]).lines().count(), 1);
+
+    std::fs::remove_file(&live).unwrap();
+    std::fs::write(&live, "b\n").unwrap();
+    env.dm_input(&["update", "g"], "y\n");
+    assert_eq!(std::fs::read_link(&live).unwrap(), stored);
+    assert_eq!(env.read(&stored), "b\n");
+    // Both the old stored file and the replaced live file
+    assert_eq!(env.dm(&["backup", "list"]).lines().count(), 3);
+
+    // Installed over a regular file
+    std::fs::remove_file(&live).unwrap();
+    std::fs::write(&live, "c\n").unwrap();
+    env.dm(&["install", "-f", "g"]);
+    assert_eq!(std::fs::read_link(&live).unwrap(), stored);
+    assert_eq!(env.read(&stored), "b\n");
+    assert_eq!(env.dm(&["backup", "list"]).lines().count(), 4);
+    // No temporary link is left
+    assert_eq!(std::fs::read_dir(env.home()).unwrap().count(), 1);
+}
@@ -0,0 +1,46 @@
+mod common;
+
+use common::TestEnv;
+
+/// Leave a lock file owned by `pid` on this host, without holding the lock
+fn leave_lock(env: &TestEnv, pid: u32) {
+    std::fs::write(
+        env.data().join(".lock"),
+        format!(
+            "pid = {}\nhostname = \"{}\"\nstarted = 0\n",
+            pid,
+            gethostname::gethostname().to_string_lossy()
+        ),
+    )
+    .unwrap();
+}
+
+#[test]
+fn lock_of_running_owner_is_kept() {
+    let env = TestEnv::new("lock-running");
+    env.dm(&["group", "create", "g"]);
+    // The test itself is running, though it doesn't hold the file lock
+    leave_lock(&env, std::process::id());
+    let output = env.run(&["group", "create", "h"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("locked"));
+    assert!(!env.run(&["unlock"]).status.success());
+    assert!(env.data().join(".lock").exists());
+}
+
+#[test]
+fn lock_of_exited_owner_is_taken_over() {
+    let env = TestEnv::new("lock-exited");
+    env.dm(&["group", "create", "g"]);
+    let mut child = env
+        .command()
+        .arg("--help")
+        .stdout(std::process::Stdio::null())
+        .spawn()
+        .unwrap();
+    let pid = child.id();
+    child.wait().unwrap();
+    leave_lock(&env, pid);
+    env.dm(&["group", "create", "h"]);
+    assert!(!env.data().join(".lock").exists());
+}
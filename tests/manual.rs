@@ -0,0 +1,70 @@
+mod common;
+
+use common::TestEnv;
+
+/// Script which copies the live file into depository with `tag` appended
+fn copy_script(env: &TestEnv, path: &str, tag: &str) -> std::path::PathBuf {
+    env.write(
+        path,
+        &format!(
+            "mkdir -p \"$(dirname \"$DM_DST\")\"\n{{ cat \"$DM_SRC\"; echo {}; }} > \"$DM_DST\"\n",
+            tag
+        ),
+    )
+}
+
+#[test]
+fn scripts_are_kept_per_entry() {
+    let env = TestEnv::new("manual-per-entry");
+    let script_a = copy_script(&env, "scripts/a/sync.sh", "from-a");
+    let script_b = copy_script(&env, "scripts/b/sync.sh", "from-b");
+    let a = env.write("a.txt", "a\n");
+    let b = env.write("b.txt", "b\n");
+    env.dm(&["group", "create", "g"]);
+    env.dm(&[
+        "add",
+        "-m",
+        "--update-script",
+        script_a.to_str().unwrap(),
+        "g",
+        a.to_str().unwrap(),
+    ]);
+    env.dm(&[
+        "add",
+        "-m",
+        "--update-script",
+        script_b.to_str().unwrap(),
+        "g",
+        b.to_str().unwrap(),
+    ]);
+    assert_eq!(env.read(&env.stored("g", "a.txt")), "a\nfrom-a\n");
+    assert_eq!(env.read(&env.stored("g", "b.txt")), "b\nfrom-b\n");
+
+    let manifest = env.read(&env.data().join("depository/g/manifest.toml"));
+    let os = std::env::consts::OS;
+    assert!(manifest.contains(&format!("a.txt/{}/update.sh", os)));
+    assert!(manifest.contains(&format!("b.txt/{}/update.sh", os)));
+}
+
+#[test]
+fn scripts_for_other_platform() {
+    let env = TestEnv::new("manual-other-os");
+    let script = copy_script(&env, "sync.sh", "scripted");
+    let a = env.write("a.txt", "a\n");
+    env.dm(&["group", "create", "g"]);
+    env.dm(&[
+        "add",
+        "-m",
+        "--script-os",
+        "plan9",
+        "--update-script",
+        script.to_str().unwrap(),
+        "g",
+        a.to_str().unwrap(),
+    ]);
+    // No script for this platform, so it is copied as is
+    assert_eq!(env.read(&env.stored("g", "a.txt")), "a\n");
+    let manifest = env.read(&env.data().join("depository/g/manifest.toml"));
+    assert!(manifest.contains("a.txt/plan9/update.sh"));
+    assert!(manifest.contains("[files.script.plan9]"));
+}
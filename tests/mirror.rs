@@ -0,0 +1,115 @@
+mod common;
+
+use common::{dav::DavServer, TestEnv};
+
+/// A machine mirroring to `server`, with group `g` tracking `a.txt`
+fn setup(name: &str, server: &DavServer) -> TestEnv {
+    let env = TestEnv::new(name);
+    env.set_config(&server.config("p"));
+    let live = env.write("a.txt", "one");
+    env.dm(&["group", "create", "g"]);
+    env.dm(&["add", "g", live.to_str().unwrap()]);
+    env
+}
+
+/// Path of the stored `a.txt` in the mirror
+fn remote_path(env: &TestEnv) -> String {
+    env.stored("g", "a.txt")
+        .strip_prefix(env.data())
+        .unwrap()
+        .to_string_lossy()
+        .to_string()
+}
+
+#[test]
+fn push_and_pull() {
+    let server = DavServer::start();
+    let a = setup("mirror-push-a", &server);
+    let output = a.dm(&["mirror", "push"]);
+    let files = server.files();
+    assert!(files.contains(&"dm.toml".to_string()));
+    assert!(files.contains(&"depository/g/manifest.toml".to_string()));
+    assert_eq!(server.read(&remote_path(&a)).unwrap(), b"one");
+    assert!(
+        output.contains(&format!("Uploaded {} file(s)", files.len())),
+        "{}",
+        output
+    );
+
+    // ETags are taken from responses of PUT, nothing is uploaded again
+    let log = server.take_log();
+    assert!(
+        !log.iter().any(|request| request.ends_with(" 0")),
+        "{:?}",
+        log
+    );
+    let output = a.dm(&["mirror", "push"]);
+    assert!(output.contains("Uploaded 0 file(s)"), "{}", output);
+
+    let b = TestEnv::new("mirror-push-b");
+    b.set_config(&server.config("p"));
+    b.dm(&["mirror", "pull"]);
+    assert!(b.dm(&["group", "list"]).contains('g'));
+    assert_eq!(b.read(&b.stored("g", "a.txt")), "one");
+
+    // Files deleted locally are deleted remotely
+    a.dm_input(&["group", "delete", "g"], "y\n");
+    a.dm(&["mirror", "push"]);
+    assert!(!server
+        .files()
+        .iter()
+        .any(|file| file.starts_with("depository/g/")));
+    b.dm(&["mirror", "pull"]);
+    assert!(!b.data().join("depository/g").exists());
+}
+
+#[test]
+fn conflict_and_force() {
+    let server = DavServer::start();
+    let a = setup("mirror-conflict", &server);
+    a.dm(&["mirror", "push"]);
+    let path = remote_path(&a);
+    server.write(&path, b"remote");
+    std::fs::write(a.stored("g", "a.txt"), "local").unwrap();
+
+    let output = a.run(&["mirror", "push"]);
+    assert!(!output.status.success());
+    assert_eq!(server.read(&path).unwrap(), b"remote");
+
+    a.dm(&["mirror", "push", "--force"]);
+    assert_eq!(server.read(&path).unwrap(), b"local");
+}
+
+#[test]
+fn changed_while_pushing() {
+    let server = DavServer::start();
+    let a = setup("mirror-race", &server);
+    a.dm(&["mirror", "push"]);
+    let path = remote_path(&a);
+    std::fs::write(a.stored("g", "a.txt"), "local").unwrap();
+
+    // Changed by others after it is checked but before it is uploaded
+    server.tamper(&path);
+    let output = a.run(&["mirror", "push"]);
+    assert!(!output.status.success());
+    let stderr = common::strip_ansi(&String::from_utf8_lossy(&output.stderr));
+    assert!(stderr.contains("changed by others"), "{}", stderr);
+    assert_eq!(server.read(&path).unwrap(), b"changed by others");
+
+    let output = a.run(&["mirror", "push"]);
+    assert!(!output.status.success());
+    a.dm(&["mirror", "pull", "--force"]);
+    assert_eq!(a.read(&a.stored("g", "a.txt")), "changed by others");
+}
+
+#[test]
+fn wrong_password() {
+    let server = DavServer::start();
+    let a = setup("mirror-auth", &server);
+    a.set_config(&server.config("wrong"));
+    let output = a.run(&["mirror", "push"]);
+    assert!(!output.status.success());
+    let stderr = common::strip_ansi(&String::from_utf8_lossy(&output.stderr));
+    assert!(stderr.contains("401"), "{}", stderr);
+    assert!(server.files().is_empty());
+}
@@ -0,0 +1,117 @@
+mod common;
+
+use std::io::Read;
+use std::path::Path;
+
+use common::TestEnv;
+
+/// Content of a compressed stored file
+fn decompress(path: &Path) -> Vec<u8> {
+    let data = std::fs::read(path).unwrap();
+    // Magic number of zstd frame
+    assert_eq!(data[..4], [0x28, 0xb5, 0x2f, 0xfd]);
+    zstd::decode_all(&data[..]).unwrap()
+}
+
+/// Files and their content in a compressed tarball
+fn unpack(path: &Path) -> Vec<(String, String)> {
+    let data = decompress(path);
+    let mut archive = tar::Archive::new(&data[..]);
+    let mut files = vec![];
+    for item in archive.entries().unwrap() {
+        let mut item = item.unwrap();
+        let mut content = String::new();
+        item.read_to_string(&mut content).unwrap();
+        files.push((item.path().unwrap().to_string_lossy().to_string(), content));
+    }
+    files.sort();
+    files
+}
+
+fn files(items: &[(&str, &str)]) -> Vec<(String, String)> {
+    items
+        .iter()
+        .map(|(path, content)| (path.to_string(), content.to_string()))
+        .collect()
+}
+
+#[test]
+fn compressed_file_round_trip() {
+    let env = TestEnv::new("packed-file");
+    let live = env.write("a.txt", "a\n");
+    env.dm(&["group", "create", "g"]);
+    env.dm(&["add", "-c", "g", live.to_str().unwrap()]);
+    let stored = env.stored("g", "a.txt.zst");
+    assert_eq!(decompress(&stored), b"a\n");
+
+    std::fs::remove_file(&live).unwrap();
+    env.dm(&["install", "g"]);
+    assert_eq!(env.read(&live), "a\n");
+
+    std::fs::write(&live, "b\n").unwrap();
+    env.dm_input(&["update", "g"], "y\n");
+    assert_eq!(decompress(&stored), b"b\n");
+    assert!(env.dm(&["status"]).contains("clean"));
+}
+
+#[test]
+fn compressed_dir_round_trip() {
+    let env = TestEnv::new("packed-dir");
+    env.write("conf/a.txt", "a");
+    env.write("conf/sub/b.txt", "b");
+    let live = env.home().join("conf");
+    env.dm(&["group", "create", "g"]);
+    env.dm(&["add", "-c", "g", live.to_str().unwrap()]);
+    let stored = env.stored("g", "conf.tar.zst");
+    assert_eq!(
+        unpack(&stored),
+        files(&[("a.txt", "a"), ("sub/b.txt", "b")])
+    );
+
+    std::fs::remove_dir_all(&live).unwrap();
+    env.dm(&["install", "g"]);
+    assert_eq!(env.read(&live.join("a.txt")), "a");
+    assert_eq!(env.read(&live.join("sub/b.txt")), "b");
+
+    env.write("conf/a.txt", "changed");
+    env.write("conf/c.txt", "c");
+    std::fs::remove_file(live.join("sub/b.txt")).unwrap();
+    env.dm_input(&["update", "g"], "y\n");
+    assert_eq!(
+        unpack(&stored),
+        files(&[("a.txt", "changed"), ("c.txt", "c")])
+    );
+    assert!(env.dm(&["status"]).contains("clean"));
+}
+
+#[test]
+fn archive_out_of_dir_is_refused() {
+    let env = TestEnv::new("packed-unsafe");
+    env.write("conf/a.txt", "a");
+    let live = env.home().join("conf");
+    env.dm(&["group", "create", "g"]);
+    env.dm(&["add", "-c", "g", live.to_str().unwrap()]);
+
+    // The tar crate refuses to write such a path, so it is set in header directly
+    let mut header = tar::Header::new_gnu();
+    let name = b"../escaped.txt";
+    header.as_gnu_mut().unwrap().name[..name.len()].copy_from_slice(name);
+    header.set_size(4);
+    header.set_mode(0o644);
+    header.set_cksum();
+    let mut builder = tar::Builder::new(vec![]);
+    builder.append(&header, &b"evil"[..]).unwrap();
+    let tarball = builder.into_inner().unwrap();
+    let stored = env.stored("g", "conf.tar.zst");
+    std::fs::write(&stored, zstd::encode_all(&tarball[..], 0).unwrap()).unwrap();
+
+    for args in [&["install", "-f", "g"][..], &["diff"][..]] {
+        let output = env.run(args);
+        assert!(!output.status.success());
+        // Install reports failed entries before failing
+        let text =
+            String::from_utf8_lossy(&output.stdout) + String::from_utf8_lossy(&output.stderr);
+        assert!(text.contains("points out of"), "{}", text);
+    }
+    assert!(!env.home().join("escaped.txt").exists());
+}
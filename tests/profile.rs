@@ -0,0 +1,209 @@
+mod common;
+
+use common::TestEnv;
+
+/// Lines of `dm profile show`, tabs replaced by spaces
+fn show(env: &TestEnv, name: &str) -> Vec<String> {
+    env.dm(&["profile", "show", name])
+        .lines()
+        .map(|line| line.trim().split('\t').collect::<Vec<_>>().join(" "))
+        .collect()
+}
+
+#[test]
+fn attach_and_detach() {
+    let env = TestEnv::new("profile-attach");
+    let live = env.write("a.txt", "a\n");
+    env.dm(&["group", "create", "-n", "g"]);
+    env.dm(&["add", "g", live.to_str().unwrap()]);
+    env.dm(&["profile", "create", "work"]);
+    assert_eq!(show(&env, "work"), ["work"]);
+
+    env.dm(&["profile", "attach", "work", "g"]);
+    assert_eq!(show(&env, "work"), ["work", "g 1 file(s)"]);
+    assert_eq!(show(&env, "default"), ["default (using)"]);
+    // Attaching twice, or attaching a missing group, is refused
+    assert!(!env
+        .run(&["profile", "attach", "work", "g"])
+        .status
+        .success());
+    assert!(!env
+        .run(&["profile", "attach", "work", "h"])
+        .status
+        .success());
+
+    env.dm(&["profile", "detach", "work", "g"]);
+    assert_eq!(show(&env, "work"), ["work"]);
+    // The group itself is kept
+    assert!(env.dm(&["group", "list"]).contains("g\t"));
+
+    let output = env.run(&["profile", "detach", "work", "g"]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Group 'g' is not attached"), "{}", stderr);
+}
+
+#[test]
+fn show_current_profile() {
+    let env = TestEnv::new("profile-show-current");
+    env.dm(&["group", "create", "g"]);
+    env.dm(&["profile", "create", "work"]);
+    env.dm(&["profile", "use", "work"]);
+    let current: Vec<String> = env
+        .dm(&["profile", "show"])
+        .lines()
+        .map(str::to_string)
+        .collect();
+    assert_eq!(current, ["work (using)"]);
+    assert_eq!(show(&env, "default"), ["default", "g 0 file(s)"]);
+    assert!(!env.run(&["profile", "show", "ghost"]).status.success());
+}
+
+#[cfg(unix)]
+#[test]
+fn show_along_with_readers() {
+    let env = TestEnv::new("profile-show-shared");
+    let script = env.write("slow.sh", "sleep 2\nexit 0\n");
+    let live = env.write("a.txt", "a\n");
+    env.dm(&["group", "create", "g"]);
+    env.dm(&[
+        "add",
+        "-m",
+        "--diff-script",
+        script.to_str().unwrap(),
+        "g",
+        live.to_str().unwrap(),
+    ]);
+    let slow = env
+        .command()
+        .arg("status")
+        .stdout(std::process::Stdio::null())
+        .spawn()
+        .unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(500));
+    assert_eq!(show(&env, "default"), ["default (using)", "g 1 file(s)"]);
+    env.dm(&["profile", "use", "default"]);
+    assert!(slow.wait_with_output().unwrap().status.success());
+}
+
+/// Create profile `name` with a group of the same name attached
+fn profile_with_group(env: &TestEnv, name: &str) {
+    env.dm(&["profile", "create", name]);
+    env.dm(&["group", "create", "-n", name]);
+    env.dm(&["profile", "attach", name, name]);
+}
+
+#[test]
+fn inherit_transitively() {
+    let env = TestEnv::new("profile-inherit");
+    for name in ["a", "b", "c"] {
+        profile_with_group(&env, name);
+    }
+    env.dm(&["profile", "inherit", "b", "a"]);
+    env.dm(&["profile", "inherit", "c", "b"]);
+    assert_eq!(
+        show(&env, "c"),
+        [
+            "c",
+            "Inherits from: b",
+            "a 0 file(s) (from a)",
+            "b 0 file(s) (from b)",
+            "c 0 file(s)",
+        ]
+    );
+
+    env.dm(&["profile", "inherit", "-r", "c", "b"]);
+    assert_eq!(show(&env, "c"), ["c", "c 0 file(s)"]);
+}
+
+#[test]
+fn inherit_cycle_is_refused() {
+    let env = TestEnv::new("profile-inherit-cycle");
+    profile_with_group(&env, "a");
+    profile_with_group(&env, "b");
+    env.dm(&["profile", "inherit", "a", "b"]);
+    let output = env.run(&["profile", "inherit", "b", "a"]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("CyclicInherit"), "{}", stderr);
+
+    // A cycle written into the manifest by hand is reported as well
+    let manifest = env.data().join("dm.toml");
+    let content = env.read(&manifest);
+    assert!(!content.contains("parent = [\"a\"]"));
+    let content = content.replacen("name = \"b\"\n", "name = \"b\"\nparent = [\"a\"]\n", 1);
+    std::fs::write(&manifest, content).unwrap();
+    let output = env.run(&["profile", "show", "b"]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("CyclicInherit"), "{}", stderr);
+}
+
+#[test]
+fn group_inherited_twice_is_listed_once() {
+    let env = TestEnv::new("profile-inherit-twice");
+    for name in ["a", "b", "c", "d"] {
+        profile_with_group(&env, name);
+    }
+    env.dm(&["profile", "inherit", "b", "a"]);
+    env.dm(&["profile", "inherit", "c", "a"]);
+    env.dm(&["profile", "inherit", "d", "b"]);
+    env.dm(&["profile", "inherit", "d", "c"]);
+    assert_eq!(
+        show(&env, "d"),
+        [
+            "d",
+            "Inherits from: b, c",
+            "a 0 file(s) (from a)",
+            "b 0 file(s) (from b)",
+            "c 0 file(s) (from c)",
+            "d 0 file(s)",
+        ]
+    );
+}
+
+/// Header line of `dm profile show` for the current profile
+fn current(env: &TestEnv) -> String {
+    env.dm(&["profile", "show"])
+        .lines()
+        .next()
+        .unwrap()
+        .to_string()
+}
+
+#[test]
+fn default_without_rules() {
+    let env = TestEnv::new("profile-select-default");
+    env.dm(&["profile", "create", "work"]);
+    env.dm(&["profile", "rule", "work", "--os", "plan9"]);
+    assert_eq!(current(&env), "default (using)");
+}
+
+#[test]
+fn rule_matching_machine() {
+    let env = TestEnv::new("profile-select-rule");
+    env.dm(&["profile", "create", "other"]);
+    env.dm(&["profile", "rule", "other", "--hostname", "no-such-host"]);
+    env.dm(&["profile", "create", "work"]);
+    let hostname = gethostname::gethostname().to_string_lossy().to_string();
+    env.dm(&["profile", "rule", "work", "--hostname", &hostname]);
+    assert_eq!(current(&env), "work (using)");
+
+    env.dm(&["profile", "rule", "work", "--clear"]);
+    env.dm(&["profile", "rule", "work", "--os", std::env::consts::OS]);
+    assert_eq!(current(&env), "work (using)");
+}
+
+#[test]
+fn configured_profile_wins() {
+    let env = TestEnv::new("profile-select-config");
+    env.dm(&["profile", "create", "work"]);
+    env.dm(&["profile", "rule", "work", "--os", std::env::consts::OS]);
+    env.dm(&["profile", "create", "home"]);
+    env.dm(&["profile", "use", "home"]);
+    assert_eq!(current(&env), "home (using)");
+
+    // Rules apply again once the configured one is cleared
+    env.dm(&["profile", "use", "--auto"]);
+    assert_eq!(current(&env), "work (using)");
+}
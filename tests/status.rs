@@ -0,0 +1,53 @@
+mod common;
+
+use std::time::Duration;
+
+use common::TestEnv;
+
+#[test]
+fn exit_code_on_drift() {
+    let env = TestEnv::new("status-exit-code");
+    let live = env.write("a.txt", "a\n");
+    env.dm(&["group", "create", "g"]);
+    env.dm(&["add", "g", live.to_str().unwrap()]);
+    assert_eq!(env.run(&["status"]).status.code(), Some(0));
+
+    std::fs::write(&live, "b\n").unwrap();
+    let output = env.run(&["status"]);
+    assert_eq!(output.status.code(), Some(1));
+    // Drift is not reported as an error
+    assert!(output.stderr.is_empty());
+}
+
+#[cfg(unix)]
+#[test]
+fn readers_share_lock() {
+    let env = TestEnv::new("status-shared-lock");
+    let script = env.write("slow.sh", "sleep 2\nexit 0\n");
+    let live = env.write("a.txt", "a\n");
+    env.dm(&["group", "create", "g"]);
+    env.dm(&[
+        "add",
+        "-m",
+        "--diff-script",
+        script.to_str().unwrap(),
+        "g",
+        live.to_str().unwrap(),
+    ]);
+
+    let slow = env
+        .command()
+        .arg("status")
+        .stdout(std::process::Stdio::null())
+        .spawn()
+        .unwrap();
+    std::thread::sleep(Duration::from_millis(500));
+    // Another reader runs at the same time, while a writer is refused
+    assert!(env.run(&["diff"]).status.success());
+    let output = env.run(&["group", "create", "h"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("lock"));
+
+    assert!(slow.wait_with_output().unwrap().status.success());
+    env.dm(&["group", "create", "h"]);
+}
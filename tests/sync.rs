@@ -0,0 +1,91 @@
+mod common;
+
+use common::{git, TestEnv};
+
+/// A bare repository shared by machines, and its `file://` URL
+fn remote(env: &TestEnv) -> String {
+    let remote = env.root.join("remote.git");
+    git(
+        &env.root,
+        &["init", "--quiet", "--bare", remote.to_str().unwrap()],
+    );
+    format!("file://{}", remote.to_string_lossy())
+}
+
+fn head(env: &TestEnv) -> String {
+    git(&env.data(), &["rev-parse", "HEAD"])
+}
+
+fn groups(env: &TestEnv) -> String {
+    env.dm(&["group", "list"])
+}
+
+#[test]
+fn fast_forward() {
+    let (a, b) = (TestEnv::new("sync-ff-a"), TestEnv::new("sync-ff-b"));
+    let url = remote(&a);
+    a.dm(&["group", "create", "one"]);
+    a.dm(&["sync", &url]);
+    b.dm(&["sync", &url]);
+    assert!(groups(&b).contains("one"));
+
+    b.dm(&["group", "create", "two"]);
+    b.dm(&["sync", &url]);
+    let output = a.dm(&["sync", &url]);
+    assert!(output.contains("Remote changes are pulled"));
+    assert_eq!(head(&a), head(&b));
+    assert!(groups(&a).contains("two"));
+
+    let output = a.dm(&["sync", &url]);
+    assert!(output.contains("Already up to date"));
+}
+
+#[test]
+fn merge_manifests_key_wise() {
+    let (a, b) = (TestEnv::new("sync-merge-a"), TestEnv::new("sync-merge-b"));
+    let url = remote(&a);
+    a.dm(&["group", "create", "base"]);
+    a.dm(&["sync", &url]);
+    b.dm(&["sync", &url]);
+
+    // Both sides change the group list of the same profile
+    a.dm(&["group", "create", "one"]);
+    b.dm(&["group", "create", "two"]);
+    a.dm(&["sync", &url]);
+    let output = b.dm(&["sync", &url]);
+    assert!(output.contains("Remote changes are merged"));
+    a.dm(&["sync", &url]);
+    assert_eq!(head(&a), head(&b));
+    for env in [&a, &b] {
+        let groups = groups(env);
+        assert!(["base", "one", "two"]
+            .iter()
+            .all(|name| groups.contains(name)));
+        assert!(git(&env.data(), &["status", "--porcelain"]).is_empty());
+    }
+}
+
+#[test]
+fn abort_on_conflict() {
+    let (a, b) = (TestEnv::new("sync-abort-a"), TestEnv::new("sync-abort-b"));
+    let url = remote(&a);
+    let live = a.write("a.txt", "base");
+    a.dm(&["group", "create", "g"]);
+    a.dm(&["add", "g", live.to_str().unwrap()]);
+    a.dm(&["sync", &url]);
+    b.dm(&["sync", &url]);
+
+    std::fs::write(a.stored("g", "a.txt"), "one").unwrap();
+    std::fs::write(b.stored("g", "a.txt"), "two").unwrap();
+    a.dm(&["sync", &url]);
+    let output = b.run(&["sync", &url]);
+    assert!(!output.status.success());
+    let stderr = common::strip_ansi(&String::from_utf8_lossy(&output.stderr));
+    assert!(stderr.contains("conflict"), "{}", stderr);
+
+    // Nothing is merged, and local changes are kept
+    assert!(!b.data().join(".git/MERGE_HEAD").exists());
+    assert!(git(&b.data(), &["status", "--porcelain"]).is_empty());
+    assert_eq!(b.read(&b.stored("g", "a.txt")), "two");
+    assert_ne!(head(&a), head(&b));
+}
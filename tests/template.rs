@@ -0,0 +1,80 @@
+mod common;
+
+use std::path::PathBuf;
+
+use common::TestEnv;
+
+const TEMPLATE: &str = "name={{ vars.name }}\nos={{ os }}\nshell=sh\n";
+
+/// Track `rc` as a template in group `g` with variable `name`, and install
+/// it rendered, returns the live path and the stored path
+fn setup(env: &TestEnv) -> (PathBuf, PathBuf) {
+    let live = env.write("rc", TEMPLATE);
+    env.dm(&["group", "create", "g"]);
+    env.dm(&["add", "-t", "g", live.to_str().unwrap()]);
+    let manifest = env.data().join("depository/g/manifest.toml");
+    let content = env.read(&manifest) + "\n[variables]\nname = \"alice\"\n";
+    std::fs::write(&manifest, content).unwrap();
+    std::fs::remove_file(&live).unwrap();
+    env.dm(&["install", "g"]);
+    (live, env.stored("g", "rc"))
+}
+
+fn rendered(shell: &str) -> String {
+    format!("name=alice\nos={}\nshell={}\n", std::env::consts::OS, shell)
+}
+
+#[test]
+fn rendered_on_install() {
+    let env = TestEnv::new("template-install");
+    let (live, stored) = setup(&env);
+    assert_eq!(env.read(&live), rendered("sh"));
+    assert_eq!(env.read(&stored), TEMPLATE);
+    assert!(env.dm(&["status"]).contains("clean"));
+
+    // Undefined variables fail instead of rendering empty
+    std::fs::write(&stored, "{{ vars.missing }}\n").unwrap();
+    assert!(!env.run(&["install", "-f", "g"]).status.success());
+    assert_eq!(env.read(&live), rendered("sh"));
+}
+
+#[test]
+fn update_merges_edits_into_template() {
+    let env = TestEnv::new("template-merge");
+    let (live, stored) = setup(&env);
+    std::fs::write(&live, rendered("zsh")).unwrap();
+    // Confirm the update, then choose to merge
+    env.dm_input(&["update", "g"], "y\n1\n");
+    assert_eq!(
+        env.read(&stored),
+        "name={{ vars.name }}\nos={{ os }}\nshell=zsh\n"
+    );
+    assert_eq!(env.read(&live), rendered("zsh"));
+}
+
+#[test]
+fn update_edit_of_rendered_line_conflicts() {
+    let env = TestEnv::new("template-conflict");
+    let (live, stored) = setup(&env);
+    let edited = rendered("sh").replace("alice", "bob");
+    std::fs::write(&live, &edited).unwrap();
+
+    let output = env.run_input(&["update", "g"], "y\n1\n");
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("conflict with template variables"),
+        "{}",
+        stderr
+    );
+    assert_eq!(env.read(&stored), TEMPLATE);
+
+    // Aborting keeps the template as well
+    assert!(!env.run_input(&["update", "g"], "y\n0\n").status.success());
+    assert_eq!(env.read(&stored), TEMPLATE);
+
+    // Replacing stores the live file as the template
+    env.dm_input(&["update", "g"], "y\n2\n");
+    assert_eq!(env.read(&stored), edited);
+    assert_eq!(env.read(&live), edited);
+}